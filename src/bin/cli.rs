@@ -1,18 +1,110 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use serde_json::json;
-use std::io::{BufRead, BufReader, Write};
-use std::os::unix::net::UnixStream;
+use anyhow::{Result, anyhow};
+use clap::{Args as ClapArgs, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use papyrust::protocol::{
+    Client, Easing, Event, Fit, FitMode, IpcCommand, IpcError, IpcResponse, MediaType, MonitorInfo,
+    MonitorStatus, PlaylistConfig, PlaylistOrder, ScalingMode, Transition, TransitionKind,
+    UniformValue, Uniforms,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use tracing_subscriber::{EnvFilter, fmt};
 
+/// The daemon answered with an error
+const EXIT_ERROR: u8 = 1;
+/// The daemon could not be reached, or its reply could not be read
+const EXIT_UNREACHABLE: u8 = 3;
+
 #[derive(Parser)]
 #[command(name = "papyrust")]
 #[command(about = "A small cli for papyrust-daemon")]
+#[command(
+    after_help = "Exits with 1 when the daemon reports an error and 3 when it cannot be reached."
+)]
 struct Args {
+    /// Daemon socket to connect to instead of $PAPYRUST_SOCKET or
+    /// $XDG_RUNTIME_DIR/papyrust-$WAYLAND_DISPLAY.sock
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
+    /// Print replies and events as JSON, one per line
+    #[arg(long, global = true)]
+    json: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(ClapArgs)]
+struct FitArgs {
+    /// How media is fitted to outputs with a different aspect ratio
+    #[arg(long, value_enum)]
+    fit: Option<FitMode>,
+    /// Resampling filter, overriding the daemon's --scaling
+    #[arg(long, value_enum)]
+    filter: Option<ScalingMode>,
+    /// Colour around contained or centered media, as #rrggbb
+    #[arg(long, value_parser = parse_letterbox)]
+    letterbox: Option<[u8; 3]>,
+}
+
+impl FitArgs {
+    fn to_fit(&self) -> Fit {
+        Fit {
+            mode: self.fit.unwrap_or_default(),
+            filter: self.filter,
+            letterbox: self.letterbox.unwrap_or_default(),
+        }
+    }
+}
+
+fn parse_letterbox(arg: &str) -> Result<[u8; 3], String> {
+    papyrust::protocol::parse_color(arg).map_err(|e| e.to_string())
+}
+
+#[derive(ClapArgs)]
+struct TransitionArgs {
+    /// Effect when switching from the current media: fade, wipe, grow,
+    /// pixelate, or the path of a transition GLSL file
+    #[arg(long)]
+    transition: Option<String>,
+    /// Transition length in seconds
+    #[arg(long)]
+    transition_duration: Option<f32>,
+    #[arg(long, value_enum)]
+    transition_easing: Option<Easing>,
+    /// Origin of the grow transition as X,Y from the top left, each 0.0 to 1.0
+    #[arg(long, value_delimiter = ',', num_args = 2)]
+    transition_position: Option<Vec<f32>>,
+}
+
+impl TransitionArgs {
+    fn to_transition(&self) -> Transition {
+        let mut transition = Transition::default();
+        if let Some(kind) = &self.transition {
+            transition.kind = match kind.as_str() {
+                "none" => TransitionKind::None,
+                "fade" => TransitionKind::Fade,
+                "wipe" => TransitionKind::Wipe,
+                "grow" => TransitionKind::Grow,
+                "pixelate" => TransitionKind::Pixelate,
+                path => TransitionKind::Custom(path.to_string()),
+            };
+        }
+        if let Some(duration) = self.transition_duration {
+            transition.duration = duration;
+        }
+        if let Some(easing) = self.transition_easing {
+            transition.easing = easing;
+        }
+        if let Some(&[x, y]) = self.transition_position.as_deref() {
+            transition.position = [x, y];
+        }
+        transition
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     Image {
@@ -21,6 +113,13 @@ enum Commands {
         shader: Option<String>,
         #[arg(long, action = clap::ArgAction::Append)]
         monitor: Vec<String>,
+        /// Reply once the first frame is on screen, or with why it is not
+        #[arg(long)]
+        wait: bool,
+        #[command(flatten)]
+        fit: FitArgs,
+        #[command(flatten)]
+        transition: TransitionArgs,
     },
     Video {
         path: String,
@@ -30,15 +129,132 @@ enum Commands {
         monitor: Vec<String>,
         #[arg(long)]
         mute: bool,
+        #[arg(long)]
+        wait: bool,
+        #[command(flatten)]
+        fit: FitArgs,
+        #[command(flatten)]
+        transition: TransitionArgs,
     },
     Shader {
         path: String,
         #[arg(long, action = clap::ArgAction::Append)]
         monitor: Vec<String>,
+        #[arg(long)]
+        wait: bool,
+        #[command(flatten)]
+        transition: TransitionArgs,
+    },
+    /// Stop drawing and freeze playback, keeping the current frame on screen
+    Pause {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Resume drawing after `pause`
+    Resume {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Set the audio volume of videos, from 0.0 to 1.0
+    Volume {
+        volume: f32,
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Set shader uniforms as NAME=VALUE, replacing those set before.
+    /// Values are true/false, a number, or 2 to 4 comma-separated numbers.
+    /// No uniforms clears them
+    Uniforms {
+        #[arg(value_parser = parse_uniform)]
+        uniforms: Vec<(String, UniformValue)>,
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Rotate through media files and directories
+    Playlist {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Seconds between items
+        #[arg(long)]
+        interval: Option<u64>,
+        #[arg(long)]
+        shuffle: bool,
+        /// Give each output its own position in the list
+        #[arg(long)]
+        per_output: bool,
+        #[arg(long)]
+        shader: Option<String>,
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+        #[arg(long)]
+        mute: bool,
+        #[command(flatten)]
+        fit: FitArgs,
+        #[command(flatten)]
+        transition: TransitionArgs,
+    },
+    /// Skip to the next playlist item
+    Next {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Go back to the previous playlist item
+    #[command(alias = "previous")]
+    Prev {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Stop the playlist advancing on its own
+    PausePlaylist {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
     },
+    ResumePlaylist {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Show what every output is playing
+    Status,
+    /// List the outputs and their layout
+    Monitors,
+    /// Reread the daemon's config file
+    Reload,
+    /// Show the media last set by hand again, stopping any playlist there
+    Restore {
+        #[arg(long, action = clap::ArgAction::Append)]
+        monitor: Vec<String>,
+    },
+    /// Stop the daemon
+    Kill,
+    /// Print the daemon's events as they happen
+    Watch,
+    /// Print a shell completion script
+    Completions { shell: Shell },
 }
 
-fn main() -> Result<()> {
+fn parse_uniform(arg: &str) -> Result<(String, UniformValue), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected NAME=VALUE, got {}", arg))?;
+    let value = match value {
+        "true" | "false" => UniformValue::Bool(value == "true"),
+        _ => {
+            let components = value
+                .split(',')
+                .map(|c| c.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Invalid value for {}: {}", name, e))?;
+            match components.as_slice() {
+                [number] => UniformValue::Float(*number),
+                _ => UniformValue::Vector(components),
+            }
+        }
+    };
+    papyrust::protocol::validate_uniform(name, &value).map_err(|e| e.to_string())?;
+    Ok((name.to_string(), value))
+}
+
+fn main() -> ExitCode {
     let _ = fmt()
         .with_env_filter(
             EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("off")),
@@ -48,57 +264,304 @@ fn main() -> Result<()> {
         .try_init();
     let args = Args::parse();
 
-    let mut stream = UnixStream::connect("/tmp/papyrust-daemon.sock")?;
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("papyrust: {:#}", e);
+            // Error replies turned into errors, such as a refused Hello, still
+            // count as the daemon's
+            if e.downcast_ref::<IpcError>().is_some() {
+                ExitCode::from(EXIT_ERROR)
+            } else {
+                ExitCode::from(EXIT_UNREACHABLE)
+            }
+        }
+    }
+}
+
+fn run(args: Args) -> Result<ExitCode> {
+    let socket_path = papyrust::socket::path(args.socket.clone());
 
+    let targets = |monitor: Vec<String>| if monitor.is_empty() { None } else { Some(monitor) };
     let command = match args.command {
+        Commands::Completions { shell } => {
+            let mut command = Args::command();
+            let bin_name = env!("CARGO_BIN_NAME");
+            clap_complete::generate(shell, &mut command, bin_name, &mut std::io::stdout());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Watch => return watch(&socket_path, args.json),
         Commands::Image {
             path,
             shader,
             monitor,
-        } => {
-            let monitors = if monitor.is_empty() { None } else { Some(monitor) };
-            json!({
-                "SetImage": {
-                    "path": path,
-                    "shader": shader,
-                    "monitors": monitors
-                }
-            })
-        }
+            wait,
+            fit,
+            transition,
+        } => IpcCommand::SetImage {
+            path,
+            shader,
+            monitors: targets(monitor),
+            fit: fit.to_fit(),
+            transition: transition.to_transition(),
+            wait,
+        },
         Commands::Video {
             path,
             shader,
             monitor,
             mute,
+            wait,
+            fit,
+            transition,
+        } => IpcCommand::SetVideo {
+            path,
+            shader,
+            monitors: targets(monitor),
+            mute,
+            fit: fit.to_fit(),
+            transition: transition.to_transition(),
+            wait,
+        },
+        Commands::Shader {
+            path,
+            monitor,
+            wait,
+            transition,
+        } => IpcCommand::SetShader {
+            path,
+            monitors: targets(monitor),
+            transition: transition.to_transition(),
+            wait,
+        },
+        Commands::Pause { monitor } => IpcCommand::Pause {
+            monitors: targets(monitor),
+        },
+        Commands::Resume { monitor } => IpcCommand::Resume {
+            monitors: targets(monitor),
+        },
+        Commands::Volume { volume, monitor } => IpcCommand::SetVolume {
+            volume,
+            monitors: targets(monitor),
+        },
+        Commands::Uniforms { uniforms, monitor } => IpcCommand::SetUniforms {
+            uniforms: uniforms.into_iter().collect::<Uniforms>(),
+            monitors: targets(monitor),
+        },
+        Commands::Playlist {
+            paths,
+            interval,
+            shuffle,
+            per_output,
+            shader,
+            monitor,
+            mute,
+            fit,
+            transition,
         } => {
-            let monitors = if monitor.is_empty() { None } else { Some(monitor) };
-            json!({
-                "SetVideo": {
-                    "path": path,
-                    "shader": shader,
-                    "monitors": monitors,
-                    "mute": mute
-                }
-            })
-        }
-        Commands::Shader { path, monitor } => {
-            let monitors = if monitor.is_empty() { None } else { Some(monitor) };
-            json!({
-                "SetShader": {
-                    "path": path,
-                    "monitors": monitors
-                }
-            })
+            let defaults = PlaylistConfig::default();
+            IpcCommand::SetPlaylist {
+                playlist: PlaylistConfig {
+                    monitors: targets(monitor),
+                    media: paths,
+                    interval: interval.unwrap_or(defaults.interval),
+                    order: if shuffle {
+                        PlaylistOrder::Shuffle
+                    } else {
+                        PlaylistOrder::Sequential
+                    },
+                    per_output,
+                    shader,
+                    mute,
+                    fit: fit.to_fit(),
+                    transition: transition.to_transition(),
+                    ..defaults
+                },
+            }
         }
+        Commands::Next { monitor } => IpcCommand::Next {
+            monitors: targets(monitor),
+        },
+        Commands::Prev { monitor } => IpcCommand::Previous {
+            monitors: targets(monitor),
+        },
+        Commands::PausePlaylist { monitor } => IpcCommand::PausePlaylist {
+            monitors: targets(monitor),
+        },
+        Commands::ResumePlaylist { monitor } => IpcCommand::ResumePlaylist {
+            monitors: targets(monitor),
+        },
+        Commands::Status => IpcCommand::GetStatus,
+        Commands::Monitors => IpcCommand::ListMonitors,
+        Commands::Reload => IpcCommand::Reload,
+        Commands::Restore { monitor } => IpcCommand::Restore {
+            monitors: targets(monitor),
+        },
+        Commands::Kill => IpcCommand::Shutdown,
     };
 
-    writeln!(stream, "{}", command)?;
-    stream.flush()?;
+    let mut client = Client::connect(&socket_path, "papyrust-cli")?;
+    let response = client.call(command)?;
+    if args.json {
+        println!("{}", serde_json::to_string(&response)?);
+    } else {
+        print_response(&response);
+    }
+    Ok(match response {
+        IpcResponse::Error { .. } => ExitCode::from(EXIT_ERROR),
+        _ => ExitCode::SUCCESS,
+    })
+}
 
-    let mut reader = BufReader::new(stream);
-    let mut response = String::new();
-    reader.read_line(&mut response)?;
+/// Prints events until the daemon exits.
+fn watch(socket_path: &Path, json: bool) -> Result<ExitCode> {
+    let client = Client::connect(socket_path, "papyrust-cli")?;
+    for event in client.subscribe()? {
+        let event = event?;
+        if json {
+            println!("{}", serde_json::to_string(&event)?);
+        } else {
+            println!("{}", describe_event(&event));
+        }
+    }
+    Err(anyhow!("Daemon closed the connection"))
+}
+
+fn print_response(response: &IpcResponse) {
+    match response {
+        IpcResponse::Status { monitors } => print_status(monitors),
+        IpcResponse::Monitors { monitors } => print_monitors(monitors),
+        IpcResponse::Error { code, message } => {
+            eprintln!("papyrust: {} ({})", message, wire_name(code))
+        }
+        IpcResponse::Success | IpcResponse::Hello { .. } => {}
+    }
+}
+
+fn print_status(monitors: &[MonitorStatus]) {
+    let rows: Vec<Vec<String>> = monitors
+        .iter()
+        .map(|monitor| {
+            let mut state = wire_name(&monitor.playback);
+            if monitor.playlist {
+                state.push_str(", playlist");
+            }
+            vec![
+                monitor.output.name.clone(),
+                format!("{}x{}", monitor.output.width, monitor.output.height),
+                state,
+                format!("{:.1}", monitor.fps),
+                if monitor.audio {
+                    format!("{:.0}%", monitor.volume * 100.0)
+                } else {
+                    "-".to_string()
+                },
+                describe(&monitor.media),
+            ]
+        })
+        .collect();
+    print_table(&["OUTPUT", "SIZE", "STATE", "FPS", "AUDIO", "MEDIA"], &rows);
+
+    for monitor in monitors {
+        if let Some(error) = &monitor.shader_error {
+            println!(
+                "\n{}: shader error: {}",
+                monitor.output.name,
+                error.trim_end()
+            );
+        }
+    }
+}
+
+fn print_monitors(monitors: &[MonitorInfo]) {
+    let rows: Vec<Vec<String>> = monitors
+        .iter()
+        .map(|monitor| {
+            let position = match (monitor.x, monitor.y) {
+                (Some(x), Some(y)) => format!("{},{}", x, y),
+                _ => "-".to_string(),
+            };
+            let logical = match (monitor.logical_width, monitor.logical_height) {
+                (Some(width), Some(height)) => format!("{}x{}", width, height),
+                _ => "-".to_string(),
+            };
+            vec![
+                monitor.name.clone(),
+                format!("{}x{}", monitor.width, monitor.height),
+                monitor.scale.to_string(),
+                monitor.transform.clone(),
+                position,
+                logical,
+            ]
+        })
+        .collect();
+    print_table(
+        &["NAME", "SIZE", "SCALE", "TRANSFORM", "POSITION", "LOGICAL"],
+        &rows,
+    );
+}
+
+/// Prints rows under a header, each column as wide as its widest cell.
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let print_row = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    print_row(header.to_vec());
+    for row in rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn describe(media: &MediaType) -> String {
+    let (kind, path, shader) = match media {
+        MediaType::Shader(path) => return format!("shader {}", path),
+        MediaType::Image { path, shader, .. } => ("image", path, shader),
+        MediaType::Video { path, shader, .. } => ("video", path, shader),
+        MediaType::Animation { path, shader, .. } => ("animation", path, shader),
+    };
+    match shader {
+        Some(shader) => format!("{} {} with shader {}", kind, path, shader),
+        None => format!("{} {}", kind, path),
+    }
+}
+
+fn describe_event(event: &Event) -> String {
+    match event {
+        Event::MediaChanged { output, media } => format!("{}: showing {}", output, describe(media)),
+        Event::OutputAdded { output } => format!("{}: connected", output),
+        Event::OutputRemoved { output } => format!("{}: disconnected", output),
+        Event::VideoLooped { path, loop_count } => {
+            format!("{}: looped, {} times so far", path, loop_count)
+        }
+        Event::PlaylistAdvanced { monitors, media } => {
+            let outputs = monitors
+                .as_ref()
+                .map_or("all outputs".to_string(), |m| m.join(", "));
+            format!("{}: playlist moved to {}", outputs, describe(media))
+        }
+        Event::ShaderError { path, error } => {
+            format!("{}: shader error: {}", path, error.trim_end())
+        }
+        Event::Paused { output } => format!("{}: paused", output),
+        Event::Resumed { output } => format!("{}: resumed", output),
+    }
+}
 
-    println!("{}", response.trim());
-    Ok(())
+/// Name of a unit variant on the wire, such as `file_not_found`.
+fn wire_name(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
 }
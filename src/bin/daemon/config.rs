@@ -0,0 +1,293 @@
+use crate::Layer;
+use crate::media::{Fit, HwDecode, MediaType, ScalingMode};
+use crate::playlist::PlaylistConfig;
+use crate::power::PowerPolicy;
+use crate::state::MediaAssignment;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Values given on the command line, which take precedence over the config file.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub fps: Option<u16>,
+    pub layer: Option<Layer>,
+    pub fifo: Option<String>,
+    pub mute: bool,
+    pub scaling: Option<ScalingMode>,
+    pub hwdec: Option<HwDecode>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    fps: Option<u16>,
+    layer: Option<Layer>,
+    fifo: Option<String>,
+    mute: Option<bool>,
+    scaling: Option<ScalingMode>,
+    hwdec: Option<HwDecode>,
+    power: PowerPolicy,
+    #[serde(rename = "output")]
+    outputs: Vec<OutputRule>,
+    #[serde(rename = "playlist")]
+    playlists: Vec<PlaylistConfig>,
+}
+
+/// A `[[output]]` table. `match` is an output name or glob such as `DP-*`.
+/// When `image` or `video` is set, `shader` is applied on top of it;
+/// otherwise `shader` alone selects a shader wallpaper.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputRule {
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub image: Option<String>,
+    pub video: Option<String>,
+    pub shader: Option<String>,
+    #[serde(default)]
+    pub fit: Fit,
+    pub fps: Option<u16>,
+    pub layer: Option<Layer>,
+    pub mute: Option<bool>,
+    /// Audio volume from 0.0 to 1.0 for videos on this output
+    pub volume: Option<f32>,
+}
+
+impl OutputRule {
+    fn matches(&self, output_name: &str) -> bool {
+        match glob::Pattern::new(&self.pattern) {
+            Ok(pattern) => pattern.matches(output_name),
+            Err(_) => self.pattern == output_name,
+        }
+    }
+
+    fn media_type(&self) -> Option<MediaType> {
+        let expand = |p: &String| shellexpand::tilde(p).to_string();
+        let shader = self.shader.as_ref().map(expand);
+        if let Some(path) = &self.video {
+            Some(MediaType::Video {
+                path: expand(path),
+                shader,
+                fit: self.fit,
+            })
+        } else if let Some(path) = &self.image {
+            Some(MediaType::Image {
+                path: expand(path),
+                shader,
+                fit: self.fit,
+            })
+        } else {
+            shader.map(MediaType::Shader)
+        }
+    }
+}
+
+/// Effective settings for a single output after applying matching rules and
+/// command line overrides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSettings {
+    pub layer: Option<Layer>,
+    pub fps: u16,
+    pub media: Option<MediaAssignment>,
+    pub volume: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub fps: u16,
+    pub layer: Option<Layer>,
+    pub fifo: Option<String>,
+    pub mute: bool,
+    /// Resampling filter for media that does not pick its own
+    pub scaling: ScalingMode,
+    /// Hardware video decoding, for videos opened after it is set
+    pub hwdec: HwDecode,
+    pub power: PowerPolicy,
+    pub outputs: Vec<OutputRule>,
+    pub playlists: Vec<PlaylistConfig>,
+    path: PathBuf,
+    overrides: Overrides,
+}
+
+impl Settings {
+    pub fn default_path() -> Result<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".config"))
+                .ok_or_else(|| anyhow!("Neither XDG_CONFIG_HOME nor HOME is set"))?,
+        };
+        Ok(base.join("papyrust").join(CONFIG_FILE))
+    }
+
+    pub fn load(path: Option<PathBuf>, overrides: Overrides) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => Self::default_path()?,
+        };
+
+        let file = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read config {}: {}", path.display(), e))?;
+            let file: ConfigFile = toml::from_str(&content)
+                .map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))?;
+            for playlist in &file.playlists {
+                playlist
+                    .validate()
+                    .map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))?;
+            }
+            tracing::info!(
+                event = "config_loaded",
+                path = %path.display(),
+                rules = file.outputs.len(),
+                "Loaded config file"
+            );
+            file
+        } else {
+            tracing::debug!(event = "config_missing", path = %path.display(), "No config file, using defaults");
+            ConfigFile::default()
+        };
+
+        Ok(Self::from_file(file, path, overrides))
+    }
+
+    fn from_file(file: ConfigFile, path: PathBuf, overrides: Overrides) -> Self {
+        Self {
+            fps: overrides.fps.or(file.fps).unwrap_or(0),
+            layer: overrides.layer.clone().or(file.layer),
+            fifo: overrides.fifo.clone().or(file.fifo),
+            mute: overrides.mute || file.mute.unwrap_or(false),
+            scaling: overrides.scaling.or(file.scaling).unwrap_or_default(),
+            hwdec: overrides.hwdec.or(file.hwdec).unwrap_or_default(),
+            power: file.power,
+            outputs: file.outputs,
+            playlists: file.playlists,
+            path,
+            overrides,
+        }
+    }
+
+    pub fn reload(&self) -> Result<Self> {
+        Self::load(Some(self.path.clone()), self.overrides.clone())
+    }
+
+    pub fn layer_name(&self) -> Option<String> {
+        self.layer.as_ref().map(|l| l.to_string())
+    }
+
+    /// Merges every rule matching `output_name` in file order, so later rules
+    /// override fields set by earlier ones.
+    pub fn output(&self, output_name: &str) -> OutputSettings {
+        let mut layer = None;
+        let mut fps = None;
+        let mut mute = None;
+        let mut media = None;
+        let mut volume = None;
+
+        for rule in self.outputs.iter().filter(|r| r.matches(output_name)) {
+            layer = rule.layer.clone().or(layer);
+            fps = rule.fps.or(fps);
+            mute = rule.mute.or(mute);
+            volume = rule.volume.or(volume);
+            media = rule.media_type().or(media);
+        }
+
+        let mute = self.overrides.mute || mute.unwrap_or(self.mute);
+        OutputSettings {
+            layer: self.overrides.layer.clone().or(layer).or(self.layer.clone()),
+            fps: self.overrides.fps.or(fps).unwrap_or(self.fps),
+            media: media.map(|media_type| MediaAssignment::new(media_type, mute)),
+            volume: volume.unwrap_or(1.0).clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(config: &str, overrides: Overrides) -> Settings {
+        let file: ConfigFile = toml::from_str(config).unwrap();
+        Settings::from_file(file, PathBuf::from("config.toml"), overrides)
+    }
+
+    const CONFIG: &str = r#"
+        fps = 30
+        layer = "background"
+
+        [[output]]
+        match = "DP-*"
+        image = "/wall/a.png"
+        fps = 60
+        volume = 0.5
+
+        [[output]]
+        match = "DP-2"
+        video = "/wall/b.mp4"
+        layer = "bottom"
+        mute = true
+
+        [[output]]
+        match = "HDMI-A-1"
+        volume = 3.0
+    "#;
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let settings = load(CONFIG, Overrides::default());
+
+        let dp1 = settings.output("DP-1");
+        assert_eq!(dp1.fps, 60);
+        assert_eq!(dp1.layer, Some(Layer::Background));
+        assert_eq!(dp1.volume, 0.5);
+        let media = dp1.media.unwrap();
+        assert!(matches!(media.media_type, MediaType::Image { ref path, .. } if path == "/wall/a.png"));
+        assert!(!media.mute);
+
+        // Fields the later rule leaves unset keep the earlier rule's values
+        let dp2 = settings.output("DP-2");
+        assert_eq!(dp2.fps, 60);
+        assert_eq!(dp2.layer, Some(Layer::Bottom));
+        assert_eq!(dp2.volume, 0.5);
+        let media = dp2.media.unwrap();
+        assert!(matches!(media.media_type, MediaType::Video { ref path, .. } if path == "/wall/b.mp4"));
+        assert!(media.mute);
+
+        // Unmatched outputs fall back to the top-level values
+        let other = settings.output("eDP-1");
+        assert_eq!(other.fps, 30);
+        assert_eq!(other.layer, Some(Layer::Background));
+        assert_eq!(other.media, None);
+        assert_eq!(other.volume, 1.0);
+    }
+
+    #[test]
+    fn overrides_beat_the_file() {
+        let overrides = Overrides {
+            fps: Some(24),
+            layer: Some(Layer::Overlay),
+            mute: true,
+            ..Overrides::default()
+        };
+        let settings = load(CONFIG, overrides);
+        assert_eq!(settings.fps, 24);
+
+        let dp1 = settings.output("DP-1");
+        assert_eq!(dp1.fps, 24);
+        assert_eq!(dp1.layer, Some(Layer::Overlay));
+        assert!(dp1.media.unwrap().mute);
+        assert_eq!(settings.output("DP-2").layer, Some(Layer::Overlay));
+    }
+
+    #[test]
+    fn clamps_volume() {
+        assert_eq!(load(CONFIG, Overrides::default()).output("HDMI-A-1").volume, 1.0);
+
+        let negative = load("[[output]]\nmatch = \"DP-1\"\nvolume = -1.0\n", Overrides::default());
+        assert_eq!(negative.output("DP-1").volume, 0.0);
+    }
+}
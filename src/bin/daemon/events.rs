@@ -0,0 +1,34 @@
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+
+pub use papyrust::protocol::Event;
+
+/// Events a subscriber may fall behind by before it is dropped, so a client
+/// that stops reading cannot make the daemon buffer without bound.
+const EVENT_QUEUE: usize = 256;
+
+static SUBSCRIBERS: Mutex<Vec<SyncSender<Event>>> = Mutex::new(Vec::new());
+
+/// Receives every event published from now on.
+pub fn subscribe() -> Receiver<Event> {
+    let (tx, rx) = mpsc::sync_channel(EVENT_QUEUE);
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(tx);
+    }
+    rx
+}
+
+/// Sends `event` to every subscriber without blocking the caller.
+pub fn publish(event: Event) {
+    let Ok(mut subscribers) = SUBSCRIBERS.lock() else {
+        return;
+    };
+    subscribers.retain(|tx| match tx.try_send(event.clone()) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            tracing::warn!(event = "ipc_subscriber_dropped", "Dropping event subscriber that stopped reading");
+            false
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
@@ -1,6 +1,7 @@
 use crate::gl_bindings as gl;
 use anyhow::{Result, anyhow};
 use std::ffi::{CStr, CString};
+use std::mem::ManuallyDrop;
 
 pub struct GlTexture {
     pub id: u32,
@@ -10,6 +11,12 @@ pub struct GlTexture {
 
 impl GlTexture {
     pub fn new(width: u32, height: u32) -> Result<Self> {
+        Self::with_format(width, height, gl::RGBA8, gl::RGBA)
+    }
+
+    /// Creates an empty texture with the given internal and pixel format, such
+    /// as `R8`/`RED` for a single video plane.
+    pub fn with_format(width: u32, height: u32, internal_format: u32, format: u32) -> Result<Self> {
         let mut texture = 0;
         unsafe {
             gl::GenTextures(1, &mut texture);
@@ -24,11 +31,11 @@ impl GlTexture {
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
-                gl::RGBA as i32,
+                internal_format as i32,
                 width as i32,
                 height as i32,
                 0,
-                gl::RGBA,
+                format,
                 gl::UNSIGNED_BYTE,
                 std::ptr::null(),
             );
@@ -82,9 +89,19 @@ impl GlTexture {
         Ok(Self { id: texture, width, height })
     }
 
-    pub fn update_data(&self, data: &[u8]) {
+    /// Uploads rows that are `stride` bytes apart, as in ffmpeg frames whose
+    /// lines are padded for alignment.
+    pub fn update_data(&self, data: &[u8], stride: usize) {
+        self.update_plane(data, stride, gl::RGBA, 4);
+    }
+
+    /// Like `update_data` for textures of another `format` with
+    /// `bytes_per_pixel` bytes per texel.
+    pub fn update_plane(&self, data: &[u8], stride: usize, format: u32, bytes_per_pixel: usize) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, (stride / bytes_per_pixel) as i32);
             gl::TexSubImage2D(
                 gl::TEXTURE_2D,
                 0,
@@ -92,10 +109,20 @@ impl GlTexture {
                 0,
                 self.width as i32,
                 self.height as i32,
-                gl::RGBA,
+                format,
                 gl::UNSIGNED_BYTE,
                 data.as_ptr() as *const _,
             );
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+        }
+    }
+
+    pub fn set_repeat(&self, repeat: bool) {
+        let wrap = if repeat { gl::REPEAT } else { gl::CLAMP_TO_EDGE };
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as i32);
         }
     }
 
@@ -104,6 +131,12 @@ impl GlTexture {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
         }
     }
+
+    /// A handle to a texture owned elsewhere in the shared context group,
+    /// which must not delete it.
+    pub fn borrowed(id: u32, width: u32, height: u32) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self { id, width, height })
+    }
 }
 
 impl Drop for GlTexture {
@@ -114,6 +147,98 @@ impl Drop for GlTexture {
     }
 }
 
+/// Offscreen render target backed by a texture, used for shader buffer passes.
+pub struct GlFramebuffer {
+    pub id: u32,
+    pub texture: GlTexture,
+}
+
+impl GlFramebuffer {
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        let mut texture = 0;
+        let mut framebuffer = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+            // Feedback shaders accumulate state in their buffers, which needs
+            // more precision than 8 bits when the driver can render to it
+            let (internal_format, data_type) = if Self::supports_half_float() {
+                (gl::RGBA16F, gl::HALF_FLOAT)
+            } else {
+                (gl::RGBA8, gl::UNSIGNED_BYTE)
+            };
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                data_type,
+                std::ptr::null(),
+            );
+
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                texture,
+                0,
+            );
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+
+            gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            let texture = GlTexture { id: texture, width, height };
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                gl::DeleteFramebuffers(1, &framebuffer);
+                return Err(anyhow!("Framebuffer incomplete: 0x{:x}", status));
+            }
+            Ok(Self { id: framebuffer, texture })
+        }
+    }
+
+    fn supports_half_float() -> bool {
+        static SUPPORTED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+        *SUPPORTED.get_or_init(|| unsafe {
+            let mut count = 0;
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+            (0..count.max(0) as u32).any(|i| {
+                let name = gl::GetStringi(gl::EXTENSIONS, i);
+                if name.is_null() {
+                    return false;
+                }
+                let name = CStr::from_ptr(name as *const _).to_bytes();
+                name == b"GL_EXT_color_buffer_float" || name == b"GL_EXT_color_buffer_half_float"
+            })
+        })
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+    }
+}
+
+impl Drop for GlFramebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
+
 pub struct GlProgram {
     pub id: u32,
 }
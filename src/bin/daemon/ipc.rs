@@ -1,57 +1,125 @@
-use crate::media::MediaType;
+use crate::events::{self, Event};
+use crate::media::passes::{self, Uniforms};
+use crate::media::transition::TransitionKind;
+use crate::media::{self, MediaType, Transition};
+use crate::playlist::PlaylistConfig;
 use anyhow::{Result, anyhow};
-use serde::{Deserialize, Serialize};
-use std::io::{BufRead, BufReader, Write};
+use papyrust::protocol::{PROTOCOL_VERSION, Request, Response};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::sync::mpsc::Sender;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum IpcCommand {
-    SetImage {
-        path: String,
-        shader: Option<String>,
-        monitors: Option<Vec<String>>,
-    },
-    SetVideo {
-        path: String,
-        shader: Option<String>,
-        monitors: Option<Vec<String>>,
-        #[serde(default)]
-        mute: bool,
-    },
-    SetShader {
-        path: String,
-        monitors: Option<Vec<String>>,
-    },
-}
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+const HANGUP_CHECK: Duration = Duration::from_secs(1);
+/// How long a media change sent with `wait` may take to show its first frame
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum IpcResponse {
-    Success,
-    Error { message: String },
-    Status { current_media: String },
-}
+pub use papyrust::protocol::{
+    ErrorCode, IpcCommand, IpcError, IpcResponse, MonitorInfo, MonitorStatus, PlaybackState,
+};
+
+pub type IpcResult<T> = std::result::Result<T, IpcError>;
 
 #[derive(Debug, Clone)]
 pub struct MediaChange {
     pub media_type: MediaType,
     pub monitors: Option<Vec<String>>,
     pub mute: bool,
+    pub transition: Transition,
+}
+
+pub enum PlaylistRequest {
+    Start(PlaylistConfig),
+    Step {
+        delta: isize,
+        monitors: Option<Vec<String>>,
+    },
+    Pause {
+        paused: bool,
+        monitors: Option<Vec<String>>,
+    },
+}
+
+pub enum DaemonRequest {
+    /// Answered once the change is applied or, with `wait`, once its first
+    /// frame is on every targeted output
+    Media {
+        change: MediaChange,
+        wait: bool,
+        reply: Sender<IpcResult<()>>,
+    },
+    Status(Sender<Vec<MonitorStatus>>),
+    Reload(Sender<Result<(), String>>),
+    Restore {
+        monitors: Option<Vec<String>>,
+        reply: Sender<Result<(), String>>,
+    },
+    Shutdown,
+    Volume {
+        volume: f32,
+        monitors: Option<Vec<String>>,
+        reply: Sender<IpcResult<()>>,
+    },
+    Uniforms {
+        uniforms: Uniforms,
+        monitors: Option<Vec<String>>,
+        reply: Sender<IpcResult<()>>,
+    },
+    Pause {
+        paused: bool,
+        monitors: Option<Vec<String>>,
+        reply: Sender<IpcResult<()>>,
+    },
+    Playlist(PlaylistRequest, Sender<Result<(), String>>),
 }
 
-pub fn start_server(tx: Sender<MediaChange>) -> Result<()> {
-    let socket_path = "/tmp/papyrust-daemon.sock";
-    let _ = std::fs::remove_file(socket_path);
+/// Binds the IPC socket at `path`, readable and writable by the current user
+/// only. A socket left behind by a daemon that did not exit cleanly is
+/// replaced, but one another daemon still answers on is an error.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if !meta.file_type().is_socket() => {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        Ok(_) => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(anyhow!(
+                    "Another papyrust daemon is already listening on {}",
+                    path.display()
+                ));
+            }
+            fs::remove_file(path)
+                .map_err(|e| anyhow!("Failed to remove stale socket {}: {}", path.display(), e))?;
+            tracing::debug!(event = "ipc_stale_socket", path = %path.display(), "Removed stale IPC socket");
+        }
+        Err(_) => {}
+    }
 
-    let listener =
-        UnixListener::bind(socket_path).map_err(|e| anyhow!("Failed to bind IPC socket: {}", e))?;
+    // Clear the group and other bits before binding so the socket is never
+    // reachable by other users, even briefly
+    let old_mask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(old_mask) };
+    let listener = listener
+        .map_err(|e| anyhow!("Failed to bind IPC socket {}: {}", path.display(), e))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| anyhow!("Failed to restrict IPC socket {}: {}", path.display(), e))?;
 
     tracing::info!(
         event = "ipc_listen",
-        path = socket_path,
+        path = %path.display(),
         "IPC server listening"
     );
+    Ok(listener)
+}
+
+pub fn start_server(listener: UnixListener, tx: Sender<DaemonRequest>) -> Result<()> {
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
@@ -71,7 +139,7 @@ pub fn start_server(tx: Sender<MediaChange>) -> Result<()> {
     Ok(())
 }
 
-fn handle_client(stream: UnixStream, tx: Sender<MediaChange>) -> Result<()> {
+fn handle_client(stream: UnixStream, tx: Sender<DaemonRequest>) -> Result<()> {
     let peer = stream.peer_addr().ok();
     tracing::debug!(event = "ipc_client_begin", ?peer, "Client connected");
 
@@ -80,98 +148,513 @@ fn handle_client(stream: UnixStream, tx: Sender<MediaChange>) -> Result<()> {
     let mut line = String::new();
 
     while reader.read_line(&mut line)? > 0 {
-        let trimmed = line.trim();
-        let command: IpcCommand =
-            serde_json::from_str(trimmed).map_err(|e| anyhow!("Invalid JSON command: {}", e))?;
-
-        match &command {
-            IpcCommand::SetImage { monitors, path, .. } => {
-                let target_desc = match monitors {
-                    None => "all monitors".to_string(),
-                    Some(mons) => format!("monitors: {}", mons.join(", ")),
-                };
-                tracing::info!(event = "ipc_command", cmd = "SetImage", target = %target_desc, path = %path, "Applying image");
-            }
-            IpcCommand::SetVideo {
-                monitors,
-                path,
-                mute,
-                ..
-            } => {
-                let target_desc = match monitors {
-                    None => "all monitors".to_string(),
-                    Some(mons) => format!("monitors: {}", mons.join(", ")),
-                };
-                tracing::info!(event = "ipc_command", cmd = "SetVideo", target = %target_desc, path = %path, mute = *mute, "Applying video");
+        let (framing, command) = parse_request(line.trim());
+        // Subscribed before replying so no event after the reply is missed
+        let subscription = matches!(command, Ok(IpcCommand::Subscribe)).then(events::subscribe);
+        let shutdown = matches!(command, Ok(IpcCommand::Shutdown));
+        let response = match command {
+            Ok(command) => handle_command(command, &tx),
+            Err(e) => {
+                tracing::warn!(event = "ipc_invalid_request", error = %e, "Rejecting malformed request");
+                e.into()
             }
-            IpcCommand::SetShader { monitors, path } => {
-                let target_desc = match monitors {
-                    None => "all monitors".to_string(),
-                    Some(mons) => format!("monitors: {}", mons.join(", ")),
-                };
-                tracing::info!(event = "ipc_command", cmd = "SetShader", target = %target_desc, path = %path, "Applying shader");
+        };
+
+        // Bare commands from before the versioned protocol get bare replies
+        let response_json = match framing {
+            Framing::Request(id) => serde_json::to_string(&Response { id, response })?,
+            Framing::Bare => serde_json::to_string(&response)?,
+        };
+        writeln!(writer, "{}", response_json)?;
+        writer.flush()?;
+
+        tracing::debug!(event = "ipc_reply", response = %response_json, "Sent reply to client");
+        line.clear();
+
+        if let Some(events) = subscription {
+            return stream_events(writer, events);
+        }
+        if shutdown {
+            tx.send(DaemonRequest::Shutdown)?;
+        }
+    }
+
+    tracing::debug!(event = "ipc_client_end", "Client disconnected");
+    Ok(())
+}
+
+/// Writes events to a subscribed client until it disconnects. While no
+/// events come the socket is checked every [`HANGUP_CHECK`], so an idle
+/// subscriber that went away does not hold on to its thread.
+fn stream_events(mut writer: UnixStream, events: Receiver<Event>) -> Result<()> {
+    loop {
+        match events.recv_timeout(HANGUP_CHECK) {
+            Ok(event) => {
+                let line = serde_json::to_string(&event)?;
+                if writeln!(writer, "{}", line).and_then(|_| writer.flush()).is_err() {
+                    break;
+                }
             }
+            Err(RecvTimeoutError::Timeout) if !hung_up(&writer) => {}
+            Err(_) => break,
         }
+    }
+    tracing::debug!(event = "ipc_unsubscribe", "Event subscriber disconnected");
+    Ok(())
+}
 
-        let response = match command {
-            IpcCommand::SetImage {
-                path,
-                shader,
-                monitors,
-            } => {
-                let media_change = MediaChange {
-                    media_type: MediaType::Image { path, shader },
+/// Whether the client closed its end of `stream`. Subscribers send nothing,
+/// so the socket only turns readable at end of stream.
+fn hung_up(stream: &UnixStream) -> bool {
+    let mut byte = [0];
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let peeked = stream.peek(&mut byte);
+    let _ = stream.set_nonblocking(false);
+    match peeked {
+        Ok(read) => read == 0,
+        Err(e) => e.kind() != ErrorKind::WouldBlock,
+    }
+}
+
+/// How a request was sent, and so how its reply is framed.
+enum Framing {
+    /// A [`Request`] envelope, with its id when one could be read
+    Request(Option<u64>),
+    /// A bare [`IpcCommand`]
+    Bare,
+}
+
+fn parse_request(line: &str) -> (Framing, IpcResult<IpcCommand>) {
+    let invalid = |e: serde_json::Error| IpcError::new(ErrorCode::InvalidRequest, format!("Invalid request: {}", e));
+
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return (Framing::Bare, Err(invalid(e))),
+    };
+    if value.get("id").is_some() || value.get("command").is_some() {
+        let id = value.get("id").and_then(Value::as_u64);
+        match serde_json::from_value::<Request>(value) {
+            Ok(request) => (Framing::Request(Some(request.id)), Ok(request.command)),
+            Err(e) => (Framing::Request(id), Err(invalid(e))),
+        }
+    } else {
+        (Framing::Bare, serde_json::from_value(value).map_err(invalid))
+    }
+}
+
+fn handle_command(command: IpcCommand, tx: &Sender<DaemonRequest>) -> IpcResponse {
+    match &command {
+        IpcCommand::Hello { version, client } => {
+            tracing::debug!(event = "ipc_command", cmd = "Hello", version = *version, client = client.as_deref(), "Client introduced itself");
+        }
+        IpcCommand::SetImage { monitors, path, .. } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "SetImage", target = %target_desc, path = %path, "Applying image");
+        }
+        IpcCommand::SetVideo {
+            monitors,
+            path,
+            mute,
+            ..
+        } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "SetVideo", target = %target_desc, path = %path, mute = *mute, "Applying video");
+        }
+        IpcCommand::SetShader { monitors, path, .. } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "SetShader", target = %target_desc, path = %path, "Applying shader");
+        }
+        IpcCommand::SetVolume { volume, monitors } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "SetVolume", target = %target_desc, volume = *volume, "Setting volume");
+        }
+        IpcCommand::SetUniforms { uniforms, monitors } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "SetUniforms", target = %target_desc, count = uniforms.len(), "Setting shader uniforms");
+        }
+        IpcCommand::Pause { monitors } | IpcCommand::Resume { monitors } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            let cmd = if matches!(command, IpcCommand::Pause { .. }) { "Pause" } else { "Resume" };
+            tracing::info!(event = "ipc_command", cmd, target = %target_desc, "Changing playback");
+        }
+        IpcCommand::SetPlaylist { playlist } => {
+            let target_desc = match &playlist.monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "SetPlaylist", target = %target_desc, "Starting playlist");
+        }
+        IpcCommand::Next { monitors }
+        | IpcCommand::Previous { monitors }
+        | IpcCommand::PausePlaylist { monitors }
+        | IpcCommand::ResumePlaylist { monitors } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            let cmd = match command {
+                IpcCommand::Next { .. } => "Next",
+                IpcCommand::Previous { .. } => "Previous",
+                IpcCommand::PausePlaylist { .. } => "PausePlaylist",
+                _ => "ResumePlaylist",
+            };
+            tracing::info!(event = "ipc_command", cmd, target = %target_desc, "Controlling playlist");
+        }
+        IpcCommand::GetStatus | IpcCommand::ListMonitors => {
+            tracing::debug!(event = "ipc_command", cmd = ?command, "Querying daemon status");
+        }
+        IpcCommand::Reload => {
+            tracing::info!(event = "ipc_command", cmd = "Reload", "Reloading config");
+        }
+        IpcCommand::Subscribe => {
+            tracing::debug!(event = "ipc_command", cmd = "Subscribe", "Client subscribed to events");
+        }
+        IpcCommand::Restore { monitors } => {
+            let target_desc = match monitors {
+                None => "all monitors".to_string(),
+                Some(mons) => format!("monitors: {}", mons.join(", ")),
+            };
+            tracing::info!(event = "ipc_command", cmd = "Restore", target = %target_desc, "Restoring saved media");
+        }
+        IpcCommand::Shutdown => {
+            tracing::info!(event = "ipc_command", cmd = "Shutdown", "Shutting down");
+        }
+    }
+
+    execute(command, tx).unwrap_or_else(IpcResponse::from)
+}
+
+fn execute(command: IpcCommand, tx: &Sender<DaemonRequest>) -> IpcResult<IpcResponse> {
+    match command {
+        IpcCommand::Hello { version, .. } => {
+            if version < PROTOCOL_VERSION {
+                return Err(IpcError::new(
+                    ErrorCode::UnsupportedVersion,
+                    format!(
+                        "Protocol version {} is not supported: this daemon speaks version {}",
+                        version, PROTOCOL_VERSION
+                    ),
+                ));
+            }
+            Ok(IpcResponse::Hello {
+                version: PROTOCOL_VERSION,
+                daemon: env!("CARGO_PKG_VERSION").to_string(),
+            })
+        }
+        IpcCommand::SetImage {
+            path,
+            shader,
+            monitors,
+            fit,
+            transition,
+            wait,
+        } => {
+            require_files(&path, shader.as_deref(), &transition)?;
+            send_media(
+                tx,
+                MediaChange {
+                    media_type: MediaType::Image { path, shader, fit },
                     monitors,
                     mute: false,
-                };
-                match tx.send(media_change) {
-                    Ok(_) => IpcResponse::Success,
-                    Err(e) => IpcResponse::Error {
-                        message: e.to_string(),
-                    },
-                }
-            }
-            IpcCommand::SetVideo {
-                path,
-                shader,
-                monitors,
-                mute,
-            } => {
-                let media_change = MediaChange {
-                    media_type: MediaType::Video { path, shader },
+                    transition,
+                },
+                wait,
+            )
+        }
+        IpcCommand::SetVideo {
+            path,
+            shader,
+            monitors,
+            mute,
+            fit,
+            transition,
+            wait,
+        } => {
+            require_files(&path, shader.as_deref(), &transition)?;
+            send_media(
+                tx,
+                MediaChange {
+                    media_type: MediaType::Video { path, shader, fit },
                     monitors,
                     mute,
-                };
-                match tx.send(media_change) {
-                    Ok(_) => IpcResponse::Success,
-                    Err(e) => IpcResponse::Error {
-                        message: e.to_string(),
-                    },
-                }
-            }
-            IpcCommand::SetShader { path, monitors } => {
-                let media_change = MediaChange {
+                    transition,
+                },
+                wait,
+            )
+        }
+        IpcCommand::SetShader {
+            path,
+            monitors,
+            transition,
+            wait,
+        } => {
+            require_files(&path, None, &transition)?;
+            send_media(
+                tx,
+                MediaChange {
                     media_type: MediaType::Shader(path),
                     monitors,
                     mute: false,
-                };
-                match tx.send(media_change) {
-                    Ok(_) => IpcResponse::Success,
-                    Err(e) => IpcResponse::Error {
-                        message: e.to_string(),
-                    },
-                }
+                    transition,
+                },
+                wait,
+            )
+        }
+        IpcCommand::SetVolume { volume, monitors } => {
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(IpcError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Volume {} out of range: expected 0.0 to 1.0", volume),
+                ));
+            }
+            query(tx, |reply| DaemonRequest::Volume { volume, monitors, reply })??;
+            Ok(IpcResponse::Success)
+        }
+        IpcCommand::SetUniforms { uniforms, monitors } => {
+            for (name, value) in &uniforms {
+                passes::validate_uniform(name, value)
+                    .map_err(|e| IpcError::new(ErrorCode::InvalidArgument, e.to_string()))?;
             }
+            query(tx, |reply| DaemonRequest::Uniforms { uniforms, monitors, reply })??;
+            Ok(IpcResponse::Success)
+        }
+        IpcCommand::Pause { monitors } => send_pause(tx, true, monitors),
+        IpcCommand::Resume { monitors } => send_pause(tx, false, monitors),
+        IpcCommand::SetPlaylist { playlist } => send_playlist(tx, PlaylistRequest::Start(playlist)),
+        IpcCommand::Next { monitors } => {
+            send_playlist(tx, PlaylistRequest::Step { delta: 1, monitors })
+        }
+        IpcCommand::Previous { monitors } => {
+            send_playlist(tx, PlaylistRequest::Step { delta: -1, monitors })
+        }
+        IpcCommand::PausePlaylist { monitors } => {
+            send_playlist(tx, PlaylistRequest::Pause { paused: true, monitors })
+        }
+        IpcCommand::ResumePlaylist { monitors } => {
+            send_playlist(tx, PlaylistRequest::Pause { paused: false, monitors })
+        }
+        IpcCommand::GetStatus => Ok(IpcResponse::Status {
+            monitors: query(tx, DaemonRequest::Status)?,
+        }),
+        IpcCommand::ListMonitors => Ok(IpcResponse::Monitors {
+            monitors: query(tx, DaemonRequest::Status)?
+                .into_iter()
+                .map(|m| m.output)
+                .collect(),
+        }),
+        IpcCommand::Reload => {
+            query(tx, DaemonRequest::Reload)?
+                .map_err(|message| IpcError::new(ErrorCode::ConfigError, message))?;
+            Ok(IpcResponse::Success)
+        }
+        IpcCommand::Restore { monitors } => {
+            query(tx, |reply| DaemonRequest::Restore { monitors, reply })?
+                .map_err(|message| IpcError::new(ErrorCode::Internal, message))?;
+            Ok(IpcResponse::Success)
+        }
+        // Both are carried out by `handle_client` once this is answered
+        IpcCommand::Subscribe | IpcCommand::Shutdown => Ok(IpcResponse::Success),
+    }
+}
+
+/// Checks that the media, shader and custom transition of a command exist
+/// before it reaches the render loop.
+fn require_files(path: &str, shader: Option<&str>, transition: &Transition) -> IpcResult<()> {
+    // Transition paths are the only ones the render loop expands `~` in
+    let custom = match &transition.kind {
+        TransitionKind::Custom(path) => Some(shellexpand::tilde(path).into_owned()),
+        _ => None,
+    };
+    let files = std::iter::once(path).chain(shader).chain(custom.as_deref());
+    for file in files {
+        if file != "default" && !Path::new(file).exists() {
+            return Err(IpcError::new(
+                ErrorCode::FileNotFound,
+                format!("No such file: {}", file),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn unavailable(e: impl std::fmt::Display) -> IpcError {
+    IpcError::new(ErrorCode::Unavailable, format!("Render loop unavailable: {}", e))
+}
+
+fn send_pause(
+    tx: &Sender<DaemonRequest>,
+    paused: bool,
+    monitors: Option<Vec<String>>,
+) -> IpcResult<IpcResponse> {
+    query(tx, |reply| DaemonRequest::Pause { paused, monitors, reply })??;
+    Ok(IpcResponse::Success)
+}
+
+/// Probes the media before handing it to the render loop, which compiles its
+/// shaders before applying it.
+fn send_media(tx: &Sender<DaemonRequest>, change: MediaChange, wait: bool) -> IpcResult<IpcResponse> {
+    media::probe::probe(&change.media_type)
+        .map_err(|e| IpcError::new(ErrorCode::DecodeFailed, e.to_string()))?;
+    // The render loop gives up on waiting itself; this only guards against it
+    // not answering at all
+    let timeout = if wait { WAIT_TIMEOUT + QUERY_TIMEOUT } else { QUERY_TIMEOUT };
+    query_within(tx, timeout, |reply| DaemonRequest::Media { change, wait, reply })??;
+    Ok(IpcResponse::Success)
+}
+
+/// Sends a request the render loop answers on the given channel and waits for
+/// the answer.
+fn query<T>(
+    tx: &Sender<DaemonRequest>,
+    request: impl FnOnce(Sender<T>) -> DaemonRequest,
+) -> IpcResult<T> {
+    query_within(tx, QUERY_TIMEOUT, request)
+}
+
+fn query_within<T>(
+    tx: &Sender<DaemonRequest>,
+    timeout: Duration,
+    request: impl FnOnce(Sender<T>) -> DaemonRequest,
+) -> IpcResult<T> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    tx.send(request(reply_tx)).map_err(unavailable)?;
+    reply_rx.recv_timeout(timeout).map_err(|e| {
+        IpcError::new(
+            ErrorCode::Unavailable,
+            format!("Timed out waiting for render loop: {}", e),
+        )
+    })
+}
+
+fn send_playlist(tx: &Sender<DaemonRequest>, request: PlaylistRequest) -> IpcResult<IpcResponse> {
+    query(tx, |reply| DaemonRequest::Playlist(request, reply))?
+        .map_err(|message| IpcError::new(ErrorCode::InvalidArgument, message))?;
+    Ok(IpcResponse::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(reader: &mut BufReader<UnixStream>, writer: &mut UnixStream, line: &str) -> Value {
+        writeln!(writer, "{}", line).unwrap();
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    fn error_code(response: &Value) -> ErrorCode {
+        match serde_json::from_value(response.clone()).unwrap() {
+            IpcResponse::Error { code, .. } => code,
+            response => panic!("expected an error, got {:?}", response),
+        }
+    }
+
+    #[test]
+    fn frames_requests() {
+        let (framing, command) = parse_request(r#"{"id":4,"command":"GetStatus"}"#);
+        assert!(matches!(framing, Framing::Request(Some(4))));
+        assert!(matches!(command, Ok(IpcCommand::GetStatus)));
+
+        let (framing, command) = parse_request(r#"{"SetImage":{"path":"/a.png","shader":null,"monitors":null}}"#);
+        assert!(matches!(framing, Framing::Bare));
+        assert!(matches!(command, Ok(IpcCommand::SetImage { .. })));
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let (framing, command) = parse_request("{not json");
+        assert!(matches!(framing, Framing::Bare));
+        assert_eq!(command.unwrap_err().code, ErrorCode::InvalidRequest);
+
+        // The id is echoed even when the command cannot be read
+        let (framing, command) = parse_request(r#"{"id":9,"command":{"Bogus":{}}}"#);
+        assert!(matches!(framing, Framing::Request(Some(9))));
+        assert_eq!(command.unwrap_err().code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn rejects_old_protocol_versions() {
+        let (tx, _rx) = mpsc::channel();
+        let old = IpcCommand::Hello {
+            version: PROTOCOL_VERSION - 1,
+            client: None,
         };
+        assert_eq!(execute(old, &tx).unwrap_err().code, ErrorCode::UnsupportedVersion);
 
-        let response_json = serde_json::to_string(&response)?;
-        writeln!(writer, "{}", response_json)?;
-        writer.flush()?;
+        let current = IpcCommand::Hello {
+            version: PROTOCOL_VERSION,
+            client: None,
+        };
+        assert!(matches!(
+            execute(current, &tx),
+            Ok(IpcResponse::Hello { version: PROTOCOL_VERSION, .. })
+        ));
+    }
 
-        tracing::debug!(event = "ipc_reply", response = %response_json, "Sent reply to client");
-        line.clear();
+    #[test]
+    fn keeps_connection_open_after_errors() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let (tx, _rx) = mpsc::channel();
+        let handler = thread::spawn(move || handle_client(server, tx));
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut writer = client;
+
+        let response = reply(&mut reader, &mut writer, "{not json");
+        assert_eq!(error_code(&response), ErrorCode::InvalidRequest);
+
+        let response = reply(&mut reader, &mut writer, r#"{"id":7,"command":{"Bogus":{}}}"#);
+        assert_eq!(response["id"], 7);
+        assert_eq!(error_code(&response["response"]), ErrorCode::InvalidRequest);
+
+        // Bare commands get bare replies
+        let response = reply(
+            &mut reader,
+            &mut writer,
+            r#"{"SetImage":{"path":"/nonexistent/a.png","shader":null,"monitors":null}}"#,
+        );
+        assert!(response.get("id").is_none());
+        assert_eq!(error_code(&response), ErrorCode::FileNotFound);
+
+        let response = reply(
+            &mut reader,
+            &mut writer,
+            &format!(r#"{{"id":8,"command":{{"Hello":{{"version":{}}}}}}}"#, PROTOCOL_VERSION),
+        );
+        assert_eq!(response["id"], 8);
+        assert_eq!(response["response"]["Hello"]["version"], PROTOCOL_VERSION);
+
+        drop(writer);
+        drop(reader);
+        handler.join().unwrap().unwrap();
     }
 
-    tracing::debug!(event = "ipc_client_end", "Client disconnected");
-    Ok(())
+    #[test]
+    fn stops_streaming_to_idle_clients_that_hang_up() {
+        let (client, server) = UnixStream::pair().unwrap();
+        // Kept alive so only the hang-up can end the stream
+        let (_events_tx, events) = mpsc::channel();
+        drop(client);
+        stream_events(server, events).unwrap();
+    }
 }
@@ -1,21 +1,29 @@
 use anyhow::Result;
 use clap::{Parser, ValueEnum};
-use std::{process, sync::mpsc, thread};
+use serde::Deserialize;
+use std::{path::PathBuf, process, sync::mpsc, thread};
 
 use tracing_log::LogTracer;
 use tracing_subscriber::{EnvFilter, fmt};
 
+mod config;
+mod events;
 mod gl_utils;
 mod ipc;
 mod media;
+mod playlist;
+mod power;
+mod state;
 mod utils;
+mod watcher;
 mod wayland; 
 
 mod gl_bindings {
     include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
 enum Layer {
     Bottom,
     Top,
@@ -35,15 +43,6 @@ impl std::fmt::Display for Layer {
     }
 }
 
-#[derive(ValueEnum, Clone, Debug)]
-enum ScalingMode {
-    FSR,
-    Lanczos,
-    Mitchell,
-    Bicubic,
-    None,
-}
-
 #[derive(Parser, Debug)]
 #[command(
     name = "papyrust-daemon",
@@ -54,8 +53,8 @@ struct Args {
     #[arg(short = 'F', long)]
     fork: bool,
 
-    #[arg(short, long, default_value = "0")]
-    fps: u16,
+    #[arg(short, long)]
+    fps: Option<u16>,
 
     #[arg(short, long)]
     layer: Option<Layer>,
@@ -65,6 +64,23 @@ struct Args {
 
     #[arg(long, alias = "no-audio")]
     mute: bool,
+
+    /// Resampling filter used when media is scaled to fit an output
+    #[arg(long)]
+    scaling: Option<media::ScalingMode>,
+
+    /// Decode video on the GPU, falling back to software when unavailable
+    #[arg(long)]
+    hwdec: Option<media::HwDecode>,
+
+    /// Config file to read instead of ~/.config/papyrust/config.toml
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// IPC socket to listen on instead of $PAPYRUST_SOCKET or
+    /// $XDG_RUNTIME_DIR/papyrust-$WAYLAND_DISPLAY.sock
+    #[arg(long)]
+    socket: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
@@ -79,16 +95,33 @@ fn main() -> Result<()> {
 
     let args = Args::parse();
 
+    let overrides = config::Overrides {
+        fps: args.fps,
+        layer: args.layer.clone(),
+        fifo: args.fifo.clone(),
+        mute: args.mute,
+        scaling: args.scaling,
+        hwdec: args.hwdec,
+    };
+    let settings = config::Settings::load(args.config.clone(), overrides)?;
+    media::hwaccel::set_mode(settings.hwdec);
+
     tracing::info!(
         event = "daemon_start",
         fork = args.fork,
-        fps = args.fps,
-        layer = args.layer.as_ref().map(|l| l.to_string()),
-        fifo = args.fifo.as_deref(),
-        mute = args.mute,
+        fps = settings.fps,
+        layer = settings.layer_name(),
+        fifo = settings.fifo.as_deref(),
+        mute = settings.mute,
+        scaling = ?settings.scaling,
+        hwdec = ?settings.hwdec,
         "Starting Papyrust daemon with unified resource management"
     );
 
+    // Bound before forking so a second daemon fails where it was started
+    let socket_path = papyrust::socket::path(args.socket.clone());
+    let listener = ipc::bind(&socket_path)?;
+
     if args.fork {
         unsafe {
             let pid = libc::fork();
@@ -111,21 +144,19 @@ fn main() -> Result<()> {
 
     let ipc_tx = tx.clone();
     thread::spawn(move || {
-        if let Err(e) = ipc::start_server(ipc_tx) {
+        if let Err(e) = ipc::start_server(listener, ipc_tx) {
             tracing::error!(event = "ipc_server_error", error = %e, "IPC server error");
         }
     });
 
-    let init_media = media::MediaType::Shader("default".to_string());
+    let init_state = state::DaemonState::load().unwrap_or_else(|e| {
+        tracing::warn!(event = "state_load_error", error = %e, "Failed to load daemon state, starting fresh");
+        state::DaemonState::default()
+    });
 
-    wayland::init(
-        init_media,
-        args.fps,
-        args.layer.as_ref().map(|l| l.to_string()).as_deref(),
-        args.fifo.as_deref(),
-        rx,
-        args.mute,
-    )?;
+    let result = wayland::init(init_state, settings, rx);
+    let _ = std::fs::remove_file(&socket_path);
+    result?;
 
     tracing::info!(event = "daemon_exit", "Papyrust daemon exited");
     Ok(())
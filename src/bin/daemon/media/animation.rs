@@ -0,0 +1,565 @@
+use crate::gl_utils::GlTexture;
+use crate::media::{BaseMediaHandler, Fit, MediaHandler, scaling};
+use crate::utils;
+use anyhow::{Result, anyhow};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Frames};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::thread;
+
+/// Frames the decoder thread may get ahead of presentation.
+const FRAME_QUEUE: usize = 3;
+/// Browsers show frames with a delay this short or shorter for 100 ms, and
+/// many files rely on it.
+const MIN_DELAY_MS: u64 = 10;
+const DEFAULT_DELAY_MS: u64 = 100;
+/// Bytes read to find the animation chunks, which come before any image data
+const HEADER_LEN: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Gif,
+    Png,
+    WebP,
+}
+
+impl Format {
+    fn sniff(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+            Some(Format::Gif)
+        } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Format::Png)
+        } else if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+            Some(Format::WebP)
+        } else {
+            None
+        }
+    }
+}
+
+fn read_header(path: &str) -> Option<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)
+        .ok()?;
+    Some(header)
+}
+
+/// Chunks of a PNG file as type and data.
+fn png_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = 8;
+    std::iter::from_fn(move || {
+        let header = data.get(offset..offset + 8)?;
+        let len = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+        let body = data.get(offset + 8..offset + 8 + len)?;
+        offset += 12 + len;
+        Some((&header[4..8], body))
+    })
+}
+
+/// Chunks of a RIFF (WebP) file as FourCC and data.
+fn riff_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = 12;
+    std::iter::from_fn(move || {
+        let header = data.get(offset..offset + 8)?;
+        let len = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+        let body = data.get(offset + 8..(offset + 8 + len).min(data.len()))?;
+        offset += 8 + len + (len & 1);
+        Some((&header[0..4], body))
+    })
+}
+
+/// Whether the image at `path` is an animated GIF, APNG or WebP with more
+/// than one frame. Only the file's structure is read, never its pixels, as
+/// this runs on the render thread for every media change.
+pub fn is_animated(path: &str) -> bool {
+    let Some(header) = read_header(path) else {
+        return false;
+    };
+    match Format::sniff(&header) {
+        Some(Format::Gif) => File::open(path)
+            .ok()
+            .and_then(|file| gif_is_animated(BufReader::new(file)))
+            .unwrap_or(false),
+        Some(Format::Png) => png_is_animated(&header),
+        Some(Format::WebP) => riff_chunks(&header).any(|(kind, _)| kind == b"ANIM"),
+        None => false,
+    }
+}
+
+/// Whether an `acTL` chunk before the image data asks for more than one frame.
+fn png_is_animated(header: &[u8]) -> bool {
+    png_chunks(header)
+        .take_while(|(kind, _)| *kind != b"IDAT")
+        .any(|(kind, body)| {
+            kind == b"acTL"
+                && body.len() >= 4
+                && u32::from_be_bytes([body[0], body[1], body[2], body[3]]) > 1
+        })
+}
+
+/// Whether a GIF holds more than one image, found by walking its blocks and
+/// skipping their data, so no frame is decoded. `None` for a malformed file.
+fn gif_is_animated(mut reader: impl BufRead) -> Option<bool> {
+    // Signature and logical screen descriptor, then the global colour table
+    let mut screen = [0u8; 13];
+    reader.read_exact(&mut screen).ok()?;
+    skip(&mut reader, gif_color_table_len(screen[10]))?;
+
+    let mut images = 0;
+    loop {
+        let mut introducer = [0u8; 1];
+        reader.read_exact(&mut introducer).ok()?;
+        match introducer[0] {
+            // Extension label and its data
+            0x21 => {
+                skip(&mut reader, 1)?;
+                skip_gif_sub_blocks(&mut reader)?;
+            }
+            // Image descriptor, local colour table, LZW code size and image data
+            0x2C => {
+                images += 1;
+                if images > 1 {
+                    return Some(true);
+                }
+                let mut descriptor = [0u8; 9];
+                reader.read_exact(&mut descriptor).ok()?;
+                skip(&mut reader, gif_color_table_len(descriptor[8]) + 1)?;
+                skip_gif_sub_blocks(&mut reader)?;
+            }
+            // Trailer
+            0x3B => return Some(false),
+            _ => return None,
+        }
+    }
+}
+
+fn gif_color_table_len(flags: u8) -> u64 {
+    if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+fn skip_gif_sub_blocks(reader: &mut impl BufRead) -> Option<()> {
+    loop {
+        let mut len = [0u8; 1];
+        reader.read_exact(&mut len).ok()?;
+        if len[0] == 0 {
+            return Some(());
+        }
+        skip(reader, u64::from(len[0]))?;
+    }
+}
+
+/// Reads past `len` bytes, failing if the file ends first.
+fn skip(reader: &mut impl BufRead, len: u64) -> Option<()> {
+    let skipped = io::copy(&mut reader.by_ref().take(len), &mut io::sink()).ok()?;
+    (skipped == len).then_some(())
+}
+
+/// How many times the file asks to be played, `None` meaning forever.
+fn play_count(header: &[u8], format: Format) -> Option<u32> {
+    let count = match format {
+        // The NETSCAPE2.0 extension counts repeats after the first play; without
+        // it the animation plays once
+        Format::Gif => match header.windows(11).position(|w| w == b"NETSCAPE2.0") {
+            Some(at) => match header.get(at + 11..at + 15) {
+                Some([3, 1, lo, hi]) => match u16::from_le_bytes([*lo, *hi]) {
+                    0 => 0,
+                    repeats => u32::from(repeats) + 1,
+                },
+                _ => 0,
+            },
+            None => 1,
+        },
+        Format::Png => png_chunks(header)
+            .find(|(kind, body)| *kind == b"acTL" && body.len() >= 8)
+            .map_or(0, |(_, body)| {
+                u32::from_be_bytes([body[4], body[5], body[6], body[7]])
+            }),
+        Format::WebP => riff_chunks(header)
+            .find(|(kind, body)| *kind == b"ANIM" && body.len() >= 6)
+            .map_or(0, |(_, body)| {
+                u32::from(u16::from_le_bytes([body[4], body[5]]))
+            }),
+    };
+    (count > 0).then_some(count)
+}
+
+fn open_frames(path: &str, format: Format) -> Result<Frames<'static>> {
+    let reader =
+        BufReader::new(File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path, e))?);
+    let frames = match format {
+        Format::Gif => GifDecoder::new(reader)?.into_frames(),
+        Format::Png => PngDecoder::new(reader)?.apng()?.into_frames(),
+        Format::WebP => WebPDecoder::new(reader)?.into_frames(),
+    };
+    Ok(frames)
+}
+
+struct AnimationFrame {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    delay_ms: u64,
+}
+
+enum AnimationEvent {
+    Frame(AnimationFrame),
+    Error(String),
+}
+
+/// Decodes frames in a loop for as many plays as the file asks for, resampled
+/// for the last size received on `resizes`.
+fn decode_loop(
+    path: &str,
+    fit: Fit,
+    events: &SyncSender<AnimationEvent>,
+    resizes: &Receiver<(u32, u32)>,
+) -> Result<()> {
+    let header = read_header(path).ok_or_else(|| anyhow!("Failed to read {}", path))?;
+    let format =
+        Format::sniff(&header).ok_or_else(|| anyhow!("{} is not a GIF, PNG or WebP file", path))?;
+    let plays = play_count(&header, format);
+    tracing::info!(event = "animation_info", path = %path, ?format, ?plays, "Decoding animation");
+
+    let mut output_size = (0, 0);
+    let mut played = 0;
+    loop {
+        let mut frame_count = 0;
+        for frame in open_frames(path, format)? {
+            while let Ok(size) = resizes.try_recv() {
+                output_size = size;
+            }
+            let frame = frame.map_err(|e| anyhow!("Failed to decode frame of {}: {}", path, e))?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay_ms = match u64::from(numer) / u64::from(denom.max(1)) {
+                delay if delay <= MIN_DELAY_MS => DEFAULT_DELAY_MS,
+                delay => delay,
+            };
+
+            let buffer = frame.into_buffer();
+            let (width, height) = buffer.dimensions();
+            let frame = match fit.scaled_size(width, height, output_size.0, output_size.1) {
+                Some((target_width, target_height)) => AnimationFrame {
+                    width: target_width,
+                    height: target_height,
+                    data: scaling::resize_rgba(
+                        buffer.as_raw(),
+                        width,
+                        height,
+                        target_width,
+                        target_height,
+                        fit.filter(),
+                    )?,
+                    delay_ms,
+                },
+                None => AnimationFrame {
+                    width,
+                    height,
+                    data: buffer.into_raw(),
+                    delay_ms,
+                },
+            };
+            frame_count += 1;
+            if events.send(AnimationEvent::Frame(frame)).is_err() {
+                return Ok(());
+            }
+        }
+        if frame_count == 0 {
+            return Err(anyhow!("No frames in {}", path));
+        }
+
+        played += 1;
+        if plays.is_some_and(|plays| played >= plays) {
+            tracing::debug!(event = "animation_finished", path = %path, played, "Animation played its loops");
+            return Ok(());
+        }
+    }
+}
+
+/// Animated GIF, APNG and WebP. Frames are decoded on a thread and shown for
+/// the delay the file gives each; the last one stays up once the file's loop
+/// count is used up.
+pub struct AnimationHandler {
+    base: BaseMediaHandler,
+    fit: Fit,
+    events: Receiver<AnimationEvent>,
+    resizes: Sender<(u32, u32)>,
+    output_size: (u32, u32),
+    next_frame: Option<AnimationFrame>,
+    /// When the frame on screen is due to be replaced
+    next_at: u64,
+    paused_at: Option<u64>,
+    finished: bool,
+}
+
+impl AnimationHandler {
+    pub fn new(path: &str, shader_path: Option<&str>, fit: Fit) -> Result<Self> {
+        tracing::info!(
+            event = "animation_create",
+            path = %path,
+            shader = shader_path.unwrap_or("default"),
+            ?fit,
+            "Creating animation handler"
+        );
+
+        let base = BaseMediaHandler::new_with_shader(shader_path)?;
+        let (event_tx, events) = mpsc::sync_channel(FRAME_QUEUE);
+        let (resizes, resize_rx) = mpsc::channel();
+        let path = path.to_string();
+        thread::Builder::new()
+            .name("papyrust-animation".to_string())
+            .spawn(move || {
+                if let Err(e) = decode_loop(&path, fit, &event_tx, &resize_rx) {
+                    tracing::error!(event = "animation_error", path = %path, error = %e, "Animation decoding stopped");
+                    let _ = event_tx.send(AnimationEvent::Error(e.to_string()));
+                }
+            })
+            .map_err(|e| anyhow!("Failed to start animation decoder thread: {}", e))?;
+
+        Ok(Self {
+            base,
+            fit,
+            events,
+            resizes,
+            output_size: (0, 0),
+            next_frame: None,
+            next_at: 0,
+            paused_at: None,
+            finished: false,
+        })
+    }
+
+    /// Freezes on the current frame, or resumes with the rest of its delay.
+    pub fn set_paused(&mut self, paused: bool) {
+        let now = utils::get_time_millis();
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(now),
+            (false, Some(paused_at)) => {
+                self.next_at += now.saturating_sub(paused_at);
+                self.paused_at = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn fetch_next_frame(&mut self) -> Result<()> {
+        if self.next_frame.is_some() || self.finished {
+            return Ok(());
+        }
+        match self.events.try_recv() {
+            Ok(AnimationEvent::Frame(frame)) => self.next_frame = Some(frame),
+            Ok(AnimationEvent::Error(e)) => return Err(anyhow!(e)),
+            Err(TryRecvError::Empty) => {}
+            // The decoder thread is done once the loops are played
+            Err(TryRecvError::Disconnected) => self.finished = true,
+        }
+        Ok(())
+    }
+
+    fn upload(&mut self, frame: &AnimationFrame) -> Result<()> {
+        let size = (frame.width, frame.height);
+        if self.base.dimensions != size || self.base.texture.is_none() {
+            self.base.texture = Some(GlTexture::new(size.0, size.1)?);
+            self.base.dimensions = size;
+        }
+        if let Some(texture) = &self.base.texture {
+            texture.update_data(&frame.data, frame.width as usize * 4);
+        }
+        Ok(())
+    }
+}
+
+impl MediaHandler for AnimationHandler {
+    fn get_texture(&self) -> Option<&GlTexture> {
+        self.base.texture.as_ref()
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        self.base.dimensions
+    }
+
+    fn update(&mut self) -> Result<bool> {
+        self.base.has_new_frame = false;
+        // An animation paused before its first frame still shows that frame
+        if self.paused_at.is_some() && self.base.texture.is_some() {
+            return Ok(false);
+        }
+        self.fetch_next_frame()?;
+        let now = utils::get_time_millis();
+        if now < self.next_at {
+            return Ok(false);
+        }
+        let Some(frame) = self.next_frame.take() else {
+            return Ok(false);
+        };
+        self.upload(&frame)?;
+        // A late frame is not shortened, which keeps slow outputs from racing
+        // through the animation
+        self.next_at = now + frame.delay_ms;
+        self.base.has_new_frame = true;
+        Ok(true)
+    }
+
+    fn has_new_frame(&self) -> bool {
+        self.base.has_new_frame
+    }
+
+    fn get_shader_program(&self) -> &crate::gl_utils::GlProgram {
+        &self.base.shader_program
+    }
+
+    fn get_base(&self) -> &BaseMediaHandler {
+        &self.base
+    }
+
+    fn get_base_mut(&mut self) -> &mut BaseMediaHandler {
+        &mut self.base
+    }
+
+    fn get_fit(&self) -> Fit {
+        self.fit
+    }
+
+    fn set_output_size(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.output_size != (width, height) {
+            self.output_size = (width, height);
+            let _ = self.resizes.send((width, height));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A GIF with `images` one-pixel images, and a NETSCAPE2.0 extension
+    /// asking for `repeats` when given.
+    fn gif(images: usize, repeats: Option<u16>) -> Vec<u8> {
+        // 1x1 screen with a two-colour global table
+        let mut data = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        data.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        if let Some(repeats) = repeats {
+            data.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01");
+            data.extend_from_slice(&repeats.to_le_bytes());
+            data.push(0);
+        }
+        for _ in 0..images {
+            // Graphic control extension, then the image and its data
+            data.extend_from_slice(b"\x21\xf9\x04\x00\x0a\x00\x00\x00");
+            data.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00");
+            data.extend_from_slice(b"\x02\x02\x44\x01\x00");
+        }
+        data.push(0x3b);
+        data
+    }
+
+    fn png_chunk(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = (body.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(body);
+        // The CRC is not checked
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    /// A PNG with an `acTL` chunk for `frames` and `plays`, placed before or
+    /// after the image data.
+    fn apng(frames: u32, plays: u32, before_data: bool) -> Vec<u8> {
+        let mut actl = frames.to_be_bytes().to_vec();
+        actl.extend_from_slice(&plays.to_be_bytes());
+        let actl = png_chunk(b"acTL", &actl);
+
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend(png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]));
+        if before_data {
+            data.extend_from_slice(&actl);
+        }
+        data.extend(png_chunk(b"IDAT", &[0; 4]));
+        if !before_data {
+            data.extend_from_slice(&actl);
+        }
+        data.extend(png_chunk(b"IEND", &[]));
+        data
+    }
+
+    /// A WebP with an extended header, and an `ANIM` chunk with `loops` when
+    /// given.
+    fn webp(loops: Option<u16>) -> Vec<u8> {
+        let mut chunks = b"VP8X\x0a\x00\x00\x00".to_vec();
+        chunks.extend_from_slice(&[0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        if let Some(loops) = loops {
+            chunks.extend_from_slice(b"ANIM\x06\x00\x00\x00\xff\xff\xff\xff");
+            chunks.extend_from_slice(&loops.to_le_bytes());
+        }
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend(chunks);
+        data
+    }
+
+    #[test]
+    fn sniffs_formats() {
+        assert_eq!(Format::sniff(&gif(1, None)), Some(Format::Gif));
+        assert_eq!(Format::sniff(b"GIF87a"), Some(Format::Gif));
+        assert_eq!(Format::sniff(&apng(2, 0, true)), Some(Format::Png));
+        assert_eq!(Format::sniff(&webp(None)), Some(Format::WebP));
+        assert_eq!(Format::sniff(b"\xff\xd8\xff\xe0"), None);
+        assert_eq!(Format::sniff(b"RIFF"), None);
+    }
+
+    #[test]
+    fn counts_gif_images() {
+        assert_eq!(gif_is_animated(Cursor::new(gif(1, None))), Some(false));
+        assert_eq!(gif_is_animated(Cursor::new(gif(2, None))), Some(true));
+        assert_eq!(gif_is_animated(Cursor::new(gif(3, Some(0)))), Some(true));
+    }
+
+    #[test]
+    fn rejects_truncated_gifs() {
+        let mut data = gif(1, None);
+        data.truncate(data.len() - 4);
+        assert_eq!(gif_is_animated(Cursor::new(data)), None);
+        assert_eq!(gif_is_animated(Cursor::new(b"GIF89a".to_vec())), None);
+    }
+
+    #[test]
+    fn reads_gif_loop_counts() {
+        assert_eq!(play_count(&gif(2, None), Format::Gif), Some(1));
+        assert_eq!(play_count(&gif(2, Some(0)), Format::Gif), None);
+        assert_eq!(play_count(&gif(2, Some(2)), Format::Gif), Some(3));
+    }
+
+    #[test]
+    fn reads_apng_control_chunks() {
+        assert!(png_is_animated(&apng(2, 0, true)));
+        assert!(!png_is_animated(&apng(1, 0, true)));
+        // acTL only counts before the image data
+        assert!(!png_is_animated(&apng(2, 0, false)));
+
+        assert_eq!(play_count(&apng(2, 0, true), Format::Png), None);
+        assert_eq!(play_count(&apng(2, 4, true), Format::Png), Some(4));
+    }
+
+    #[test]
+    fn reads_webp_animation_chunks() {
+        let animated = webp(Some(0));
+        assert!(riff_chunks(&animated).any(|(kind, _)| kind == b"ANIM"));
+        assert!(!riff_chunks(&webp(None)).any(|(kind, _)| kind == b"ANIM"));
+
+        assert_eq!(play_count(&animated, Format::WebP), None);
+        assert_eq!(play_count(&webp(Some(5)), Format::WebP), Some(5));
+    }
+}
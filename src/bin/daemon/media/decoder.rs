@@ -0,0 +1,452 @@
+use crate::media::hwaccel;
+use crate::media::{Fit, scaling};
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::thread;
+
+/// Converted frames the decoder thread may get ahead of presentation. Each
+/// one is a full RGBA frame at the scaled size, so this stays small.
+const FRAME_QUEUE: usize = 3;
+
+/// Stream properties, sent once the file is open.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamInfo {
+    pub fps: f64,
+}
+
+/// A frame converted for upload.
+pub struct DecodedFrame {
+    pub frame: ffmpeg::frame::Video,
+    /// Presentation time in seconds from the start of the loop, when the
+    /// frame has a PTS
+    pub time: Option<f64>,
+    /// Times the video had looped when the frame was decoded
+    pub loop_count: u64,
+}
+
+enum DecoderEvent {
+    Info(StreamInfo),
+    Frame(DecodedFrame),
+    Error(String),
+}
+
+enum DecoderCommand {
+    /// Size the media is drawn at, which frames are resampled for
+    Resize(u32, u32),
+}
+
+/// Demuxes, decodes and converts a video on its own thread, looping at the
+/// end. Frames wait in a bounded queue for the render thread to present them;
+/// the thread blocks while the queue is full and exits once the decoder is
+/// dropped.
+pub struct VideoDecoder {
+    events: Receiver<DecoderEvent>,
+    commands: Sender<DecoderCommand>,
+    info: Option<StreamInfo>,
+}
+
+impl VideoDecoder {
+    /// Starts decoding `path`. NV12 frames are passed on unconverted when
+    /// `nv12_passthrough` is set and no resampling is needed.
+    pub fn spawn(path: &str, fit: Fit, nv12_passthrough: bool) -> Result<Self> {
+        let (event_tx, events) = mpsc::sync_channel(FRAME_QUEUE);
+        let (commands, command_rx) = mpsc::channel();
+        let path = path.to_string();
+        thread::Builder::new()
+            .name("papyrust-video".to_string())
+            .spawn(move || {
+                if let Err(e) = decode_loop(&path, fit, nv12_passthrough, &event_tx, &command_rx) {
+                    tracing::error!(event = "video_decoder_error", path = %path, error = %e, "Video decoding stopped");
+                    let _ = event_tx.send(DecoderEvent::Error(e.to_string()));
+                }
+                tracing::debug!(event = "video_decoder_exit", path = %path, "Video decoder thread finished");
+            })
+            .map_err(|e| anyhow!("Failed to start video decoder thread: {}", e))?;
+        Ok(Self {
+            events,
+            commands,
+            info: None,
+        })
+    }
+
+    /// Stream properties, once the decoder thread has opened the file.
+    pub fn info(&self) -> Option<StreamInfo> {
+        self.info
+    }
+
+    pub fn resize(&self, width: u32, height: u32) {
+        let _ = self.commands.send(DecoderCommand::Resize(width, height));
+    }
+
+    /// The next decoded frame, without waiting for one. Fails once the
+    /// decoder thread has given up on the file.
+    pub fn try_next(&mut self) -> Result<Option<DecodedFrame>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(DecoderEvent::Info(info)) => self.info = Some(info),
+                Ok(DecoderEvent::Frame(frame)) => return Ok(Some(frame)),
+                Ok(DecoderEvent::Error(e)) => return Err(anyhow!(e)),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(anyhow!("Video decoder stopped")),
+            }
+        }
+    }
+}
+
+fn decode_loop(
+    path: &str,
+    fit: Fit,
+    nv12_passthrough: bool,
+    events: &SyncSender<DecoderEvent>,
+    commands: &Receiver<DecoderCommand>,
+) -> Result<()> {
+    let mut stream = DecoderStream::open(path, fit, nv12_passthrough)?;
+    if events.send(DecoderEvent::Info(stream.info())).is_err() {
+        return Ok(());
+    }
+
+    let mut loop_count = 0;
+    let mut loop_frames = 0u64;
+    loop {
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                DecoderCommand::Resize(width, height) => stream.resize(width, height),
+            }
+        }
+
+        match stream.next_frame()? {
+            Some((frame, time)) => {
+                loop_frames += 1;
+                let frame = DecodedFrame {
+                    frame,
+                    time,
+                    loop_count,
+                };
+                if events.send(DecoderEvent::Frame(frame)).is_err() {
+                    return Ok(());
+                }
+            }
+            None if loop_frames == 0 => return Err(anyhow!("No decodable frames in {}", path)),
+            None => {
+                loop_count += 1;
+                loop_frames = 0;
+                stream.restart()?;
+            }
+        }
+    }
+}
+
+/// Decoder state owned by the decoder thread.
+struct DecoderStream {
+    input_ctx: ffmpeg::format::context::Input,
+    decoder: ffmpeg::decoder::Video,
+    stream_index: usize,
+    video_path: String,
+    time_base: f64,
+    fps: f64,
+    first_pts: Option<i64>,
+    reached_eof: bool,
+    scaler: Option<ffmpeg::software::scaling::Context>,
+    /// Pixel format the scaler was built for; it is rebuilt when frames arrive
+    /// in another one, as hardware decoders only settle on theirs once decoding
+    scaler_format: ffmpeg::format::Pixel,
+    nv12_passthrough: bool,
+    fit: Fit,
+    source_size: (u32, u32),
+    scaled_size: (u32, u32),
+}
+
+impl DecoderStream {
+    fn open(path: &str, fit: Fit, nv12_passthrough: bool) -> Result<Self> {
+        ffmpeg::init().map_err(|e| anyhow!("Failed to initialize FFmpeg: {}", e))?;
+        let input_ctx = ffmpeg::format::input(&Path::new(path))
+            .map_err(|e| anyhow!("Failed to open video file {}: {}", path, e))?;
+
+        let stream = input_ctx
+            .streams()
+            .best(ffmpeg::media::Type::Video)
+            .ok_or_else(|| anyhow!("No video stream found in {}", path))?;
+        let stream_index = stream.index();
+
+        let (decoder, hwaccel) = Self::open_decoder(&stream)?;
+
+        let width = decoder.width();
+        let height = decoder.height();
+
+        let time_base = {
+            let tb = stream.time_base();
+            tb.0 as f64 / tb.1 as f64
+        };
+        let video_duration = {
+            let duration = stream.duration();
+            if duration != ffmpeg::ffi::AV_NOPTS_VALUE {
+                duration as f64 * time_base
+            } else {
+                let format_duration = input_ctx.duration();
+                if format_duration != ffmpeg::ffi::AV_NOPTS_VALUE {
+                    format_duration as f64 / ffmpeg::ffi::AV_TIME_BASE as f64
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        let fps = Self::detect_fps(&stream, time_base);
+
+        tracing::info!(
+            event = "video_info",
+            width,
+            height,
+            fps,
+            duration = video_duration,
+            time_base,
+            "Video stream initialized"
+        );
+        tracing::info!(event = "video_decoder", path = %path, hwaccel, "Opened video decoder");
+
+        Ok(Self {
+            input_ctx,
+            decoder,
+            stream_index,
+            video_path: path.to_string(),
+            time_base,
+            fps,
+            first_pts: None,
+            reached_eof: false,
+            scaler: None,
+            scaler_format: ffmpeg::format::Pixel::None,
+            nv12_passthrough: nv12_passthrough && hwaccel,
+            fit,
+            source_size: (width, height),
+            scaled_size: (width, height),
+        })
+    }
+
+    fn info(&self) -> StreamInfo {
+        StreamInfo { fps: self.fps }
+    }
+
+    /// Opens a decoder for `stream`, on the hardware decoding device when one
+    /// is enabled. Returns whether the device was attached.
+    fn open_decoder(stream: &ffmpeg::format::stream::Stream) -> Result<(ffmpeg::decoder::Video, bool)> {
+        let mut context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+            .map_err(|e| anyhow!("Failed to create codec context: {}", e))?;
+        let hwaccel = hwaccel::attach(&mut context);
+        let decoder = context
+            .decoder()
+            .video()
+            .map_err(|e| anyhow!("Failed to create video decoder: {}", e))?;
+        Ok((decoder, hwaccel))
+    }
+
+    fn detect_fps(stream: &ffmpeg::format::stream::Stream, time_base: f64) -> f64 {
+        let rate = stream.rate();
+        let avg_rate = stream.avg_frame_rate();
+
+        let fps_from_rate = if rate.1 > 0 {
+            rate.0 as f64 / rate.1 as f64
+        } else {
+            0.0
+        };
+
+        let fps_from_avg = if avg_rate.1 > 0 {
+            avg_rate.0 as f64 / avg_rate.1 as f64
+        } else {
+            0.0
+        };
+
+        let detected_fps = if (1.0..=120.0).contains(&fps_from_rate) {
+            fps_from_rate
+        } else if (1.0..=120.0).contains(&fps_from_avg) {
+            fps_from_avg
+        } else if time_base > 0.0 {
+            let tb_fps = 1.0 / time_base;
+            if (1.0..=120.0).contains(&tb_fps) {
+                tb_fps
+            } else {
+                25.0
+            }
+        } else {
+            25.0
+        };
+
+        tracing::debug!(
+            event = "fps_detection",
+            rate_fps = fps_from_rate,
+            avg_fps = fps_from_avg,
+            time_base_fps = if time_base > 0.0 {
+                1.0 / time_base
+            } else {
+                0.0
+            },
+            detected_fps,
+            "FPS detection results"
+        );
+
+        detected_fps
+    }
+
+    /// Converts to RGBA and, when the fit asks for it, resamples to `target`
+    /// with the fit's filter. Returns `None` when frames can be uploaded as is.
+    fn create_scaler(
+        format: ffmpeg::format::Pixel,
+        source: (u32, u32),
+        target: (u32, u32),
+        fit: &Fit,
+    ) -> Result<Option<ffmpeg::software::scaling::Context>> {
+        if format == ffmpeg::format::Pixel::RGBA && source == target {
+            return Ok(None);
+        }
+        let context = ffmpeg::software::scaling::Context::get(
+            format,
+            source.0,
+            source.1,
+            ffmpeg::format::Pixel::RGBA,
+            target.0,
+            target.1,
+            scaling::sws_flags(fit.filter()),
+        )
+        .map_err(|e| anyhow!("Failed to create scaler: {}", e))?;
+        Ok(Some(context))
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        let (source_width, source_height) = self.source_size;
+        let target = self
+            .fit
+            .scaled_size(source_width, source_height, width, height)
+            .unwrap_or(self.source_size);
+        if target == self.scaled_size {
+            return;
+        }
+
+        tracing::debug!(
+            event = "video_rescale",
+            source_width,
+            source_height,
+            target_width = target.0,
+            target_height = target.1,
+            filter = ?self.fit.filter(),
+            "Rebuilding video scaler for output size"
+        );
+        // Rebuilt for the new size with the next frame
+        self.scaler = None;
+        self.scaler_format = ffmpeg::format::Pixel::None;
+        self.scaled_size = target;
+    }
+
+    fn pts_to_time(&self, pts: i64) -> f64 {
+        let adjusted_pts = if let Some(first) = self.first_pts {
+            pts - first
+        } else {
+            pts
+        };
+        adjusted_pts as f64 * self.time_base
+    }
+
+    /// Decodes and converts the next frame, with its time from the start of
+    /// the loop. `None` at the end of the file.
+    fn next_frame(&mut self) -> Result<Option<(ffmpeg::frame::Video, Option<f64>)>> {
+        if self.reached_eof {
+            return Ok(None);
+        }
+
+        for (stream, packet) in self.input_ctx.packets() {
+            if stream.index() != self.stream_index {
+                continue;
+            }
+
+            match self.decoder.send_packet(&packet) {
+                Ok(_) => {
+                    let mut decoded = ffmpeg::frame::Video::empty();
+                    if self.decoder.receive_frame(&mut decoded).is_ok() {
+                        let pts = decoded.pts();
+
+                        if let Some(pts_val) = pts {
+                            if self.first_pts.is_none() {
+                                self.first_pts = Some(pts_val);
+                                tracing::debug!(
+                                    event = "video_first_pts",
+                                    pts = pts_val,
+                                    time = self.pts_to_time(pts_val),
+                                    "First frame PTS recorded"
+                                );
+                            }
+                        }
+
+                        let time = pts.map(|pts| self.pts_to_time(pts));
+                        let frame = self.convert_frame(decoded)?;
+                        return Ok(Some((frame, time)));
+                    }
+                }
+                Err(ffmpeg::Error::Eof) => {
+                    self.reached_eof = true;
+                    return Ok(None);
+                }
+                Err(_) => {
+                    continue;
+                }
+            }
+        }
+
+        self.reached_eof = true;
+        Ok(None)
+    }
+
+    fn convert_frame(&mut self, frame: ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
+        use ffmpeg::format::Pixel;
+
+        // Hardware frames are downloaded right away so the decoder's small
+        // surface pool is never held by queued frames
+        let frame = if frame.format() == Pixel::VAAPI {
+            hwaccel::download(&frame)?
+        } else {
+            frame
+        };
+        if self.nv12_passthrough && frame.format() == Pixel::NV12 && self.scaled_size == self.source_size {
+            return Ok(frame);
+        }
+
+        if frame.format() != self.scaler_format {
+            self.scaler = Self::create_scaler(frame.format(), self.source_size, self.scaled_size, &self.fit)?;
+            self.scaler_format = frame.format();
+        }
+        let Some(ref mut scaler) = self.scaler else {
+            return Ok(frame);
+        };
+
+        let mut rgba_frame = ffmpeg::frame::Video::empty();
+        scaler
+            .run(&frame, &mut rgba_frame)
+            .map_err(|e| anyhow!("Scaling failed: {}", e))?;
+        rgba_frame.set_pts(frame.pts());
+
+        if self.fit.filter().sharpens() && self.scaled_size != self.source_size {
+            let (width, height) = (rgba_frame.width() as usize, rgba_frame.height() as usize);
+            let stride = rgba_frame.stride(0);
+            scaling::sharpen_rgba(rgba_frame.data_mut(0), width, height, stride);
+        }
+        Ok(rgba_frame)
+    }
+
+    fn restart(&mut self) -> Result<()> {
+        self.first_pts = None;
+        self.reached_eof = false;
+
+        self.input_ctx = ffmpeg::format::input(&Path::new(&self.video_path))
+            .map_err(|e| anyhow!("Failed to re-open video {}: {}", self.video_path, e))?;
+
+        let stream = self
+            .input_ctx
+            .streams()
+            .best(ffmpeg::media::Type::Video)
+            .ok_or_else(|| anyhow!("No video stream on restart"))?;
+
+        self.stream_index = stream.index();
+        self.decoder = Self::open_decoder(&stream)?.0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use crate::gl_bindings as gl;
+use crate::gl_utils::{GlProgram, GlTexture};
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::ffi::{AVBufferRef, AVCodecContext, AVPixelFormat};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Hardware video decoding backend. Off by default; when the device cannot be
+/// opened or the codec is not supported by it, videos decode in software as
+/// before.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HwDecode {
+    #[default]
+    None,
+    Vaapi,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Applies to videos opened from now on.
+pub fn set_mode(mode: HwDecode) {
+    ENABLED.store(mode == HwDecode::Vaapi, Ordering::Relaxed);
+}
+
+/// Whether videos opened from now on try to decode on the VAAPI device.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+struct HwDevice(*mut AVBufferRef);
+
+// The device context is reference counted by FFmpeg and only read here
+unsafe impl Send for HwDevice {}
+unsafe impl Sync for HwDevice {}
+
+/// The VAAPI device shared by every decoder, opened on first use. A failure is
+/// remembered so it is logged once rather than for every video.
+fn device() -> Option<&'static HwDevice> {
+    static DEVICE: OnceLock<Option<HwDevice>> = OnceLock::new();
+    DEVICE
+        .get_or_init(|| {
+            let mut device = std::ptr::null_mut();
+            let ret = unsafe {
+                ffmpeg::ffi::av_hwdevice_ctx_create(
+                    &mut device,
+                    ffmpeg::ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                    0,
+                )
+            };
+            if ret < 0 || device.is_null() {
+                tracing::warn!(
+                    event = "hwaccel_unavailable",
+                    error = ret,
+                    "No VAAPI device, decoding video in software"
+                );
+                return None;
+            }
+            tracing::info!(event = "hwaccel_device", "Opened VAAPI device");
+            Some(HwDevice(device))
+        })
+        .as_ref()
+}
+
+unsafe extern "C" fn get_format(
+    context: *mut AVCodecContext,
+    formats: *const AVPixelFormat,
+) -> AVPixelFormat {
+    let mut format = formats;
+    unsafe {
+        while *format != AVPixelFormat::AV_PIX_FMT_NONE {
+            if *format == AVPixelFormat::AV_PIX_FMT_VAAPI {
+                return *format;
+            }
+            format = format.add(1);
+        }
+        // The device cannot decode this codec or profile
+        ffmpeg::ffi::avcodec_default_get_format(context, formats)
+    }
+}
+
+/// Sets up `context` to decode on the VAAPI device when hardware decoding is
+/// enabled and available. Must be called before the decoder is opened.
+pub fn attach(context: &mut ffmpeg::codec::context::Context) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    let Some(device) = device() else {
+        return false;
+    };
+    unsafe {
+        let context = context.as_mut_ptr();
+        (*context).hw_device_ctx = ffmpeg::ffi::av_buffer_ref(device.0);
+        (*context).get_format = Some(get_format);
+    }
+    true
+}
+
+/// Copies a frame decoded into GPU memory back into system memory, usually
+/// as NV12.
+pub fn download(frame: &ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
+    let mut software = ffmpeg::frame::Video::empty();
+    unsafe {
+        let ret = ffmpeg::ffi::av_hwframe_transfer_data(software.as_mut_ptr(), frame.as_ptr(), 0);
+        if ret < 0 {
+            return Err(anyhow!("Failed to download hardware frame: error {}", ret));
+        }
+        ffmpeg::ffi::av_frame_copy_props(software.as_mut_ptr(), frame.as_ptr());
+    }
+    Ok(software)
+}
+
+const NV12_VERTEX_SHADER: &str = r#"#version 300 es
+out vec2 v_uv;
+void main() {
+    // One triangle covering the target, no vertex buffer needed
+    vec2 position = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    v_uv = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const NV12_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision mediump float;
+in vec2 v_uv;
+out vec4 frag_color;
+uniform sampler2D u_luma;
+uniform sampler2D u_chroma;
+uniform mat3 u_matrix;
+uniform vec2 u_offset;
+uniform vec2 u_scale;
+void main() {
+    float y = (texture(u_luma, v_uv).r - u_offset.x) * u_scale.x;
+    vec2 uv = (texture(u_chroma, v_uv).rg - u_offset.y) * u_scale.y;
+    frag_color = vec4(clamp(u_matrix * vec3(y, uv), 0.0, 1.0), 1.0);
+}
+"#;
+
+/// YUV to RGB matrices, column major with Y, U and V columns.
+const BT601: [f32; 9] = [1.0, 1.0, 1.0, 0.0, -0.344136, 1.772, 1.402, -0.714136, 0.0];
+const BT709: [f32; 9] = [1.0, 1.0, 1.0, 0.0, -0.1873, 1.8556, 1.5748, -0.4681, 0.0];
+const BT2020: [f32; 9] = [1.0, 1.0, 1.0, 0.0, -0.164553, 1.8814, 1.4746, -0.571353, 0.0];
+
+/// Converts NV12 frames to RGBA on the GPU, replacing the swscale conversion
+/// for hardware decoded video. Both planes are uploaded as they are and drawn
+/// into the media texture.
+pub struct Nv12Converter {
+    program: GlProgram,
+    luma: Option<GlTexture>,
+    chroma: Option<GlTexture>,
+    framebuffer: u32,
+    vao: u32,
+}
+
+impl Nv12Converter {
+    pub fn new() -> Result<Self> {
+        let program = GlProgram::new(NV12_VERTEX_SHADER, NV12_FRAGMENT_SHADER)?;
+        let mut framebuffer = 0;
+        let mut vao = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        Ok(Self {
+            program,
+            luma: None,
+            chroma: None,
+            framebuffer,
+            vao,
+        })
+    }
+
+    fn matrix(frame: &ffmpeg::frame::Video) -> &'static [f32; 9] {
+        use ffmpeg::color::Space;
+        match frame.color_space() {
+            Space::BT709 => &BT709,
+            Space::BT470BG | Space::SMPTE170M => &BT601,
+            Space::BT2020NCL | Space::BT2020CL => &BT2020,
+            // Untagged video: SD content is usually BT.601, HD BT.709
+            _ if frame.height() <= 576 => &BT601,
+            _ => &BT709,
+        }
+    }
+
+    /// Draws `frame` into `target`, which must match the frame's size.
+    pub fn convert(&mut self, frame: &ffmpeg::frame::Video, target: &GlTexture) -> Result<()> {
+        let (width, height) = (frame.width(), frame.height());
+        let chroma_size = (width.div_ceil(2), height.div_ceil(2));
+        if self.luma.as_ref().is_none_or(|t| (t.width, t.height) != (width, height)) {
+            self.luma = Some(GlTexture::with_format(width, height, gl::R8, gl::RED)?);
+            self.chroma = Some(GlTexture::with_format(chroma_size.0, chroma_size.1, gl::RG8, gl::RG)?);
+        }
+        let (Some(luma), Some(chroma)) = (&self.luma, &self.chroma) else {
+            return Ok(());
+        };
+        luma.update_plane(frame.data(0), frame.stride(0), gl::RED, 1);
+        chroma.update_plane(frame.data(1), frame.stride(1), gl::RG, 2);
+
+        let (offset, scale) = match frame.color_range() {
+            ffmpeg::color::Range::JPEG => ([0.0, 128.0 / 255.0], [1.0, 1.0]),
+            _ => ([16.0 / 255.0, 128.0 / 255.0], [255.0 / 219.0, 255.0 / 224.0]),
+        };
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                target.id,
+                0,
+            );
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                return Err(anyhow!("Video framebuffer incomplete: 0x{:x}", status));
+            }
+            gl::Viewport(0, 0, width as i32, height as i32);
+
+            self.program.use_program();
+            gl::ActiveTexture(gl::TEXTURE0);
+            luma.bind();
+            gl::Uniform1i(self.program.get_uniform_location("u_luma"), 0);
+            gl::ActiveTexture(gl::TEXTURE1);
+            chroma.bind();
+            gl::Uniform1i(self.program.get_uniform_location("u_chroma"), 1);
+            gl::UniformMatrix3fv(
+                self.program.get_uniform_location("u_matrix"),
+                1,
+                gl::FALSE,
+                Self::matrix(frame).as_ptr(),
+            );
+            gl::Uniform2f(self.program.get_uniform_location("u_offset"), offset[0], offset[1]);
+            gl::Uniform2f(self.program.get_uniform_location("u_scale"), scale[0], scale[1]);
+
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Nv12Converter {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
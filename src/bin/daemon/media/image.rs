@@ -1,13 +1,19 @@
 use anyhow::{Result, anyhow};
 use image as img_crate;
+use papyrust::wallpaper_engine::tex;
 use crate::gl_utils::GlTexture;
-use crate::media::{MediaHandler, BaseMediaHandler};
+use crate::media::{MediaHandler, BaseMediaHandler, Fit, ScalingMode, scaling};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
 pub struct ImageHandler {
     base: BaseMediaHandler,
     loading_state: Arc<Mutex<LoadingState>>,
+    fit: Fit,
+    output_size: (u32, u32),
+    /// Decoded pixels kept around to resample again when the output size
+    /// changes. Only held when a resampling filter is in use.
+    source: Option<(u32, u32, Vec<u8>)>,
 }
 
 #[derive(Debug)]
@@ -23,11 +29,12 @@ enum LoadingState {
 }
 
 impl ImageHandler {
-    pub fn new(path: &str, shader_path: Option<&str>) -> Result<Self> {
+    pub fn new(path: &str, shader_path: Option<&str>, fit: Fit) -> Result<Self> {
         tracing::info!(
             event = "image_create",
             path = %path,
             shader = shader_path.unwrap_or("default"),
+            ?fit,
             "Creating image handler"
         );
 
@@ -68,35 +75,78 @@ impl ImageHandler {
         Ok(Self { 
             base,
             loading_state,
+            fit,
+            output_size: (0, 0),
+            source: None,
         })
     }
 
     fn load_image_data(path: &str) -> Result<(u32, u32, Vec<u8>)> {
         tracing::info!(event = "texture_load", path = %path, "Loading image data");
 
-        let img = img_crate::open(path)
-            .map_err(|e| anyhow!("Failed to load image {}: {}", path, e))?;
-        let rgba = img.to_rgba8();
-        let (width, height) = (img.width(), img.height());
+        let rgba = if is_tex(path) {
+            let bytes = std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+            tex::decode(&bytes).map_err(|e| anyhow!("Failed to load texture {}: {}", path, e))?
+        } else {
+            img_crate::open(path)
+                .map_err(|e| anyhow!("Failed to load image {}: {}", path, e))?
+                .to_rgba8()
+        };
+        let (width, height) = rgba.dimensions();
 
         tracing::debug!(event = "image_info", width, height, "Image decoded");
 
         Ok((width, height, rgba.into_raw()))
     }
 
+    /// Creates the texture, resampled on the CPU when the fit has a filter and
+    /// the output size is known, otherwise at native size with mipmaps.
+    fn upload(&mut self, width: u32, height: u32, data: &[u8]) -> Result<()> {
+        let (output_width, output_height) = self.output_size;
+        let texture = match self.fit.scaled_size(width, height, output_width, output_height) {
+            Some((target_width, target_height)) => {
+                let pixels = scaling::resize_rgba(
+                    data,
+                    width,
+                    height,
+                    target_width,
+                    target_height,
+                    self.fit.filter(),
+                )?;
+                tracing::debug!(
+                    event = "image_resampled",
+                    width,
+                    height,
+                    target_width,
+                    target_height,
+                    filter = ?self.fit.filter(),
+                    "Resampled image for output"
+                );
+                GlTexture::from_rgba_data(target_width, target_height, &pixels, false)?
+            }
+            None => GlTexture::from_rgba_data(width, height, data, true)?,
+        };
+
+        self.base.dimensions = (texture.width, texture.height);
+        self.base.texture = Some(texture);
+        self.base.has_new_frame = true;
+        Ok(())
+    }
+
     fn check_loading_state(&mut self) -> bool {
-        if let Ok(mut state) = self.loading_state.lock() {
+        let loading_state = self.loading_state.clone();
+        if let Ok(mut state) = loading_state.lock() {
             match std::mem::replace(&mut *state, LoadingState::Loading) {
                 LoadingState::Loading => {
                     *state = LoadingState::Loading;
                     false
                 }
                 LoadingState::DataReady { width, height, data } => {
-                    match GlTexture::from_rgba_data(width, height, &data, true) {
-                        Ok(texture) => {
-                            self.base.dimensions = (texture.width, texture.height);
-                            self.base.texture = Some(texture);
-                            self.base.has_new_frame = true;
+                    match self.upload(width, height, &data) {
+                        Ok(()) => {
+                            if self.fit.filter() != ScalingMode::None {
+                                self.source = Some((width, height, data));
+                            }
                             *state = LoadingState::TextureCreated;
                             
                             tracing::debug!(
@@ -158,4 +208,44 @@ impl MediaHandler for ImageHandler {
     fn get_shader_program(&self) -> &crate::gl_utils::GlProgram {
         &self.base.shader_program
     }
+
+    fn get_base(&self) -> &BaseMediaHandler {
+        &self.base
+    }
+
+    fn get_base_mut(&mut self) -> &mut BaseMediaHandler {
+        &mut self.base
+    }
+
+    fn get_fit(&self) -> Fit {
+        self.fit
+    }
+
+    fn load_error(&self) -> Option<String> {
+        match &*self.loading_state.lock().ok()? {
+            LoadingState::Error(e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+
+    fn set_output_size(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.output_size == (width, height) {
+            return Ok(());
+        }
+        self.output_size = (width, height);
+
+        if let Some((source_width, source_height, data)) = self.source.take() {
+            let result = self.upload(source_width, source_height, &data);
+            self.source = Some((source_width, source_height, data));
+            result?;
+        }
+        Ok(())
+    }
+}
+
+/// Wallpaper Engine textures, decoded by the shared `.tex` reader.
+pub fn is_tex(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tex"))
 }
@@ -0,0 +1,91 @@
+use crate::gl_utils::GlTexture;
+use crate::media::{BaseMediaHandler, Fit, MediaHandler, MediaType};
+use anyhow::Result;
+use std::mem::ManuallyDrop;
+
+/// Shows media decoded by the shared media cache with this surface's shader
+/// and fit. The texture belongs to the cache and is looked up again before
+/// every draw, since it is replaced when the media is resampled.
+pub struct MirrorHandler {
+    base: BaseMediaHandler,
+    media_type: MediaType,
+    fit: Fit,
+    source: Option<ManuallyDrop<GlTexture>>,
+}
+
+impl MirrorHandler {
+    pub fn new(media_type: MediaType) -> Result<Self> {
+        let (shader_path, fit) = match &media_type {
+            MediaType::Image { shader, fit, .. }
+            | MediaType::Video { shader, fit, .. }
+            | MediaType::Animation { shader, fit, .. } => (shader.as_deref(), *fit),
+            MediaType::Shader(_) => (None, Fit::default()),
+        };
+        tracing::info!(
+            event = "mirror_create",
+            ?media_type,
+            "Creating mirror handler"
+        );
+
+        let base = BaseMediaHandler::new_with_shader(shader_path)?;
+        Ok(Self {
+            base,
+            media_type,
+            fit,
+            source: None,
+        })
+    }
+
+    /// Media whose decoded frames this mirror shows.
+    pub fn media_type(&self) -> &MediaType {
+        &self.media_type
+    }
+
+    pub fn set_source(&mut self, id: u32, width: u32, height: u32) {
+        self.base.has_new_frame = self
+            .source
+            .as_ref()
+            .is_none_or(|t| (t.id, t.width, t.height) != (id, width, height));
+        self.source = Some(GlTexture::borrowed(id, width, height));
+        self.base.dimensions = (width, height);
+    }
+
+    pub fn clear_source(&mut self) {
+        self.source = None;
+        self.base.dimensions = (0, 0);
+    }
+}
+
+impl MediaHandler for MirrorHandler {
+    fn get_texture(&self) -> Option<&GlTexture> {
+        self.source.as_deref()
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        self.base.dimensions
+    }
+
+    fn update(&mut self) -> Result<bool> {
+        Ok(std::mem::take(&mut self.base.has_new_frame))
+    }
+
+    fn has_new_frame(&self) -> bool {
+        self.base.has_new_frame
+    }
+
+    fn get_shader_program(&self) -> &crate::gl_utils::GlProgram {
+        &self.base.shader_program
+    }
+
+    fn get_base(&self) -> &BaseMediaHandler {
+        &self.base
+    }
+
+    fn get_base_mut(&mut self) -> &mut BaseMediaHandler {
+        &mut self.base
+    }
+
+    fn get_fit(&self) -> Fit {
+        self.fit
+    }
+}
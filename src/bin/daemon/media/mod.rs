@@ -1,18 +1,21 @@
+use crate::events::{self, Event};
 use crate::gl_utils::{GlProgram, GlTexture};
 use crate::utils;
 use anyhow::Result;
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum MediaType {
-    Shader(String),
-    Image {
-        path: String,
-        shader: Option<String>,
-    },
-    Video {
-        path: String,
-        shader: Option<String>,
-    },
+use passes::ShaderPasses;
+use crate::watcher::FileWatcher;
+use std::path::{Path, PathBuf};
+
+pub use papyrust::protocol::MediaType;
+
+/// Treats an image whose file holds several frames as an animation.
+pub fn with_animation(media_type: MediaType) -> MediaType {
+    match media_type {
+        MediaType::Image { path, shader, fit } if animation::is_animated(&path) => {
+            MediaType::Animation { path, shader, fit }
+        }
+        media_type => media_type,
+    }
 }
 
 pub trait MediaHandler {
@@ -21,52 +24,131 @@ pub trait MediaHandler {
     fn update(&mut self) -> Result<bool>;
     fn has_new_frame(&self) -> bool;
     fn get_shader_program(&self) -> &GlProgram;
+    fn get_base(&self) -> &BaseMediaHandler;
+    fn get_base_mut(&mut self) -> &mut BaseMediaHandler;
+
+    fn get_fit(&self) -> Fit {
+        Fit::default()
+    }
+
+    /// Lets handlers that resample on the CPU track the size they are drawn at.
+    fn set_output_size(&mut self, _width: u32, _height: u32) -> Result<()> {
+        Ok(())
+    }
+
+    /// Why media loading off the render thread failed, once it has.
+    fn load_error(&self) -> Option<String> {
+        None
+    }
 }
 
 pub struct BaseMediaHandler {
     pub shader_program: GlProgram,
+    pub passes: ShaderPasses,
     pub texture: Option<GlTexture>,
     pub dimensions: (u32, u32),
     pub has_new_frame: bool,
+    shader_path: Option<String>,
+    shader_watcher: Option<FileWatcher>,
+    /// Info log of the last failed hot reload; the previous program stays active
+    pub shader_error: Option<String>,
 }
 
 impl BaseMediaHandler {
     pub fn new_with_shader(shader_path: Option<&str>) -> Result<Self> {
-        let shader_program = if let Some(path) = shader_path {
-            if path == "default" {
-                Self::create_default_shader()?
-            } else {
-                Self::create_custom_shader(path)?
-            }
-        } else {
-            Self::create_default_shader()?
+        let shader_path = shader_path.filter(|&path| path != "default");
+        let (shader_program, passes) = match shader_path {
+            Some(path) => Self::create_custom_shader(path)?,
+            None => (Self::create_default_shader()?, ShaderPasses::default()),
         };
 
-        Ok(Self {
+        let mut base = Self {
             shader_program,
+            passes,
             texture: None,
             dimensions: (0, 0),
             has_new_frame: false,
-        })
+            shader_path: shader_path.map(str::to_string),
+            shader_watcher: None,
+            shader_error: None,
+        };
+        base.watch_shader();
+        Ok(base)
     }
 
-    pub fn new_pure_shader(shader_path: Option<&str>) -> Result<Self> {
-        let shader_program = if let Some(path) = shader_path {
-            if path == "default" {
-                Self::create_default_shader()?
-            } else {
-                Self::create_pure_shader(path)?
+    fn watch_shader(&mut self) {
+        let Some(path) = &self.shader_path else {
+            return;
+        };
+        let mut paths = vec![PathBuf::from(path)];
+        paths.extend(self.passes.source_paths().map(Path::to_path_buf));
+
+        self.shader_watcher = match FileWatcher::new(&paths) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                tracing::warn!(
+                    event = "shader_watch_error",
+                    path = %path,
+                    error = %e,
+                    "Cannot watch shader for changes, hot reload disabled"
+                );
+                None
             }
-        } else {
-            Self::create_default_shader()?
         };
+    }
 
-        Ok(Self {
-            shader_program,
-            texture: None,
-            dimensions: (0, 0),
-            has_new_frame: false,
-        })
+    /// Recompiles the shader and its buffer passes when one of their files
+    /// changed on disk. A shader that fails to compile leaves the current
+    /// program in place and records the error. Returns whether anything was
+    /// reloaded.
+    pub fn reload_shader_if_changed(&mut self) -> bool {
+        let Some(watcher) = self.shader_watcher.as_mut() else {
+            return false;
+        };
+        if !watcher.changed() {
+            return false;
+        }
+        let Some(path) = self.shader_path.clone() else {
+            return false;
+        };
+
+        match Self::create_custom_shader(&path) {
+            Ok((program, passes)) => {
+                self.shader_program = program;
+                self.passes = passes;
+                self.shader_error = None;
+                // Buffer files may have been added or removed
+                self.watch_shader();
+                tracing::info!(event = "shader_reloaded", path = %path, "Reloaded shader after change");
+                true
+            }
+            Err(e) => {
+                tracing::error!(
+                    event = "shader_reload_error",
+                    path = %path,
+                    error = %e,
+                    "Shader failed to compile, keeping the previous version"
+                );
+                self.shader_error = Some(e.to_string());
+                events::publish(Event::ShaderError {
+                    path,
+                    error: e.to_string(),
+                });
+                false
+            }
+        }
+    }
+
+    /// Compiles a custom shader and its buffer passes and throws them away,
+    /// to report errors before the shader is used. Needs a current context.
+    pub fn check_shader(shader_path: &str) -> Result<()> {
+        Self::create_custom_shader(shader_path).map(|_| ())
+    }
+
+    pub fn new_pure_shader(shader_path: Option<&str>) -> Result<Self> {
+        // Custom shaders are compiled the same way with or without media;
+        // the media channel is simply left unbound for pure shaders
+        Self::new_with_shader(shader_path)
     }
 
     fn create_default_shader() -> Result<GlProgram> {
@@ -75,29 +157,13 @@ impl BaseMediaHandler {
         GlProgram::new(vert_source, frag_source)
     }
 
-    fn create_custom_shader(shader_path: &str) -> Result<GlProgram> {
-        let raw = Self::load_shader_file(shader_path)?;
-        let frag_source = utils::prepare_shader_source(&raw);
-        let vert_source = utils::vertex_shader();
-        GlProgram::new(vert_source, &frag_source)
-    }
-
-    fn create_pure_shader(shader_path: &str) -> Result<GlProgram> {
+    fn create_custom_shader(shader_path: &str) -> Result<(GlProgram, ShaderPasses)> {
         let raw = Self::load_shader_file(shader_path)?;
-        let frag_source = utils::prepare_shader_source(&raw);
-
-        let vert_source = r#"
-            #version 100
-            attribute highp vec2 datIn;
-            attribute highp vec2 texIn;
-            varying highp vec2 texCoords;
-            void main() {
-                texCoords = texIn;
-                gl_Position = vec4(datIn, 0.0, 1.0);
-            }
-        "#;
-
-        GlProgram::new(vert_source, &frag_source)
+        let frag_source = utils::prepare_shader_source(&raw, true);
+        let vert_source = utils::vertex_shader_for(&frag_source);
+        let program = GlProgram::new(vert_source, &frag_source)?;
+        let passes = ShaderPasses::load(&raw, Path::new(shader_path))?;
+        Ok((program, passes))
     }
 
     fn load_shader_file(path: &str) -> Result<String> {
@@ -114,10 +180,23 @@ impl BaseMediaHandler {
     }
 }
 
+pub mod animation;
+pub mod decoder;
+pub mod hwaccel;
 pub mod image;
+pub mod mirror;
+pub mod passes;
+pub mod probe;
+pub mod scaling;
 pub mod shader;
+pub mod transition;
 pub mod video;
 
+pub use animation::AnimationHandler;
+pub use hwaccel::HwDecode;
 pub use image::ImageHandler;
+pub use mirror::MirrorHandler;
+pub use scaling::{Fit, FitMode, ScalingMode};
 pub use shader::ShaderHandler;
+pub use transition::Transition;
 pub use video::VideoHandler;
@@ -0,0 +1,373 @@
+use crate::gl_bindings as gl;
+use crate::gl_utils::{GlFramebuffer, GlProgram, GlTexture};
+use crate::utils;
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+
+pub use papyrust::protocol::{UniformValue, Uniforms, validate_uniform};
+
+/// Texture unit `u_media` is bound to, kept clear of `iChannel0..3`.
+pub const MEDIA_TEXTURE_UNIT: u32 = 4;
+
+const CHANNEL_COUNT: usize = 4;
+
+/// What an `iChannelN` sampler reads from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelSource {
+    /// The image or video frame being shown
+    Media,
+    /// Latest output of a buffer pass, by index
+    Buffer(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelTexture {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<&GlTexture> for ChannelTexture {
+    fn from(texture: &GlTexture) -> Self {
+        Self {
+            id: texture.id,
+            width: texture.width,
+            height: texture.height,
+        }
+    }
+}
+
+/// Per-frame values behind the uniform contract. Every input is published under
+/// the daemon's own names (`time`, `resolution`), their `u_` forms and the
+/// Shadertoy names, so shaders can use whichever they were written against.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderInputs<'a> {
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub date: [f32; 4],
+    pub width: f32,
+    pub height: f32,
+    /// Set with `SetUniforms`, published under their own name and its `u_` form
+    pub uniforms: &'a Uniforms,
+}
+
+impl ShaderInputs<'_> {
+    pub fn apply(&self, program: &GlProgram) {
+        let uniform = |name: &str| Some(program.get_uniform_location(name)).filter(|&l| l != -1);
+        unsafe {
+            for name in ["time", "u_time", "iTime"] {
+                if let Some(loc) = uniform(name) {
+                    gl::Uniform1f(loc, self.time);
+                }
+            }
+            for name in ["resolution", "u_resolution"] {
+                if let Some(loc) = uniform(name) {
+                    gl::Uniform2f(loc, self.width, self.height);
+                }
+            }
+            if let Some(loc) = uniform("iResolution") {
+                gl::Uniform3f(loc, self.width, self.height, 1.0);
+            }
+            if let Some(loc) = uniform("iTimeDelta") {
+                gl::Uniform1f(loc, self.time_delta);
+            }
+            if let Some(loc) = uniform("iFrame") {
+                gl::Uniform1i(loc, self.frame);
+            }
+            if let Some(loc) = uniform("iDate") {
+                let [year, month, day, seconds] = self.date;
+                gl::Uniform4f(loc, year, month, day, seconds);
+            }
+            // Background surfaces receive no pointer input
+            if let Some(loc) = uniform("iMouse") {
+                gl::Uniform4f(loc, 0.0, 0.0, 0.0, 0.0);
+            }
+            for (name, value) in self.uniforms {
+                for loc in [uniform(name), uniform(&format!("u_{}", name))].into_iter().flatten() {
+                    match value {
+                        UniformValue::Bool(b) => gl::Uniform1i(loc, *b as i32),
+                        UniformValue::Float(v) => gl::Uniform1f(loc, *v),
+                        UniformValue::Vector(v) => match v.as_slice() {
+                            [x, y] => gl::Uniform2f(loc, *x, *y),
+                            [x, y, z] => gl::Uniform3f(loc, *x, *y, *z),
+                            [x, y, z, w] => gl::Uniform4f(loc, *x, *y, *z, *w),
+                            _ => {}
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct BufferPass {
+    name: char,
+    path: PathBuf,
+    program: GlProgram,
+    channels: [Option<ChannelSource>; CHANNEL_COUNT],
+    /// Ping-pong targets so a buffer can read its own previous frame
+    targets: Vec<GlFramebuffer>,
+    front: usize,
+}
+
+impl BufferPass {
+    fn output(&self) -> Option<ChannelTexture> {
+        self.targets.get(self.front).map(|t| ChannelTexture::from(&t.texture))
+    }
+}
+
+/// Buffer passes declared by a shader and the channel bindings of its final
+/// pass. Passes are declared with directives in the main shader file:
+///
+/// ```glsl
+/// #pragma papyrust buffer A buffer_a.glsl
+/// #pragma papyrust channel0 A
+/// #pragma papyrust channel1 media
+/// ```
+///
+/// Buffer paths are relative to the shader. Buffer files take `channelN`
+/// directives of their own; a buffer bound to itself reads its previous frame.
+pub struct ShaderPasses {
+    buffers: Vec<BufferPass>,
+    channels: [Option<ChannelSource>; CHANNEL_COUNT],
+}
+
+impl Default for ShaderPasses {
+    fn default() -> Self {
+        Self {
+            buffers: Vec::new(),
+            channels: [Some(ChannelSource::Media), None, None, None],
+        }
+    }
+}
+
+enum Directive {
+    Buffer(char, PathBuf),
+    Channel(usize, String),
+}
+
+fn parse_directives(source: &str) -> Result<Vec<Directive>> {
+    let mut directives = Vec::new();
+    for line in source.lines() {
+        let Some(rest) = line.trim().strip_prefix("#pragma papyrust") else {
+            continue;
+        };
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        match words.as_slice() {
+            ["buffer", name, path] => {
+                let name = parse_buffer_name(name)?;
+                directives.push(Directive::Buffer(name, PathBuf::from(path.trim_matches('"'))));
+            }
+            [channel, source] if channel.starts_with("channel") => {
+                let index: usize = channel["channel".len()..]
+                    .parse()
+                    .ok()
+                    .filter(|&i| i < CHANNEL_COUNT)
+                    .ok_or_else(|| anyhow!("Invalid channel in directive: {}", line.trim()))?;
+                directives.push(Directive::Channel(index, source.to_string()));
+            }
+            _ => return Err(anyhow!("Unknown shader directive: {}", line.trim())),
+        }
+    }
+    Ok(directives)
+}
+
+fn parse_buffer_name(name: &str) -> Result<char> {
+    match name.to_ascii_uppercase().as_str() {
+        n @ ("A" | "B" | "C" | "D") => Ok(n.chars().next().unwrap_or('A')),
+        _ => Err(anyhow!("Invalid buffer name {}: expected A-D", name)),
+    }
+}
+
+fn resolve_channels(
+    directives: &[Directive],
+    names: &[char],
+) -> Result<Option<[Option<ChannelSource>; CHANNEL_COUNT]>> {
+    let mut channels = [None; CHANNEL_COUNT];
+    let mut any = false;
+    for directive in directives {
+        let Directive::Channel(index, source) = directive else {
+            continue;
+        };
+        any = true;
+        channels[*index] = Some(if source.eq_ignore_ascii_case("media") {
+            ChannelSource::Media
+        } else {
+            let name = parse_buffer_name(source)?;
+            let buffer = names
+                .iter()
+                .position(|&n| n == name)
+                .ok_or_else(|| anyhow!("Channel {} reads undeclared buffer {}", index, name))?;
+            ChannelSource::Buffer(buffer)
+        });
+    }
+    Ok(any.then_some(channels))
+}
+
+impl ShaderPasses {
+    /// Compiles the buffer passes declared in `source`, the main shader loaded
+    /// from `shader_path`.
+    pub fn load(source: &str, shader_path: &Path) -> Result<Self> {
+        let directives = parse_directives(source)?;
+        let declared: Vec<(char, PathBuf)> = directives
+            .iter()
+            .filter_map(|d| match d {
+                Directive::Buffer(name, path) => Some((*name, path.clone())),
+                Directive::Channel(..) => None,
+            })
+            .collect();
+        let names: Vec<char> = declared.iter().map(|(name, _)| *name).collect();
+
+        let base_dir = shader_path.parent().unwrap_or(Path::new("."));
+        let mut buffers = Vec::with_capacity(declared.len());
+        for (name, path) in &declared {
+            let path = base_dir.join(path);
+            let raw = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read buffer {} shader {}: {}", name, path.display(), e))?;
+            let buffer_directives = parse_directives(&raw)?;
+            if buffer_directives.iter().any(|d| matches!(d, Directive::Buffer(..))) {
+                tracing::warn!(
+                    event = "shader_nested_buffer",
+                    buffer = %name,
+                    path = %path.display(),
+                    "Buffer declarations are only read from the main shader"
+                );
+            }
+
+            let frag_source = utils::prepare_shader_source(&raw, false);
+            let program = GlProgram::new(utils::vertex_shader_for(&frag_source), &frag_source)
+                .map_err(|e| anyhow!("Buffer {} ({}): {}", name, path.display(), e))?;
+            buffers.push(BufferPass {
+                name: *name,
+                path,
+                program,
+                channels: resolve_channels(&buffer_directives, &names)?.unwrap_or_default(),
+                targets: Vec::new(),
+                front: 0,
+            });
+        }
+
+        let channels = resolve_channels(&directives, &names)?
+            .unwrap_or_else(|| Self::default().channels);
+
+        if !buffers.is_empty() {
+            tracing::info!(
+                event = "shader_passes_loaded",
+                buffers = ?names,
+                ?channels,
+                "Loaded shader buffer passes"
+            );
+        }
+
+        Ok(Self { buffers, channels })
+    }
+
+    fn channel_texture(
+        &self,
+        source: Option<ChannelSource>,
+        media: Option<ChannelTexture>,
+    ) -> Option<ChannelTexture> {
+        match source? {
+            ChannelSource::Media => media,
+            ChannelSource::Buffer(index) => self.buffers.get(index)?.output(),
+        }
+    }
+
+    fn bind_channels(
+        &self,
+        program: &GlProgram,
+        channels: &[Option<ChannelSource>; CHANNEL_COUNT],
+        media: Option<ChannelTexture>,
+    ) {
+        let mut resolutions = [0.0f32; CHANNEL_COUNT * 3];
+        unsafe {
+            for (unit, source) in channels.iter().enumerate() {
+                let Some(texture) = self.channel_texture(*source, media) else {
+                    continue;
+                };
+                gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+                gl::BindTexture(gl::TEXTURE_2D, texture.id);
+                let loc = program.get_uniform_location(&format!("iChannel{}", unit));
+                if loc != -1 {
+                    gl::Uniform1i(loc, unit as i32);
+                }
+                resolutions[unit * 3] = texture.width as f32;
+                resolutions[unit * 3 + 1] = texture.height as f32;
+                resolutions[unit * 3 + 2] = 1.0;
+            }
+
+            let loc = program.get_uniform_location("iChannelResolution");
+            if loc != -1 {
+                gl::Uniform3fv(loc, CHANNEL_COUNT as i32, resolutions.as_ptr());
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    /// Binds the channels of the final pass for `program`.
+    pub fn bind_image_channels(&self, program: &GlProgram, media: Option<ChannelTexture>) {
+        self.bind_channels(program, &self.channels, media);
+    }
+
+    /// Runs every buffer pass in declaration order into its offscreen target,
+    /// leaving the default framebuffer bound. `draw` issues the full-screen quad.
+    pub fn render(
+        &mut self,
+        inputs: &ShaderInputs,
+        media: Option<ChannelTexture>,
+        draw: impl Fn(),
+    ) -> Result<()> {
+        let (width, height) = (inputs.width as u32, inputs.height as u32);
+        for index in 0..self.buffers.len() {
+            let buffer = &mut self.buffers[index];
+            let resized = buffer
+                .targets
+                .first()
+                .is_none_or(|t| t.texture.width != width || t.texture.height != height);
+            if resized {
+                tracing::debug!(
+                    event = "shader_buffer_resize",
+                    buffer = %buffer.name,
+                    width,
+                    height,
+                    "Allocating buffer pass targets"
+                );
+                buffer.targets = vec![
+                    GlFramebuffer::new(width, height)?,
+                    GlFramebuffer::new(width, height)?,
+                ];
+                buffer.front = 0;
+            }
+
+            let buffer = &self.buffers[index];
+            let back = 1 - buffer.front;
+            buffer.targets[back].bind();
+            unsafe {
+                gl::Viewport(0, 0, width as i32, height as i32);
+            }
+            buffer.program.use_program();
+            inputs.apply(&buffer.program);
+            // Reads see the front targets: this frame's output for buffers
+            // already drawn, the previous frame for this one and later ones
+            self.bind_channels(&buffer.program, &buffer.channels, media);
+            draw();
+
+            self.buffers[index].front = back;
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Ok(())
+    }
+
+    /// Files the buffer passes were compiled from.
+    pub fn source_paths(&self) -> impl Iterator<Item = &Path> {
+        self.buffers.iter().map(|b| b.path.as_path())
+    }
+
+    pub fn has_buffers(&self) -> bool {
+        !self.buffers.is_empty()
+    }
+}
@@ -0,0 +1,51 @@
+use crate::media::MediaType;
+use crate::media::image::is_tex;
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use papyrust::wallpaper_engine::tex::Tex;
+use std::path::Path;
+
+/// Checks that media can be decoded without decoding it: images have their
+/// header read and videos a decoder opened for their video stream. Shaders
+/// need a GL context and are compiled by the render loop instead.
+pub fn probe(media_type: &MediaType) -> Result<()> {
+    match media_type {
+        MediaType::Image { path, .. } | MediaType::Animation { path, .. } => probe_image(path),
+        MediaType::Video { path, .. } => probe_video(path),
+        MediaType::Shader(_) => Ok(()),
+    }
+}
+
+fn probe_image(path: &str) -> Result<()> {
+    if is_tex(path) {
+        let bytes = std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+        Tex::parse(&bytes).map_err(|e| anyhow!("Failed to load texture {}: {}", path, e))?;
+        return Ok(());
+    }
+    let (width, height) = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| anyhow!("Failed to open image {}: {}", path, e))?
+        .into_dimensions()
+        .map_err(|e| anyhow!("Failed to load image {}: {}", path, e))?;
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Image {} is empty", path));
+    }
+    Ok(())
+}
+
+fn probe_video(path: &str) -> Result<()> {
+    ffmpeg::init().map_err(|e| anyhow!("Failed to initialize FFmpeg: {}", e))?;
+    let input_ctx = ffmpeg::format::input(&Path::new(path))
+        .map_err(|e| anyhow!("Failed to open video file {}: {}", path, e))?;
+    let stream = input_ctx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| anyhow!("No video stream found in {}", path))?;
+    let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+        .map_err(|e| anyhow!("Failed to create codec context: {}", e))?;
+    context
+        .decoder()
+        .video()
+        .map_err(|e| anyhow!("Failed to create video decoder for {}: {}", path, e))?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+
+pub use papyrust::protocol::{Fit, FitMode, ScalingMode};
+
+/// swscale has no Mitchell-Netravali kernel with configurable B/C through
+/// ffmpeg-next, so Mitchell uses its bicubic filter, the closest it offers.
+pub fn sws_flags(filter: ScalingMode) -> ffmpeg::software::scaling::flag::Flags {
+    use ffmpeg::software::scaling::flag::Flags;
+    match filter {
+        ScalingMode::FSR | ScalingMode::Lanczos => Flags::LANCZOS,
+        ScalingMode::Mitchell | ScalingMode::Bicubic => Flags::BICUBIC,
+        ScalingMode::None => Flags::BILINEAR,
+    }
+}
+
+fn resize_alg(filter: ScalingMode) -> fast_image_resize::ResizeAlg {
+    use fast_image_resize::{FilterType, ResizeAlg};
+    match filter {
+        ScalingMode::FSR | ScalingMode::Lanczos => ResizeAlg::Convolution(FilterType::Lanczos3),
+        ScalingMode::Mitchell => ResizeAlg::Convolution(FilterType::Mitchell),
+        ScalingMode::Bicubic => ResizeAlg::Convolution(FilterType::CatmullRom),
+        ScalingMode::None => ResizeAlg::Nearest,
+    }
+}
+
+pub fn resize_rgba(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    target_width: u32,
+    target_height: u32,
+    filter: ScalingMode,
+) -> Result<Vec<u8>> {
+    let src = fast_image_resize::images::Image::from_vec_u8(
+        width,
+        height,
+        data.to_vec(),
+        fast_image_resize::PixelType::U8x4,
+    )
+    .map_err(|e| anyhow!("Invalid source image: {}", e))?;
+    let mut dst = fast_image_resize::images::Image::new(
+        target_width,
+        target_height,
+        fast_image_resize::PixelType::U8x4,
+    );
+
+    let options = fast_image_resize::ResizeOptions::new().resize_alg(resize_alg(filter));
+    fast_image_resize::Resizer::new()
+        .resize(&src, &mut dst, Some(&options))
+        .map_err(|e| anyhow!("Resize failed: {}", e))?;
+
+    let mut pixels = dst.into_vec();
+    if filter.sharpens() {
+        sharpen_rgba(&mut pixels, target_width as usize, target_height as usize, target_width as usize * 4);
+    }
+    Ok(pixels)
+}
+
+/// Contrast-adaptive sharpening in the spirit of FSR's RCAS pass: each pixel is
+/// pushed away from its cross neighbours, weighted down where the
+/// neighbourhood already has high contrast so edges do not ring.
+pub fn sharpen_rgba(pixels: &mut [u8], width: usize, height: usize, stride: usize) {
+    const SHARPNESS: f32 = 0.2;
+
+    if width < 3 || height < 3 {
+        return;
+    }
+
+    let source = pixels.to_vec();
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let at = |dx: isize, dy: isize, c: usize| {
+                let row = (y as isize + dy) as usize * stride;
+                source[row + (x as isize + dx) as usize * 4 + c] as f32
+            };
+            for c in 0..3 {
+                let center = at(0, 0, c);
+                let n = [at(0, -1, c), at(-1, 0, c), at(1, 0, c), at(0, 1, c)];
+                let min = n.iter().copied().fold(center, f32::min);
+                let max = n.iter().copied().fold(center, f32::max);
+
+                let headroom = min.min(255.0 - max);
+                let amount = if max > 0.0 {
+                    (headroom / max).clamp(0.0, 1.0).sqrt() * SHARPNESS
+                } else {
+                    0.0
+                };
+                let sum: f32 = n.iter().sum();
+                let value = center + amount * (4.0 * center - sum);
+                pixels[y * stride + x * 4 + c] = value.clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
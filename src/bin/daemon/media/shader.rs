@@ -39,4 +39,12 @@ impl MediaHandler for ShaderHandler {
     fn get_shader_program(&self) -> &crate::gl_utils::GlProgram {
         &self.base.shader_program
     }
+
+    fn get_base(&self) -> &BaseMediaHandler {
+        &self.base
+    }
+
+    fn get_base_mut(&mut self) -> &mut BaseMediaHandler {
+        &mut self.base
+    }
 }
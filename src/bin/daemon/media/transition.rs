@@ -0,0 +1,154 @@
+use crate::gl_bindings as gl;
+use crate::gl_utils::{GlFramebuffer, GlProgram};
+use crate::utils;
+use anyhow::{Result, anyhow};
+
+pub use papyrust::protocol::{Transition, TransitionKind};
+
+/// Fragment source of the transition shader, before the prelude and `main`.
+fn source(transition: &Transition) -> Result<String> {
+    Ok(match &transition.kind {
+        TransitionKind::None | TransitionKind::Fade => FADE.to_string(),
+        TransitionKind::Wipe => WIPE.to_string(),
+        TransitionKind::Grow => GROW.to_string(),
+        TransitionKind::Pixelate => PIXELATE.to_string(),
+        TransitionKind::Custom(path) => {
+            let path = shellexpand::tilde(path).to_string();
+            std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read transition {}: {}", path, e))?
+        }
+    })
+}
+
+const PRELUDE: &str = r#"
+#ifdef GL_ES
+precision highp float;
+#endif
+
+varying vec2 texCoords;
+uniform sampler2D from;
+uniform sampler2D to;
+uniform float progress;
+uniform float ratio;
+uniform vec2 resolution;
+uniform vec2 center;
+
+vec4 getFromColor(vec2 uv) { return texture2D(from, uv); }
+vec4 getToColor(vec2 uv) { return texture2D(to, uv); }
+"#;
+
+const MAIN: &str = r#"
+void main() {
+    // Offscreen targets are stored bottom row first
+    gl_FragColor = transition(vec2(texCoords.x, 1.0 - texCoords.y));
+}
+"#;
+
+const FADE: &str = r#"
+vec4 transition(vec2 uv) {
+    return mix(getFromColor(uv), getToColor(uv), progress);
+}
+"#;
+
+const WIPE: &str = r#"
+vec4 transition(vec2 uv) {
+    float edge = progress * 1.1 - 0.05;
+    float m = smoothstep(edge - 0.05, edge + 0.05, uv.x);
+    return mix(getToColor(uv), getFromColor(uv), m);
+}
+"#;
+
+const GROW: &str = r#"
+vec4 transition(vec2 uv) {
+    vec2 aspect = vec2(ratio, 1.0);
+    float reach = length(max(center, 1.0 - center) * aspect) + 0.02;
+    float radius = progress * reach;
+    float m = smoothstep(radius - 0.02, radius, length((uv - center) * aspect));
+    return mix(getToColor(uv), getFromColor(uv), m);
+}
+"#;
+
+const PIXELATE: &str = r#"
+vec4 transition(vec2 uv) {
+    float strength = min(progress, 1.0 - progress) * 2.0;
+    vec2 cells = max(vec2(1.0), resolution / max(1.0, strength * 64.0));
+    vec2 p = strength > 0.0 ? (floor(uv * cells) + 0.5) / cells : uv;
+    return mix(getFromColor(p), getToColor(p), smoothstep(0.4, 0.6, progress));
+}
+"#;
+
+/// A running transition: the outgoing and incoming media are drawn into
+/// offscreen targets and blended by the transition shader.
+pub struct TransitionEffect {
+    transition: Transition,
+    program: GlProgram,
+    targets: Option<[GlFramebuffer; 2]>,
+    started: u64,
+}
+
+impl TransitionEffect {
+    pub fn new(transition: Transition) -> Result<Self> {
+        let source = format!("{}\n{}\n{}", PRELUDE, source(&transition)?, MAIN);
+        let program = GlProgram::new(utils::vertex_shader(), &source)
+            .map_err(|e| anyhow!("Transition {:?}: {}", transition.kind, e))?;
+        Ok(Self {
+            transition,
+            program,
+            targets: None,
+            started: utils::get_time_millis(),
+        })
+    }
+
+    /// Eased progress at `now`; 1.0 once finished.
+    pub fn progress(&self, now: u64) -> f32 {
+        let elapsed = now.saturating_sub(self.started) as f32 / 1000.0;
+        let t = elapsed / self.transition.duration.max(0.001);
+        if t >= 1.0 {
+            return 1.0;
+        }
+        self.transition.easing.apply(t)
+    }
+
+    /// Targets for the outgoing and incoming media, (re)allocated at the
+    /// output size.
+    pub fn targets(&mut self, width: u32, height: u32) -> Result<&[GlFramebuffer; 2]> {
+        let stale = self
+            .targets
+            .as_ref()
+            .is_none_or(|[t, _]| t.texture.width != width || t.texture.height != height);
+        if stale {
+            self.targets = Some([GlFramebuffer::new(width, height)?, GlFramebuffer::new(width, height)?]);
+        }
+        self.targets
+            .as_ref()
+            .ok_or_else(|| anyhow!("Transition targets unavailable"))
+    }
+
+    /// Blends the two targets into the bound framebuffer. `draw` issues the
+    /// full-screen quad.
+    pub fn composite(&self, progress: f32, width: i32, height: i32, draw: impl Fn()) {
+        let Some([from, to]) = &self.targets else {
+            return;
+        };
+        let uniform = |name: &str| self.program.get_uniform_location(name);
+        let [x, y] = self.transition.position;
+        unsafe {
+            gl::Viewport(0, 0, width, height);
+            self.program.use_program();
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            from.texture.bind();
+            gl::Uniform1i(uniform("from"), 0);
+            gl::ActiveTexture(gl::TEXTURE1);
+            to.texture.bind();
+            gl::Uniform1i(uniform("to"), 1);
+            gl::ActiveTexture(gl::TEXTURE0);
+
+            gl::Uniform1f(uniform("progress"), progress);
+            gl::Uniform1f(uniform("ratio"), width as f32 / height.max(1) as f32);
+            gl::Uniform2f(uniform("resolution"), width as f32, height as f32);
+            gl::Uniform2f(uniform("center"), x, 1.0 - y);
+        }
+        draw();
+    }
+}
@@ -1,33 +1,41 @@
+use crate::events::{self, Event};
 use crate::gl_utils::GlTexture;
-use crate::media::{BaseMediaHandler, MediaHandler};
-use anyhow::{Result, anyhow};
+use crate::media::decoder::{DecodedFrame, VideoDecoder};
+use crate::media::hwaccel::{self, Nv12Converter};
+use crate::media::{BaseMediaHandler, Fit, MediaHandler};
+use crate::wayland::audio::{AudioStream, SinkKind};
+use anyhow::Result;
 use ffmpeg_next as ffmpeg;
-use std::path::Path;
 
+/// Presents frames from a [`VideoDecoder`] thread on time, against the audio
+/// clock while the audio track plays and the wall clock otherwise.
 pub struct VideoHandler {
     base: BaseMediaHandler,
-    decoder: ffmpeg::decoder::Video,
-    scaler: Option<ffmpeg::software::scaling::Context>,
-    input_ctx: ffmpeg::format::context::Input,
-    stream_index: usize,
+    decoder: VideoDecoder,
+    /// Converts hardware decoded NV12 frames on the GPU
+    nv12: Option<Nv12Converter>,
+    fit: Fit,
+    output_size: (u32, u32),
     video_path: String,
-    time_base: f64,
     playback_start_time: f64,
     forced_fps: Option<f64>,
-    current_frame: Option<ffmpeg::frame::Video>,
-    next_frame: Option<ffmpeg::frame::Video>,
-    current_frame_pts: Option<i64>,
-    next_frame_pts: Option<i64>,
-    reached_eof: bool,
-    video_fps: f64,
+    next_frame: Option<DecodedFrame>,
     loop_count: u64,
-    first_pts: Option<i64>,
     frame_count: u64,
-    video_restarted: bool,
+    audio: Option<AudioStream>,
+    /// Set once the file turned out to have no playable audio
+    audio_unavailable: bool,
+    last_playback_time: f64,
+    paused: bool,
 }
 
 impl VideoHandler {
-    pub fn new(path: &str, shader_path: Option<&str>, forced_fps: Option<f64>) -> Result<Self> {
+    pub fn new(
+        path: &str,
+        shader_path: Option<&str>,
+        forced_fps: Option<f64>,
+        fit: Fit,
+    ) -> Result<Self> {
         let fps_msg = if let Some(fps) = forced_fps {
             format!("forced FPS: {:.1}", fps)
         } else {
@@ -38,329 +46,215 @@ impl VideoHandler {
             path = %path,
             %fps_msg,
             shader = shader_path.unwrap_or("default"),
+            ?fit,
             "Creating video handler"
         );
 
-        ffmpeg::init().map_err(|e| anyhow!("Failed to initialize FFmpeg: {}", e))?;
-        let input_ctx = ffmpeg::format::input(&Path::new(path))
-            .map_err(|e| anyhow!("Failed to open video file {}: {}", path, e))?;
-
-        let stream = input_ctx
-            .streams()
-            .best(ffmpeg::media::Type::Video)
-            .ok_or_else(|| anyhow!("No video stream found in {}", path))?;
-        let stream_index = stream.index();
-
-        let context_decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
-            .map_err(|e| anyhow!("Failed to create codec context: {}", e))?;
-        let decoder = context_decoder
-            .decoder()
-            .video()
-            .map_err(|e| anyhow!("Failed to create video decoder: {}", e))?;
-
-        let width = decoder.width();
-        let height = decoder.height();
-
-        let time_base = {
-            let tb = stream.time_base();
-            tb.0 as f64 / tb.1 as f64
-        };
-        let video_duration = {
-            let duration = stream.duration();
-            if duration != ffmpeg::ffi::AV_NOPTS_VALUE {
-                duration as f64 * time_base
-            } else {
-                let format_duration = input_ctx.duration();
-                if format_duration != ffmpeg::ffi::AV_NOPTS_VALUE {
-                    format_duration as f64 / ffmpeg::ffi::AV_TIME_BASE as f64
-                } else {
-                    0.0
+        let nv12 = if hwaccel::is_enabled() {
+            match Nv12Converter::new() {
+                Ok(converter) => Some(converter),
+                Err(e) => {
+                    tracing::warn!(
+                        event = "hwaccel_convert_unavailable",
+                        error = %e,
+                        "Converting hardware decoded frames on the CPU"
+                    );
+                    None
                 }
             }
-        };
-
-        let video_fps = Self::detect_fps(&stream, time_base);
-
-        tracing::info!(
-            event = "video_info",
-            width,
-            height,
-            fps = video_fps,
-            forced_fps,
-            duration = video_duration,
-            time_base,
-            "Video stream initialized"
-        );
-
-        let scaler = if decoder.format() != ffmpeg::format::Pixel::RGBA {
-            Some(
-                ffmpeg::software::scaling::Context::get(
-                    decoder.format(),
-                    width,
-                    height,
-                    ffmpeg::format::Pixel::RGBA,
-                    width,
-                    height,
-                    ffmpeg::software::scaling::flag::Flags::LANCZOS,
-                )
-                .map_err(|e| anyhow!("Failed to create scaler: {}", e))?,
-            )
         } else {
             None
         };
 
-        let mut base = BaseMediaHandler::new_with_shader(shader_path)?;
-        let texture = GlTexture::new(width, height)?;
-        base.dimensions = (width, height);
-        base.texture = Some(texture);
+        let base = BaseMediaHandler::new_with_shader(shader_path)?;
+        let decoder = VideoDecoder::spawn(path, fit, nv12.is_some())?;
 
         Ok(Self {
             base,
             decoder,
-            scaler,
-            input_ctx,
-            stream_index,
+            nv12,
+            fit,
+            output_size: (0, 0),
             video_path: path.to_string(),
-            time_base,
             playback_start_time: crate::utils::get_time_millis() as f64 / 1000.0,
             forced_fps,
-            current_frame: None,
             next_frame: None,
-            current_frame_pts: None,
-            next_frame_pts: None,
-            reached_eof: false,
-            video_fps,
             loop_count: 0,
-            first_pts: None,
             frame_count: 0,
-            video_restarted: false,
+            audio: None,
+            audio_unavailable: false,
+            last_playback_time: 0.0,
+            paused: false,
         })
     }
 
-    pub fn take_restart_flag(&mut self) -> bool {
-        let restarted = self.video_restarted;
-        self.video_restarted = false;
-        restarted
-    }
-
-    fn detect_fps(stream: &ffmpeg::format::stream::Stream, time_base: f64) -> f64 {
-        let rate = stream.rate();
-        let avg_rate = stream.avg_frame_rate();
-
-        let fps_from_rate = if rate.1 > 0 {
-            rate.0 as f64 / rate.1 as f64
-        } else {
-            0.0
-        };
-
-        let fps_from_avg = if avg_rate.1 > 0 {
-            avg_rate.0 as f64 / avg_rate.1 as f64
-        } else {
-            0.0
-        };
+    /// Plays the video's audio track at `volume` through `sink`, or stops it
+    /// with `None`. While audio plays, frames are presented against the audio
+    /// clock instead of the wall clock.
+    pub fn set_audio(&mut self, output: Option<(f32, SinkKind)>) {
+        if self.paused {
+            return;
+        }
+        let now = crate::utils::get_time_millis() as f64 / 1000.0;
+        if self.audio.as_ref().is_some_and(|a| a.clock().is_none()) {
+            // Playback failed; carry on from where the audio clock stopped
+            self.audio = None;
+            self.audio_unavailable = true;
+            self.playback_start_time = now - self.last_playback_time;
+        }
 
-        let detected_fps = if (1.0..=120.0).contains(&fps_from_rate) {
-            fps_from_rate
-        } else if (1.0..=120.0).contains(&fps_from_avg) {
-            fps_from_avg
-        } else if time_base > 0.0 {
-            let tb_fps = 1.0 / time_base;
-            if (1.0..=120.0).contains(&tb_fps) {
-                tb_fps
-            } else {
-                25.0
+        match (output, &self.audio) {
+            (Some((volume, _)), Some(audio)) => audio.set_volume(volume),
+            (Some((volume, sink)), None) if !self.audio_unavailable => {
+                match AudioStream::new(&self.video_path, self.last_playback_time, volume, sink) {
+                    Ok(audio) => self.audio = Some(audio),
+                    Err(e) => {
+                        tracing::debug!(
+                            event = "video_no_audio",
+                            path = %self.video_path,
+                            error = %e,
+                            "Playing video without audio"
+                        );
+                        self.audio_unavailable = true;
+                    }
+                }
             }
-        } else {
-            25.0
-        };
-
-        tracing::debug!(
-            event = "fps_detection",
-            rate_fps = fps_from_rate,
-            avg_fps = fps_from_avg,
-            time_base_fps = if time_base > 0.0 {
-                1.0 / time_base
-            } else {
-                0.0
-            },
-            detected_fps,
-            "FPS detection results"
-        );
-
-        detected_fps
+            (None, Some(_)) => {
+                self.audio = None;
+                self.playback_start_time = now - self.last_playback_time;
+            }
+            _ => {}
+        }
     }
 
-    fn update_frame(&mut self) -> Result<bool> {
-        self.base.has_new_frame = false;
-        let current_time = crate::utils::get_time_millis() as f64 / 1000.0;
-        let playback_time = current_time - self.playback_start_time;
-
-        if self.next_frame.is_none() {
-            self.decode_next_frame()?;
+    /// Freezes playback on the current frame, stopping audio, or resumes from
+    /// it.
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
         }
-
-        let should_display_next = if let Some(forced_fps) = self.forced_fps {
-            let frame_duration = 1.0 / forced_fps;
-            let expected_frame_time = self.frame_count as f64 * frame_duration;
-            playback_time >= expected_frame_time
+        self.paused = paused;
+        if paused {
+            self.audio = None;
         } else {
-            if let Some(next_pts) = self.next_frame_pts {
-                let frame_time = self.pts_to_time(next_pts);
-                playback_time >= frame_time
-            } else {
-                let frame_duration = 1.0 / self.video_fps;
-                let expected_frame_time = self.frame_count as f64 * frame_duration;
-                playback_time >= expected_frame_time
-            }
-        };
-
-        if should_display_next && self.next_frame.is_some() {
-            let frame = self.next_frame.take();
-            self.current_frame = frame;
-            self.current_frame_pts = self.next_frame_pts.take();
+            let now = crate::utils::get_time_millis() as f64 / 1000.0;
+            self.playback_start_time = now - self.last_playback_time;
+        }
+        tracing::debug!(
+            event = "video_paused",
+            path = %self.video_path,
+            paused,
+            position = self.last_playback_time,
+            "Changed video pause state"
+        );
+    }
 
-            if let Some(current_frame) = self.current_frame.take() {
-                self.upload_frame(&current_frame);
-                self.current_frame = Some(current_frame);
-                self.base.has_new_frame = true;
-                self.frame_count += 1;
-            }
+    pub fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
 
-            self.decode_next_frame()?;
+    fn playback_time(&self) -> f64 {
+        match self.audio.as_ref().and_then(AudioStream::clock) {
+            Some(clock) => clock,
+            None => crate::utils::get_time_millis() as f64 / 1000.0 - self.playback_start_time,
         }
-
-        Ok(self.base.has_new_frame)
     }
 
-    fn pts_to_time(&self, pts: i64) -> f64 {
-        let adjusted_pts = if let Some(first) = self.first_pts {
-            pts - first
-        } else {
-            pts
-        };
-        adjusted_pts as f64 * self.time_base
+    fn video_fps(&self) -> f64 {
+        self.decoder.info().map_or(25.0, |info| info.fps)
     }
 
-    fn decode_next_frame(&mut self) -> Result<()> {
+    /// Takes the next frame off the decoder's queue into `next_frame`. The
+    /// first frame of a new loop restarts the clock.
+    fn fetch_next_frame(&mut self) -> Result<()> {
         if self.next_frame.is_some() {
             return Ok(());
         }
-
-        if !self.decode_frame_to_buffer()? {
-            self.loop_count += 1;
-            self.video_restarted = true;
+        let Some(frame) = self.decoder.try_next()? else {
+            return Ok(());
+        };
+        if frame.loop_count != self.loop_count {
+            self.loop_count = frame.loop_count;
+            if let Some(audio) = &self.audio {
+                audio.restart();
+            }
             tracing::debug!(
                 event = "video_loop",
                 loop_count = self.loop_count,
                 frame_count = self.frame_count,
                 "Video restarted for loop"
             );
-
-            let current_time = crate::utils::get_time_millis() as f64 / 1000.0;
-            self.playback_start_time = current_time;
-
+            events::publish(Event::VideoLooped {
+                path: self.video_path.clone(),
+                loop_count: self.loop_count,
+            });
+            self.playback_start_time = crate::utils::get_time_millis() as f64 / 1000.0;
             self.frame_count = 0;
-            self.first_pts = None;
-
-            self.restart_video()?;
-            self.decode_frame_to_buffer()?;
         }
+        self.next_frame = Some(frame);
         Ok(())
     }
 
-    fn decode_frame_to_buffer(&mut self) -> Result<bool> {
-        if self.reached_eof {
+    fn update_frame(&mut self) -> Result<bool> {
+        self.base.has_new_frame = false;
+        // A video paused before its first frame still shows that frame
+        if self.paused && self.base.texture.is_some() {
             return Ok(false);
         }
-
-        for (stream, packet) in self.input_ctx.packets() {
-            if stream.index() != self.stream_index {
-                continue;
-            }
-
-            match self.decoder.send_packet(&packet) {
-                Ok(_) => {
-                    let mut decoded = ffmpeg::frame::Video::empty();
-                    while self.decoder.receive_frame(&mut decoded).is_ok() {
-                        let pts = decoded.pts();
-
-                        if let Some(pts_val) = pts {
-                            if self.first_pts.is_none() {
-                                self.first_pts = Some(pts_val);
-                                tracing::debug!(
-                                    event = "video_first_pts",
-                                    pts = pts_val,
-                                    time = self.pts_to_time(pts_val),
-                                    "First frame PTS recorded"
-                                );
-                            }
-                        }
-
-                        let rgba_frame = self.convert_frame(decoded)?;
-                        self.next_frame = Some(rgba_frame);
-                        self.next_frame_pts = pts;
-                        return Ok(true);
-                    }
-                }
-                Err(ffmpeg::Error::Eof) => {
-                    self.reached_eof = true;
-                    return Ok(false);
+        self.fetch_next_frame()?;
+        let playback_time = self.playback_time();
+        self.last_playback_time = playback_time;
+
+        // Against the audio clock a late video has to drop frames to catch up
+        if self.audio.is_some() && self.forced_fps.is_none() {
+            let loop_count = self.loop_count;
+            while let Some(time) = self.next_frame.as_ref().and_then(|f| f.time) {
+                let late = time + 1.0 / self.video_fps() < playback_time;
+                if !late {
+                    break;
                 }
-                Err(_) => {
-                    continue;
+                self.next_frame = None;
+                self.frame_count += 1;
+                self.fetch_next_frame()?;
+                if self.loop_count != loop_count {
+                    break;
                 }
             }
         }
 
-        self.reached_eof = true;
-        Ok(false)
-    }
-
-    fn convert_frame(&mut self, frame: ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
-        if frame.format() != ffmpeg::format::Pixel::RGBA {
-            if let Some(ref mut scaler) = self.scaler {
-                let mut rgba_frame = ffmpeg::frame::Video::empty();
-                scaler
-                    .run(&frame, &mut rgba_frame)
-                    .map_err(|e| anyhow!("Scaling failed: {}", e))?;
-                rgba_frame.set_pts(frame.pts());
-                Ok(rgba_frame)
-            } else {
-                Ok(frame)
-            }
-        } else {
-            Ok(frame)
+        let Some(next_frame) = &self.next_frame else {
+            return Ok(false);
+        };
+        let frame_time = match (self.forced_fps, next_frame.time) {
+            (None, Some(time)) => time,
+            (forced_fps, _) => self.frame_count as f64 / forced_fps.unwrap_or(self.video_fps()),
+        };
+        if playback_time < frame_time {
+            return Ok(false);
         }
-    }
 
-    fn upload_frame(&mut self, frame: &ffmpeg::frame::Video) {
-        if let Some(ref texture) = self.base.texture {
-            texture.update_data(frame.data(0));
+        if let Some(next_frame) = self.next_frame.take() {
+            self.upload_frame(&next_frame.frame)?;
+            self.base.has_new_frame = true;
+            self.frame_count += 1;
         }
+        Ok(self.base.has_new_frame)
     }
 
-    fn restart_video(&mut self) -> Result<()> {
-        self.current_frame = None;
-        self.next_frame = None;
-        self.current_frame_pts = None;
-        self.next_frame_pts = None;
-        self.reached_eof = false;
-
-        self.input_ctx = ffmpeg::format::input(&Path::new(&self.video_path))
-            .map_err(|e| anyhow!("Failed to re-open video {}: {}", self.video_path, e))?;
-
-        let stream = self
-            .input_ctx
-            .streams()
-            .best(ffmpeg::media::Type::Video)
-            .ok_or_else(|| anyhow!("No video stream on restart"))?;
-
-        self.stream_index = stream.index();
-
-        let context_decoder =
-            ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
-        self.decoder = context_decoder.decoder().video()?;
-
+    fn upload_frame(&mut self, frame: &ffmpeg::frame::Video) -> Result<()> {
+        let size = (frame.width(), frame.height());
+        // Frames decoded before a resize still have the old size
+        if self.base.dimensions != size || self.base.texture.is_none() {
+            self.base.texture = Some(GlTexture::new(size.0, size.1)?);
+            self.base.dimensions = size;
+        }
+        match (&mut self.nv12, &self.base.texture) {
+            (Some(converter), Some(texture)) if frame.format() == ffmpeg::format::Pixel::NV12 => {
+                converter.convert(frame, texture)?;
+            }
+            (_, Some(texture)) => texture.update_data(frame.data(0), frame.stride(0)),
+            _ => {}
+        }
         Ok(())
     }
 }
@@ -385,4 +279,24 @@ impl MediaHandler for VideoHandler {
     fn get_shader_program(&self) -> &crate::gl_utils::GlProgram {
         &self.base.shader_program
     }
+
+    fn get_base(&self) -> &BaseMediaHandler {
+        &self.base
+    }
+
+    fn get_base_mut(&mut self) -> &mut BaseMediaHandler {
+        &mut self.base
+    }
+
+    fn get_fit(&self) -> Fit {
+        self.fit
+    }
+
+    fn set_output_size(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.output_size != (width, height) {
+            self.output_size = (width, height);
+            self.decoder.resize(width, height);
+        }
+        Ok(())
+    }
 }
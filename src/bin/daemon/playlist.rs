@@ -0,0 +1,516 @@
+use crate::ipc::MediaChange;
+use crate::utils;
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+pub use papyrust::protocol::{PlaylistConfig, PlaylistOrder};
+
+/// Cursor key of playlists that show the same item on every target
+const SHARED: &str = "";
+
+fn local_minute() -> u32 {
+    (utils::local_date()[3] / 60.0) as u32
+}
+
+/// Supported files in `directory` and `media`, sorted by name within each
+/// directory.
+fn collect_media(config: &PlaylistConfig, directory: Option<&String>, media: &[String]) -> Vec<PathBuf> {
+    let mut items = Vec::new();
+    for entry in directory.into_iter().chain(media) {
+        let path = PathBuf::from(shellexpand::tilde(entry).as_ref());
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = match fs::read_dir(&path) {
+                Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+                Err(e) => {
+                    tracing::warn!(event = "playlist_dir_error", path = %path.display(), error = %e, "Cannot read playlist directory");
+                    continue;
+                }
+            };
+            files.sort();
+            items.extend(files.into_iter().filter(|f| config.media_type(f).is_some()));
+        } else if config.media_type(&path).is_some() {
+            items.push(path);
+        } else {
+            tracing::warn!(event = "playlist_media_skipped", path = %path.display(), "Unsupported playlist entry");
+        }
+    }
+    items
+}
+
+/// Small xorshift generator; shuffling wallpapers does not need more.
+struct Rng(u64);
+
+impl Rng {
+    fn seeded() -> Self {
+        let seed = utils::get_time_millis() ^ ((std::process::id() as u64) << 32);
+        Self(seed | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n.max(1) as u64) as usize
+    }
+
+    fn shuffle(&mut self, order: &mut [usize]) {
+        for i in (1..order.len()).rev() {
+            order.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// Position of one output, or of all of them, in the playlist.
+struct Cursor {
+    order: Vec<usize>,
+    position: usize,
+}
+
+struct Playlist {
+    config: PlaylistConfig,
+    from_config: bool,
+    items: Vec<PathBuf>,
+    schedule: Option<usize>,
+    cursors: HashMap<String, Cursor>,
+    /// Outputs given media of their own since the playlist started
+    excluded: HashSet<String>,
+    next_at: Instant,
+    paused: bool,
+}
+
+impl Playlist {
+    fn new(config: PlaylistConfig, from_config: bool) -> Result<Self> {
+        config.validate()?;
+        let mut playlist = Self {
+            config,
+            from_config,
+            items: Vec::new(),
+            schedule: None,
+            cursors: HashMap::new(),
+            excluded: HashSet::new(),
+            next_at: Instant::now(),
+            paused: false,
+        };
+        playlist.schedule = playlist.config.active_schedule(local_minute());
+        playlist.rescan();
+        if playlist.items.is_empty() {
+            return Err(anyhow!("Playlist has no supported media"));
+        }
+        Ok(playlist)
+    }
+
+    fn collect(&self) -> Vec<PathBuf> {
+        let (directory, media) = match self.schedule.and_then(|i| self.config.schedule.get(i)) {
+            Some(entry) => (entry.directory.as_ref(), entry.media.as_slice()),
+            None => (self.config.directory.as_ref(), self.config.media.as_slice()),
+        };
+        collect_media(&self.config, directory, media)
+    }
+
+    fn rescan(&mut self) {
+        self.items = self.collect();
+        self.cursors.clear();
+    }
+
+    /// Re-reads the media after a full pass, so files added to or removed
+    /// from a directory are picked up. Returns whether the list changed, in
+    /// which case every cursor starts over.
+    fn refresh(&mut self, rng: &mut Rng) -> bool {
+        let items = self.collect();
+        if items.is_empty() || items == self.items {
+            return false;
+        }
+        tracing::info!(event = "playlist_rescan", items = items.len(), "Playlist media changed on disk");
+        self.items = items;
+        let mut keys: Vec<String> = self.cursors.keys().cloned().collect();
+        keys.sort();
+        for (offset, key) in keys.into_iter().enumerate() {
+            let cursor = self.new_cursor(rng, offset);
+            self.cursors.insert(key, cursor);
+        }
+        true
+    }
+
+    fn covers(&self, output: &str) -> bool {
+        !self.excluded.contains(output)
+            && self
+                .config
+                .monitors
+                .as_ref()
+                .is_none_or(|monitors| monitors.iter().any(|m| m == output))
+    }
+
+    fn targets(&self, outputs: &[String]) -> Vec<String> {
+        let mut targets: Vec<String> = outputs.iter().filter(|o| self.covers(o)).cloned().collect();
+        targets.sort();
+        targets
+    }
+
+    fn cursor_keys(&self, outputs: &[String]) -> Vec<String> {
+        if self.config.per_output {
+            self.targets(outputs)
+        } else {
+            vec![SHARED.to_string()]
+        }
+    }
+
+    fn new_cursor(&self, rng: &mut Rng, offset: usize) -> Cursor {
+        let len = self.items.len();
+        let mut order: Vec<usize> = (0..len).collect();
+        match self.config.order {
+            PlaylistOrder::Sequential => order.rotate_left(offset % len.max(1)),
+            PlaylistOrder::Shuffle => rng.shuffle(&mut order),
+        }
+        Cursor { order, position: 0 }
+    }
+
+    /// Moves `key` by `delta` items, rescanning the media once the list has
+    /// been played through.
+    fn step(&mut self, key: &str, delta: isize, rng: &mut Rng) {
+        let Some(cursor) = self.cursors.get(key) else {
+            return;
+        };
+        let len = cursor.order.len() as isize;
+        let position = cursor.position as isize + delta;
+        if !(0..len).contains(&position) && delta > 0 && self.refresh(rng) {
+            return;
+        }
+        let Some(cursor) = self.cursors.get_mut(key) else {
+            return;
+        };
+        if (0..len).contains(&position) {
+            cursor.position = position as usize;
+            return;
+        }
+        if self.config.order == PlaylistOrder::Shuffle && delta > 0 {
+            let last = cursor.order.get(cursor.position).copied();
+            rng.shuffle(&mut cursor.order);
+            // Do not show the same item twice in a row across a reshuffle
+            if cursor.order.len() > 1 && cursor.order.first().copied() == last {
+                cursor.order.swap(0, 1);
+            }
+        }
+        cursor.position = position.rem_euclid(len.max(1)) as usize;
+    }
+
+    fn change(&self, key: &str, outputs: &[String]) -> Option<MediaChange> {
+        let cursor = self.cursors.get(key)?;
+        let item = self.items.get(*cursor.order.get(cursor.position)?)?;
+        let monitors = if key != SHARED {
+            Some(vec![key.to_string()])
+        } else if self.config.monitors.is_none() && self.excluded.is_empty() {
+            None
+        } else {
+            Some(self.targets(outputs))
+        };
+        if monitors.as_ref().is_some_and(Vec::is_empty) {
+            return None;
+        }
+        let change = MediaChange {
+            media_type: self.config.media_type(item)?,
+            monitors,
+            mute: self.config.mute,
+            transition: self.config.transition.clone(),
+        };
+        tracing::debug!(
+            event = "playlist_advance",
+            monitors = ?change.monitors,
+            media_type = ?change.media_type,
+            "Playlist moved to another item"
+        );
+        Some(change)
+    }
+
+    /// Media changes due now: the first item for outputs without one yet, and
+    /// the next item for every output once the interval has passed.
+    fn poll(&mut self, outputs: &[String], rng: &mut Rng) -> Vec<MediaChange> {
+        let schedule = self.config.active_schedule(local_minute());
+        if schedule != self.schedule {
+            tracing::info!(event = "playlist_schedule_change", ?schedule, "Playlist schedule changed");
+            self.schedule = schedule;
+            self.rescan();
+        }
+        if self.items.is_empty() {
+            return Vec::new();
+        }
+
+        let due = !self.paused && Instant::now() >= self.next_at;
+        let mut changes = Vec::new();
+        for (offset, key) in self.cursor_keys(outputs).into_iter().enumerate() {
+            if !self.cursors.contains_key(&key) {
+                let cursor = self.new_cursor(rng, offset);
+                self.cursors.insert(key.clone(), cursor);
+            } else if due {
+                self.step(&key, 1, rng);
+            } else {
+                continue;
+            }
+            changes.extend(self.change(&key, outputs));
+        }
+        if due {
+            self.next_at = Instant::now() + Duration::from_secs(self.config.interval);
+        }
+        changes
+    }
+}
+
+/// The playlists driving outputs, from the config file and `SetPlaylist`.
+/// Changes they produce go through `MonitorManager::update_media` like any
+/// other `MediaChange`.
+pub struct PlaylistManager {
+    playlists: Vec<Playlist>,
+    rng: Rng,
+}
+
+impl PlaylistManager {
+    pub fn new(configs: &[PlaylistConfig]) -> Self {
+        let mut manager = Self {
+            playlists: Vec::new(),
+            rng: Rng::seeded(),
+        };
+        manager.set_config(configs);
+        manager
+    }
+
+    /// Replaces the playlists from the config file, keeping their positions
+    /// when the file's playlists did not change.
+    pub fn set_config(&mut self, configs: &[PlaylistConfig]) {
+        let current: Vec<&PlaylistConfig> = self
+            .playlists
+            .iter()
+            .filter(|p| p.from_config)
+            .map(|p| &p.config)
+            .collect();
+        if current.len() == configs.len() && current.iter().zip(configs).all(|(a, b)| *a == b) {
+            return;
+        }
+
+        self.playlists.retain(|p| !p.from_config);
+        for config in configs {
+            match Playlist::new(config.clone(), true) {
+                Ok(playlist) => self.playlists.push(playlist),
+                Err(e) => {
+                    tracing::warn!(event = "playlist_config_error", error = %e, "Skipping playlist from config");
+                }
+            }
+        }
+        tracing::info!(event = "playlists_configured", count = self.playlists.len(), "Configured playlists");
+    }
+
+    /// Starts a playlist, taking its outputs over from any other playlist.
+    pub fn start(&mut self, config: PlaylistConfig) -> Result<()> {
+        let playlist = Playlist::new(config, false)?;
+        tracing::info!(
+            event = "playlist_start",
+            monitors = ?playlist.config.monitors,
+            items = playlist.items.len(),
+            "Starting playlist"
+        );
+        self.release(playlist.config.monitors.as_deref());
+        self.playlists.push(playlist);
+        Ok(())
+    }
+
+    /// Stops playlists from changing `monitors`, all of them when `None`, after
+    /// they were given media directly.
+    pub fn release(&mut self, monitors: Option<&[String]>) {
+        let before = self.playlists.len();
+        match monitors {
+            None => self.playlists.clear(),
+            Some(monitors) => {
+                for playlist in &mut self.playlists {
+                    playlist.excluded.extend(monitors.iter().cloned());
+                    for monitor in monitors {
+                        playlist.cursors.remove(monitor);
+                    }
+                }
+                self.playlists.retain(|p| {
+                    p.config
+                        .monitors
+                        .as_ref()
+                        .is_none_or(|m| m.iter().any(|m| !p.excluded.contains(m)))
+                });
+            }
+        }
+        if self.playlists.len() != before {
+            tracing::info!(
+                event = "playlist_stop",
+                stopped = before - self.playlists.len(),
+                "Stopped playlists replaced by other media"
+            );
+        }
+    }
+
+    fn selected<'a>(
+        playlists: &'a mut [Playlist],
+        monitors: Option<&[String]>,
+    ) -> Result<Vec<&'a mut Playlist>> {
+        let selected: Vec<&mut Playlist> = playlists
+            .iter_mut()
+            .filter(|p| monitors.is_none_or(|monitors| monitors.iter().any(|m| p.covers(m))))
+            .collect();
+        if selected.is_empty() {
+            return Err(match monitors {
+                None => anyhow!("No playlist is running"),
+                Some(monitors) => anyhow!("No playlist on {}", monitors.join(", ")),
+            });
+        }
+        Ok(selected)
+    }
+
+    /// Moves the playlists on `monitors` forward or back by `delta` items and
+    /// restarts their interval.
+    pub fn step(
+        &mut self,
+        monitors: Option<&[String]>,
+        delta: isize,
+        outputs: &[String],
+    ) -> Result<Vec<MediaChange>> {
+        let mut changes = Vec::new();
+        for playlist in Self::selected(&mut self.playlists, monitors)? {
+            for key in playlist.cursor_keys(outputs) {
+                let wanted = key == SHARED || monitors.is_none_or(|m| m.contains(&key));
+                if wanted {
+                    playlist.step(&key, delta, &mut self.rng);
+                    changes.extend(playlist.change(&key, outputs));
+                }
+            }
+            playlist.next_at = Instant::now() + Duration::from_secs(playlist.config.interval);
+        }
+        Ok(changes)
+    }
+
+    pub fn set_paused(&mut self, monitors: Option<&[String]>, paused: bool) -> Result<()> {
+        for playlist in Self::selected(&mut self.playlists, monitors)? {
+            if playlist.paused && !paused {
+                // The current item gets a full interval after resuming
+                playlist.next_at = Instant::now() + Duration::from_secs(playlist.config.interval);
+            }
+            playlist.paused = paused;
+        }
+        Ok(())
+    }
+
+    /// Whether a playlist is rotating `output`, for the status reply.
+    pub fn is_running_on(&self, output: &str) -> bool {
+        self.playlists.iter().any(|p| !p.paused && p.covers(output))
+    }
+
+    pub fn poll(&mut self, outputs: &[String]) -> Vec<MediaChange> {
+        let rng = &mut self.rng;
+        self.playlists
+            .iter_mut()
+            .flat_map(|playlist| playlist.poll(outputs, rng))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(order: PlaylistOrder, len: usize) -> Playlist {
+        let media: Vec<String> = (0..len).map(|i| format!("/walls/{}.png", i)).collect();
+        Playlist {
+            items: media.iter().map(PathBuf::from).collect(),
+            config: PlaylistConfig {
+                media,
+                order,
+                ..PlaylistConfig::default()
+            },
+            from_config: false,
+            schedule: None,
+            cursors: HashMap::new(),
+            excluded: HashSet::new(),
+            next_at: Instant::now(),
+            paused: false,
+        }
+    }
+
+    fn start(playlist: &mut Playlist, rng: &mut Rng, offset: usize) {
+        let cursor = playlist.new_cursor(rng, offset);
+        playlist.cursors.insert(SHARED.to_string(), cursor);
+    }
+
+    fn current(playlist: &Playlist) -> usize {
+        let cursor = &playlist.cursors[SHARED];
+        cursor.order[cursor.position]
+    }
+
+    #[test]
+    fn sequential_steps_wrap_both_ways() {
+        let mut rng = Rng(7);
+        let mut playlist = playlist(PlaylistOrder::Sequential, 3);
+        start(&mut playlist, &mut rng, 0);
+        assert_eq!(current(&playlist), 0);
+
+        let mut shown = Vec::new();
+        for _ in 0..4 {
+            playlist.step(SHARED, 1, &mut rng);
+            shown.push(current(&playlist));
+        }
+        assert_eq!(shown, [1, 2, 0, 1]);
+
+        shown.clear();
+        for _ in 0..3 {
+            playlist.step(SHARED, -1, &mut rng);
+            shown.push(current(&playlist));
+        }
+        assert_eq!(shown, [0, 2, 1]);
+    }
+
+    #[test]
+    fn sequential_cursors_start_at_their_offset() {
+        let mut rng = Rng(7);
+        let playlist = playlist(PlaylistOrder::Sequential, 3);
+        assert_eq!(playlist.new_cursor(&mut rng, 1).order, [1, 2, 0]);
+        assert_eq!(playlist.new_cursor(&mut rng, 4).order, [1, 2, 0]);
+    }
+
+    #[test]
+    fn seeded_shuffle_is_repeatable() {
+        let mut order: Vec<usize> = (0..10).collect();
+        Rng(42).shuffle(&mut order);
+        assert_eq!(order, [8, 9, 5, 6, 7, 0, 3, 2, 1, 4]);
+
+        let mut again: Vec<usize> = (0..10).collect();
+        Rng(42).shuffle(&mut again);
+        assert_eq!(order, again);
+    }
+
+    #[test]
+    fn reshuffle_does_not_repeat_last_item() {
+        for seed in 1..64 {
+            let mut rng = Rng(seed);
+            let mut playlist = playlist(PlaylistOrder::Shuffle, 4);
+            start(&mut playlist, &mut rng, 0);
+            for _ in 0..3 {
+                playlist.step(SHARED, 1, &mut rng);
+            }
+            let last = current(&playlist);
+
+            playlist.step(SHARED, 1, &mut rng);
+            assert_eq!(playlist.cursors[SHARED].position, 0);
+            assert_ne!(current(&playlist), last, "seed {}", seed);
+
+            let mut order = playlist.cursors[SHARED].order.clone();
+            order.sort();
+            assert_eq!(order, [0, 1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn stepping_back_past_start_keeps_shuffled_order() {
+        let mut rng = Rng(3);
+        let mut playlist = playlist(PlaylistOrder::Shuffle, 4);
+        start(&mut playlist, &mut rng, 0);
+        let order = playlist.cursors[SHARED].order.clone();
+
+        playlist.step(SHARED, -1, &mut rng);
+        assert_eq!(playlist.cursors[SHARED].position, 3);
+        assert_eq!(playlist.cursors[SHARED].order, order);
+    }
+}
@@ -0,0 +1,152 @@
+use serde::Deserialize;
+use std::fs;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// `/proc/<pid>/comm` holds at most this many bytes of the process name
+const COMM_LEN: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerAction {
+    /// Cap every output at `throttle_fps`
+    Throttle,
+    /// Stop drawing, leaving the last frame on screen
+    Freeze,
+}
+
+/// The `[power]` table: what to do on battery or while given programs run.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerPolicy {
+    pub battery: Option<PowerAction>,
+    /// Process names, such as `steam` or `mpv`, that trigger `process_action`
+    pub processes: Vec<String>,
+    pub process_action: PowerAction,
+    pub throttle_fps: u16,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self {
+            battery: None,
+            processes: Vec::new(),
+            process_action: PowerAction::Freeze,
+            throttle_fps: 10,
+        }
+    }
+}
+
+impl PowerPolicy {
+    fn is_enabled(&self) -> bool {
+        self.battery.is_some() || !self.processes.is_empty()
+    }
+}
+
+/// Polls battery and process state for the power policy. Sysfs and procfs are
+/// re-read every few seconds rather than every frame.
+pub struct PowerMonitor {
+    policy: PowerPolicy,
+    last_poll: Option<Instant>,
+    action: Option<PowerAction>,
+}
+
+impl PowerMonitor {
+    pub fn new(policy: PowerPolicy) -> Self {
+        Self {
+            policy,
+            last_poll: None,
+            action: None,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: PowerPolicy) {
+        if self.policy != policy {
+            self.policy = policy;
+            self.last_poll = None;
+        }
+    }
+
+    pub fn throttle_fps(&self) -> u16 {
+        self.policy.throttle_fps
+    }
+
+    /// The action currently in force. When both the battery and a process ask
+    /// for one, freezing wins.
+    pub fn poll(&mut self) -> Option<PowerAction> {
+        if !self.policy.is_enabled() {
+            self.action = None;
+            return None;
+        }
+        if self.last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+            return self.action;
+        }
+        self.last_poll = Some(Instant::now());
+
+        let battery = self.policy.battery.filter(|_| on_battery());
+        let process = match running_process(&self.policy.processes) {
+            Some(name) => {
+                tracing::trace!(event = "power_process_found", process = %name, "Policy process running");
+                Some(self.policy.process_action)
+            }
+            None => None,
+        };
+        let action = match (battery, process) {
+            (Some(PowerAction::Freeze), _) | (_, Some(PowerAction::Freeze)) => {
+                Some(PowerAction::Freeze)
+            }
+            (a, b) => a.or(b),
+        };
+
+        if action != self.action {
+            tracing::info!(
+                event = "power_policy_change",
+                ?action,
+                on_battery = battery.is_some(),
+                process = process.is_some(),
+                "Power policy changed"
+            );
+            self.action = action;
+        }
+        action
+    }
+}
+
+/// Whether any battery in `/sys/class/power_supply` is discharging.
+fn on_battery() -> bool {
+    let Ok(entries) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        let read = |name: &str| fs::read_to_string(path.join(name)).unwrap_or_default();
+        read("type").trim() == "Battery" && read("status").trim() == "Discharging"
+    })
+}
+
+/// First process in `/proc` whose name is one of `names`.
+fn running_process(names: &[String]) -> Option<String> {
+    if names.is_empty() {
+        return None;
+    }
+    let entries = fs::read_dir("/proc").ok()?;
+    entries.flatten().find_map(|entry| {
+        let file_name = entry.file_name();
+        if !file_name
+            .to_string_lossy()
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let comm = fs::read_to_string(entry.path().join("comm")).ok()?;
+        let comm = comm.trim_end();
+        names
+            .iter()
+            .find(|name| {
+                let truncated = &name.as_bytes()[..name.len().min(COMM_LEN)];
+                comm.as_bytes() == truncated
+            })
+            .cloned()
+    })
+}
@@ -0,0 +1,127 @@
+use crate::media::MediaType;
+use crate::media::passes::Uniforms;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "state.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaAssignment {
+    pub media_type: MediaType,
+    #[serde(default)]
+    pub mute: bool,
+}
+
+impl MediaAssignment {
+    pub fn new(media_type: MediaType, mute: bool) -> Self {
+        Self { media_type, mute }
+    }
+
+    fn paths_exist(&self) -> bool {
+        let exists = |p: &str| p == "default" || Path::new(p).exists();
+        match &self.media_type {
+            MediaType::Shader(path) => exists(path),
+            MediaType::Image { path, shader, .. }
+            | MediaType::Video { path, shader, .. }
+            | MediaType::Animation { path, shader, .. } => {
+                exists(path) && shader.as_deref().is_none_or(exists)
+            }
+        }
+    }
+}
+
+impl Default for MediaAssignment {
+    fn default() -> Self {
+        Self::new(MediaType::Shader("default".to_string()), false)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DaemonState {
+    #[serde(default)]
+    pub default: MediaAssignment,
+    #[serde(default)]
+    pub monitors: HashMap<String, MediaAssignment>,
+    /// Volumes set at runtime, by output name
+    #[serde(default)]
+    pub volumes: HashMap<String, f32>,
+    /// Shader uniforms set at runtime, by output name
+    #[serde(default)]
+    pub uniforms: HashMap<String, Uniforms>,
+}
+
+impl DaemonState {
+    pub fn path() -> Result<PathBuf> {
+        let base = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".local").join("state"))
+                .ok_or_else(|| anyhow!("Neither XDG_STATE_HOME nor HOME is set"))?,
+        };
+        Ok(base.join("papyrust").join(STATE_FILE))
+    }
+
+    /// Loads the saved assignments, dropping any whose media no longer exists
+    /// on disk so a stale entry cannot keep the daemon from starting.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read state file {}: {}", path.display(), e))?;
+        let mut state: Self = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Invalid state file {}: {}", path.display(), e))?;
+
+        if !state.default.paths_exist() {
+            tracing::warn!(
+                event = "state_media_missing",
+                media = ?state.default.media_type,
+                "Saved default media no longer exists, using default shader"
+            );
+            state.default = MediaAssignment::default();
+        }
+        state.monitors.retain(|monitor, assignment| {
+            let keep = assignment.paths_exist();
+            if !keep {
+                tracing::warn!(
+                    event = "state_media_missing",
+                    monitor = %monitor,
+                    media = ?assignment.media_type,
+                    "Saved monitor media no longer exists, dropping assignment"
+                );
+            }
+            keep
+        });
+
+        tracing::info!(
+            event = "state_loaded",
+            path = %path.display(),
+            monitors = state.monitors.len(),
+            "Restored daemon state"
+        );
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Failed to create state dir {}: {}", dir.display(), e))?;
+        }
+
+        // Write to a sibling file first so a crash mid-write never leaves a truncated state
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| anyhow!("Failed to write state file {}: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| anyhow!("Failed to replace state file {}: {}", path.display(), e))?;
+
+        tracing::debug!(event = "state_saved", path = %path.display(), "Saved daemon state");
+        Ok(())
+    }
+}
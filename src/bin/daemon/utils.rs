@@ -16,6 +16,39 @@ pub fn sleep_millis(millis: u64) {
     }
 }
 
+pub struct FpsCounter {
+    frames: u32,
+    window_start: u64,
+    fps: f32,
+}
+
+impl FpsCounter {
+    const WINDOW_MILLIS: u64 = 1000;
+
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            window_start: get_time_millis(),
+            fps: 0.0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.frames += 1;
+        let now = get_time_millis();
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed >= Self::WINDOW_MILLIS {
+            self.fps = self.frames as f32 * 1000.0 / elapsed as f32;
+            self.frames = 0;
+            self.window_start = now;
+        }
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+}
+
 pub fn check_gl_error(context: &str) {
     unsafe {
         let error = gl::GetError();
@@ -58,8 +91,8 @@ void main() {
     vec2 center = vec2(0.5);
     uv = (uv - center) * scale + center;
     
-    // Ensure UV coordinates stay within bounds
-    uv = clamp(uv, 0.0, 1.0);
+    // Out of range coordinates are left to the texture wrap mode so tiled
+    // media repeats
     
     // Sample texture with high precision
     vec4 color = texture2D(u_media, uv);
@@ -84,7 +117,58 @@ void main() {
 "#
 }
 
-pub fn prepare_shader_source(raw_shader: &str) -> String {
+/// GLSL ES requires both stages of a program to use the same version, so
+/// `#version 300 es` fragment shaders get a matching vertex shader.
+pub fn vertex_shader_for(frag_source: &str) -> &'static str {
+    let is_es3 = frag_source
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .is_some_and(|l| l.starts_with("#version 300"));
+    if !is_es3 {
+        return vertex_shader();
+    }
+
+    r#"#version 300 es
+layout(location = 0) in highp vec2 datIn;
+layout(location = 1) in highp vec2 texIn;
+out highp vec2 texCoords;
+
+void main() {
+    texCoords = texIn;
+    gl_Position = vec4(datIn, 0.0, 1.0);
+}
+"#
+}
+
+/// Declarations for the Shadertoy inputs that `mainImage()` shaders expect to
+/// be predeclared.
+const SHADERTOY_PRELUDE: &str = r#"
+uniform vec3 iResolution;
+uniform float iTime;
+uniform float iTimeDelta;
+uniform int iFrame;
+uniform vec4 iDate;
+uniform vec4 iMouse;
+uniform vec3 iChannelResolution[4];
+uniform sampler2D iChannel0;
+uniform sampler2D iChannel1;
+uniform sampler2D iChannel2;
+uniform sampler2D iChannel3;
+out vec4 papyrust_FragColor;
+"#;
+
+/// Whether the shader is written against Shadertoy's `mainImage()` entry point
+/// rather than providing its own `main()`.
+pub fn is_shadertoy_shader(raw_shader: &str) -> bool {
+    raw_shader.contains("mainImage") && !raw_shader.contains("void main(")
+}
+
+/// Normalises precision and version directives. Shadertoy shaders are wrapped
+/// in a GLSL ES 3.00 `main()`; `image_pass` forces the result opaque, as
+/// Shadertoy ignores the alpha of its final pass but keeps it in buffers.
+pub fn prepare_shader_source(raw_shader: &str, image_pass: bool) -> String {
+    let shadertoy = is_shadertoy_shader(raw_shader);
     let mut version_directive: Option<&str> = None;
     let mut body_lines = Vec::new();
 
@@ -99,11 +183,13 @@ pub fn prepare_shader_source(raw_shader: &str) -> String {
 
     body_lines.retain(|l| {
         let t = l.trim_start();
-        !(t.starts_with("precision ") && t.ends_with("float;"))
+        !(t.starts_with("precision ") && t.ends_with("float;")) && !t.starts_with("#pragma papyrust")
     });
 
     let mut frag_source = String::new();
-    if let Some(v) = version_directive {
+    if shadertoy {
+        frag_source.push_str("#version 300 es\n");
+    } else if let Some(v) = version_directive {
         frag_source.push_str(v);
         frag_source.push('\n');
     }
@@ -120,6 +206,43 @@ pub fn prepare_shader_source(raw_shader: &str) -> String {
         "#,
     );
 
+    if shadertoy {
+        frag_source.push_str(SHADERTOY_PRELUDE);
+    }
+
     frag_source.push_str(&body_lines.join("\n"));
+
+    if shadertoy {
+        frag_source.push_str(
+            r#"
+void main() {
+    papyrust_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+    mainImage(papyrust_FragColor, gl_FragCoord.xy);
+"#,
+        );
+        if image_pass {
+            frag_source.push_str("    papyrust_FragColor.a = 1.0;\n");
+        }
+        frag_source.push_str("}\n");
+    }
     frag_source
 }
+
+/// Local date in Shadertoy's `iDate` layout: year, zero-based month, day of
+/// month and seconds since midnight.
+pub fn local_date() -> [f32; 4] {
+    let millis = get_time_millis();
+    let now = (millis / 1000) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&now, &mut tm);
+    }
+    let seconds = (tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec) as f32
+        + (millis % 1000) as f32 / 1000.0;
+    [
+        (tm.tm_year + 1900) as f32,
+        tm.tm_mon as f32,
+        tm.tm_mday as f32,
+        seconds,
+    ]
+}
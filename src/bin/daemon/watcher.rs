@@ -0,0 +1,99 @@
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Non-blocking inotify watch on a set of files. The parent directories are
+/// watched rather than the files, since editors often save by writing a new
+/// file and renaming it over the old one.
+pub struct FileWatcher {
+    fd: OwnedFd,
+    watches: HashMap<i32, Vec<OsString>>,
+}
+
+impl FileWatcher {
+    const EVENT_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(anyhow!(
+                "Failed to initialise inotify: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut watches: HashMap<i32, Vec<OsString>> = HashMap::new();
+        for path in paths {
+            let (dir, name) = Self::split(path)?;
+            let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+            let wd = unsafe {
+                libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), Self::EVENT_MASK)
+            };
+            if wd < 0 {
+                return Err(anyhow!(
+                    "Failed to watch {}: {}",
+                    dir.display(),
+                    std::io::Error::last_os_error()
+                ));
+            }
+            watches.entry(wd).or_default().push(name);
+        }
+
+        Ok(Self { fd, watches })
+    }
+
+    fn split(path: &Path) -> Result<(PathBuf, OsString)> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Cannot watch {}: not a file", path.display()))?
+            .to_os_string();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        Ok((dir, name))
+    }
+
+    /// Drains pending events and reports whether any watched file changed.
+    pub fn changed(&mut self) -> bool {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut buffer = [0u8; 4096];
+        let mut changed = false;
+
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if read <= 0 {
+                break;
+            }
+
+            let mut offset = 0;
+            while offset + HEADER <= read as usize {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const _) };
+                let name_start = offset + HEADER;
+                let name_end = (name_start + event.len as usize).min(read as usize);
+                let name = buffer[name_start..name_end]
+                    .split(|&b| b == 0)
+                    .next()
+                    .unwrap_or(&[]);
+
+                if let Some(names) = self.watches.get(&event.wd) {
+                    changed |= names.iter().any(|n| n.as_bytes() == name);
+                }
+                offset = name_end;
+            }
+        }
+        changed
+    }
+}
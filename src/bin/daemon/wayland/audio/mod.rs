@@ -1,6 +1,9 @@
 pub mod fifo;
 pub mod manager;
-pub mod player;
+pub mod sink;
+pub mod stream;
 
 pub use fifo::FifoReader;
 pub use manager::AudioManager;
+pub use sink::SinkKind;
+pub use stream::AudioStream;
@@ -0,0 +1,298 @@
+use anyhow::{Result, anyhow};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Output format every sink is opened with; decoded audio is resampled to it.
+pub const SAMPLE_RATE: u32 = 48000;
+pub const CHANNELS: usize = 2;
+pub const BYTES_PER_FRAME: usize = CHANNELS * 2;
+
+/// Destination for interleaved signed 16-bit stereo samples. `write` blocks
+/// while the device buffer is full, which is what paces the decoder.
+pub trait AudioSink: Send {
+    fn write(&mut self, samples: &[i16]) -> Result<()>;
+    /// Audio written but not yet heard
+    fn latency(&mut self) -> Duration;
+    fn flush(&mut self) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkKind {
+    /// PulseAudio or PipeWire's Pulse server, through libpulse-simple
+    Pulse,
+    /// Discards samples in real time, keeping the audio clock running
+    Null,
+}
+
+impl SinkKind {
+    pub fn detect() -> Self {
+        match PulseApi::get() {
+            Ok(_) => SinkKind::Pulse,
+            Err(e) => {
+                tracing::warn!(
+                    event = "audio_sink_unavailable",
+                    error = %e,
+                    "No audio server library found, video audio will be silent"
+                );
+                SinkKind::Null
+            }
+        }
+    }
+
+    pub fn open(self, stream_name: &str) -> Result<Box<dyn AudioSink>> {
+        match self {
+            SinkKind::Pulse => Ok(Box::new(PulseSink::new(stream_name)?)),
+            SinkKind::Null => Ok(Box::new(NullSink::new())),
+        }
+    }
+}
+
+pub struct NullSink {
+    started: Option<Instant>,
+    frames: u64,
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Self {
+            started: None,
+            frames: 0,
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.frames += (samples.len() / CHANNELS) as u64;
+        let due = Duration::from_secs_f64(self.frames as f64 / SAMPLE_RATE as f64);
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        Ok(())
+    }
+
+    fn latency(&mut self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.started = None;
+        self.frames = 0;
+        Ok(())
+    }
+}
+
+#[repr(C)]
+struct PaSampleSpec {
+    format: c_int,
+    rate: u32,
+    channels: u8,
+}
+
+#[repr(C)]
+struct PaBufferAttr {
+    maxlength: u32,
+    tlength: u32,
+    prebuf: u32,
+    minreq: u32,
+    fragsize: u32,
+}
+
+const PA_STREAM_PLAYBACK: c_int = 1;
+const PA_SAMPLE_S16LE: c_int = 3;
+/// Device buffer target; small enough that seeking and restarts are heard promptly
+const TARGET_LATENCY: Duration = Duration::from_millis(60);
+
+type PaSimpleNew = unsafe extern "C" fn(
+    *const c_char,
+    *const c_char,
+    c_int,
+    *const c_char,
+    *const c_char,
+    *const PaSampleSpec,
+    *const c_void,
+    *const PaBufferAttr,
+    *mut c_int,
+) -> *mut c_void;
+type PaSimpleWrite = unsafe extern "C" fn(*mut c_void, *const c_void, usize, *mut c_int) -> c_int;
+type PaSimpleGetLatency = unsafe extern "C" fn(*mut c_void, *mut c_int) -> u64;
+type PaSimpleFlush = unsafe extern "C" fn(*mut c_void, *mut c_int) -> c_int;
+type PaSimpleFree = unsafe extern "C" fn(*mut c_void);
+type PaStrerror = unsafe extern "C" fn(c_int) -> *const c_char;
+
+/// libpulse-simple loaded at runtime, so the daemon still starts on systems
+/// without it and falls back to the null sink.
+struct PulseApi {
+    new: PaSimpleNew,
+    write: PaSimpleWrite,
+    get_latency: PaSimpleGetLatency,
+    flush: PaSimpleFlush,
+    free: PaSimpleFree,
+    strerror: PaStrerror,
+}
+
+impl PulseApi {
+    fn get() -> Result<&'static PulseApi> {
+        static API: OnceLock<Result<PulseApi, String>> = OnceLock::new();
+        API.get_or_init(|| unsafe { Self::load() })
+            .as_ref()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Loads libpulse-simple and the symbols used from it.
+    ///
+    /// # Safety
+    ///
+    /// Runs the libraries' initializers. The `Pa*` types must match the C
+    /// prototypes of the symbols they are cast to.
+    unsafe fn load() -> Result<PulseApi, String> {
+        unsafe {
+            let simple = libc::dlopen(c"libpulse-simple.so.0".as_ptr(), libc::RTLD_NOW);
+            let pulse = libc::dlopen(c"libpulse.so.0".as_ptr(), libc::RTLD_NOW);
+            if simple.is_null() || pulse.is_null() {
+                return Err("libpulse-simple.so.0 not found".to_string());
+            }
+
+            let symbol = |lib: *mut c_void, name: &CStr| {
+                let ptr = libc::dlsym(lib, name.as_ptr());
+                if ptr.is_null() {
+                    Err(format!("Missing symbol {}", name.to_string_lossy()))
+                } else {
+                    Ok(ptr)
+                }
+            };
+
+            Ok(PulseApi {
+                new: std::mem::transmute::<*mut c_void, PaSimpleNew>(symbol(
+                    simple,
+                    c"pa_simple_new",
+                )?),
+                write: std::mem::transmute::<*mut c_void, PaSimpleWrite>(symbol(
+                    simple,
+                    c"pa_simple_write",
+                )?),
+                get_latency: std::mem::transmute::<*mut c_void, PaSimpleGetLatency>(symbol(
+                    simple,
+                    c"pa_simple_get_latency",
+                )?),
+                flush: std::mem::transmute::<*mut c_void, PaSimpleFlush>(symbol(
+                    simple,
+                    c"pa_simple_flush",
+                )?),
+                free: std::mem::transmute::<*mut c_void, PaSimpleFree>(symbol(
+                    simple,
+                    c"pa_simple_free",
+                )?),
+                strerror: std::mem::transmute::<*mut c_void, PaStrerror>(symbol(
+                    pulse,
+                    c"pa_strerror",
+                )?),
+            })
+        }
+    }
+
+    fn error(&self, code: c_int) -> String {
+        unsafe {
+            let message = (self.strerror)(code);
+            if message.is_null() {
+                format!("error {}", code)
+            } else {
+                CStr::from_ptr(message).to_string_lossy().into_owned()
+            }
+        }
+    }
+}
+
+pub struct PulseSink {
+    api: &'static PulseApi,
+    handle: *mut c_void,
+}
+
+// The handle is only ever used from the thread that owns the sink
+unsafe impl Send for PulseSink {}
+
+impl PulseSink {
+    pub fn new(stream_name: &str) -> Result<Self> {
+        let api = PulseApi::get()?;
+        let spec = PaSampleSpec {
+            format: PA_SAMPLE_S16LE,
+            rate: SAMPLE_RATE,
+            channels: CHANNELS as u8,
+        };
+        let target =
+            (TARGET_LATENCY.as_secs_f64() * SAMPLE_RATE as f64) as u32 * BYTES_PER_FRAME as u32;
+        let attr = PaBufferAttr {
+            maxlength: u32::MAX,
+            tlength: target,
+            prebuf: u32::MAX,
+            minreq: u32::MAX,
+            fragsize: u32::MAX,
+        };
+        let stream_name = CString::new(stream_name)?;
+        let mut error = 0;
+
+        let handle = unsafe {
+            (api.new)(
+                std::ptr::null(),
+                c"papyrust".as_ptr(),
+                PA_STREAM_PLAYBACK,
+                std::ptr::null(),
+                stream_name.as_ptr(),
+                &spec,
+                std::ptr::null(),
+                &attr,
+                &mut error,
+            )
+        };
+        if handle.is_null() {
+            return Err(anyhow!(
+                "Failed to connect to audio server: {}",
+                api.error(error)
+            ));
+        }
+        Ok(Self { api, handle })
+    }
+}
+
+impl AudioSink for PulseSink {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        let mut error = 0;
+        let result = unsafe {
+            (self.api.write)(
+                self.handle,
+                samples.as_ptr() as *const c_void,
+                std::mem::size_of_val(samples),
+                &mut error,
+            )
+        };
+        if result < 0 {
+            return Err(anyhow!("Audio write failed: {}", self.api.error(error)));
+        }
+        Ok(())
+    }
+
+    fn latency(&mut self) -> Duration {
+        let mut error = 0;
+        let micros = unsafe { (self.api.get_latency)(self.handle, &mut error) };
+        if micros == u64::MAX {
+            return Duration::ZERO;
+        }
+        Duration::from_micros(micros)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut error = 0;
+        if unsafe { (self.api.flush)(self.handle, &mut error) } < 0 {
+            return Err(anyhow!("Audio flush failed: {}", self.api.error(error)));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PulseSink {
+    fn drop(&mut self) {
+        unsafe { (self.api.free)(self.handle) };
+    }
+}
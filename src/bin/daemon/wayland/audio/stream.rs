@@ -0,0 +1,214 @@
+use super::sink::{BYTES_PER_FRAME, CHANNELS, SAMPLE_RATE, SinkKind};
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Silence written after the audio track ends so the clock keeps running until
+/// the video loops.
+const SILENCE_CHUNK: Duration = Duration::from_millis(20);
+
+#[derive(Default)]
+struct Shared {
+    stop: AtomicBool,
+    restart: AtomicBool,
+    failed: AtomicBool,
+    volume: AtomicU32,
+    /// Media position, in microseconds, the current run started from
+    start_micros: AtomicU64,
+    /// Sample frames handed to the sink since the current run started
+    frames: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+impl Shared {
+    fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+}
+
+/// Audio track of a video decoded on its own thread and played through a sink.
+/// The position the sink has actually played up to is the clock the video
+/// presents frames against.
+pub struct AudioStream {
+    shared: Arc<Shared>,
+}
+
+impl AudioStream {
+    /// Starts playback of the best audio stream in `path` from `start` seconds.
+    /// Fails up front when the file has no audio.
+    pub fn new(path: &str, start: f64, volume: f32, sink: SinkKind) -> Result<Self> {
+        let input = ffmpeg::format::input(&Path::new(path))
+            .map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+        if input.streams().best(ffmpeg::media::Type::Audio).is_none() {
+            return Err(anyhow!("No audio stream in {}", path));
+        }
+        drop(input);
+
+        let shared = Arc::new(Shared::default());
+        shared.volume.store(volume.to_bits(), Ordering::Relaxed);
+        shared
+            .start_micros
+            .store((start.max(0.0) * 1_000_000.0) as u64, Ordering::Relaxed);
+
+        let thread_shared = shared.clone();
+        let path = path.to_string();
+        thread::Builder::new()
+            .name("papyrust-audio".to_string())
+            .spawn(move || {
+                if let Err(e) = Self::run(&path, &thread_shared, sink) {
+                    tracing::warn!(event = "audio_stream_error", path = %path, error = %e, "Audio playback stopped");
+                    thread_shared.failed.store(true, Ordering::Relaxed);
+                }
+            })?;
+
+        tracing::info!(
+            event = "audio_stream_start",
+            start,
+            volume,
+            ?sink,
+            "Started audio stream"
+        );
+        Ok(Self { shared })
+    }
+
+    /// Seconds of media played so far, or `None` once playback has failed and
+    /// the video should fall back to the wall clock.
+    pub fn clock(&self) -> Option<f64> {
+        if self.shared.failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        let start = self.shared.start_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let written = self.shared.frames.load(Ordering::Relaxed) as f64 / SAMPLE_RATE as f64;
+        let latency = self.shared.latency_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        Some(start + (written - latency).max(0.0))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.shared
+            .volume
+            .store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Plays again from the beginning, for when the video loops.
+    pub fn restart(&self) {
+        self.shared.start_micros.store(0, Ordering::Relaxed);
+        self.shared.frames.store(0, Ordering::Relaxed);
+        self.shared.latency_micros.store(0, Ordering::Relaxed);
+        self.shared.restart.store(true, Ordering::Relaxed);
+    }
+
+    fn run(path: &str, shared: &Shared, sink: SinkKind) -> Result<()> {
+        let stream_name = Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string());
+        let mut sink = sink.open(&stream_name)?;
+
+        let write =
+            |sink: &mut Box<dyn super::sink::AudioSink>, samples: &mut Vec<i16>| -> Result<()> {
+                let volume = shared.volume();
+                if volume != 1.0 {
+                    for sample in samples.iter_mut() {
+                        *sample = (*sample as f32 * volume) as i16;
+                    }
+                }
+                sink.write(samples)?;
+                shared
+                    .frames
+                    .fetch_add((samples.len() / CHANNELS) as u64, Ordering::Relaxed);
+                shared
+                    .latency_micros
+                    .store(sink.latency().as_micros() as u64, Ordering::Relaxed);
+                Ok(())
+            };
+
+        loop {
+            let start = shared.start_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+            let mut input = ffmpeg::format::input(&Path::new(path))?;
+            let stream = input
+                .streams()
+                .best(ffmpeg::media::Type::Audio)
+                .ok_or_else(|| anyhow!("No audio stream in {}", path))?;
+            let stream_index = stream.index();
+            let time_base: f64 = stream.time_base().into();
+            let mut decoder =
+                ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+                    .decoder()
+                    .audio()?;
+            let mut resampler = ffmpeg::software::resampling::Context::get(
+                decoder.format(),
+                ffmpeg::ChannelLayout::default(decoder.channels() as i32),
+                decoder.rate(),
+                ffmpeg::format::Sample::I16(ffmpeg::format::sample::Type::Packed),
+                ffmpeg::ChannelLayout::STEREO,
+                SAMPLE_RATE,
+            )?;
+
+            if start > 0.0 {
+                let timestamp = (start * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
+                input.seek(timestamp, ..timestamp)?;
+            }
+
+            let mut decoded = ffmpeg::frame::Audio::empty();
+            let mut resampled = ffmpeg::frame::Audio::empty();
+            let mut samples: Vec<i16> = Vec::new();
+
+            for (packet_stream, packet) in input.packets() {
+                if shared.stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                if shared.restart.load(Ordering::Relaxed) {
+                    break;
+                }
+                if packet_stream.index() != stream_index || decoder.send_packet(&packet).is_err() {
+                    continue;
+                }
+
+                while decoder.receive_frame(&mut decoded).is_ok() {
+                    // Seeking lands on the packet before the target
+                    let frame_time = decoded.pts().map(|pts| pts as f64 * time_base);
+                    if frame_time.is_some_and(|t| t + 0.1 < start) {
+                        continue;
+                    }
+
+                    resampler.run(&decoded, &mut resampled)?;
+                    let bytes = &resampled.data(0)[..resampled.samples() * BYTES_PER_FRAME];
+                    samples.clear();
+                    samples.extend(
+                        bytes
+                            .chunks_exact(2)
+                            .map(|b| i16::from_le_bytes([b[0], b[1]])),
+                    );
+                    write(&mut sink, &mut samples)?;
+                }
+            }
+
+            let silence_frames = (SILENCE_CHUNK.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+            while !shared.restart.load(Ordering::Relaxed) {
+                if shared.stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                samples.clear();
+                samples.resize(silence_frames * CHANNELS, 0);
+                write(&mut sink, &mut samples)?;
+            }
+
+            shared.restart.store(false, Ordering::Relaxed);
+            sink.flush()?;
+            shared.frames.store(0, Ordering::Relaxed);
+            shared.latency_micros.store(0, Ordering::Relaxed);
+            tracing::debug!(event = "audio_stream_restart", path = %path, "Restarting audio with video loop");
+        }
+    }
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        // The thread notices within one sink write and exits on its own
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
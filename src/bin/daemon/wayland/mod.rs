@@ -1,9 +1,17 @@
-use crate::ipc::MediaChange;
-use crate::media::MediaType;
+use crate::config::{OutputSettings, Settings};
+use crate::events::{self, Event};
+use crate::ipc::{
+    DaemonRequest, ErrorCode, IpcError, IpcResult, MediaChange, PlaylistRequest, WAIT_TIMEOUT,
+};
+use crate::media::{MediaType, Transition};
+use crate::playlist::PlaylistManager;
+use crate::power::PowerMonitor;
+use crate::state::DaemonState;
 use crate::utils;
 use anyhow::{Result, anyhow};
-use std::sync::mpsc::Receiver;
-use wayland_client::Connection;
+use std::sync::mpsc::{Receiver, Sender};
+use wayland_client::backend::WaylandError;
+use wayland_client::{Connection, EventQueue};
 
 pub mod audio;
 pub mod monitors;
@@ -14,131 +22,458 @@ pub mod types;
 
 use audio::{AudioManager, FifoReader};
 use monitors::MonitorManager;
+use protocol::events::AppState;
+use rendering::MediaLoad;
 use traits::WaylandSurface as WaylandSurfaceTrait;
-use types::WaylandConfig;
+use types::OutputInfo;
+
+const IDLE_FRAME_TIME_MS: u64 = 100;
+
+/// A media change whose caller waits for its first frame.
+struct MediaWaiter {
+    /// Outputs not showing the media yet, with its generation there
+    outputs: Vec<(String, u64)>,
+    reply: Sender<IpcResult<()>>,
+    deadline: u64,
+}
+
+impl MediaWaiter {
+    fn new(outputs: Vec<(String, u64)>, reply: Sender<IpcResult<()>>) -> Self {
+        Self {
+            outputs,
+            reply,
+            deadline: utils::get_time_millis() + WAIT_TIMEOUT.as_millis() as u64,
+        }
+    }
+
+    /// Replies once every output has drawn the media, or as soon as one
+    /// cannot. Returns whether it replied.
+    fn poll(&mut self, monitor_manager: &MonitorManager) -> bool {
+        let mut failure = None;
+        self.outputs.retain(|(name, generation)| {
+            let error = match monitor_manager.media_load(name, *generation) {
+                // Outputs disconnected meanwhile are not waited for
+                None | Some(MediaLoad::Shown) => return false,
+                Some(MediaLoad::Loading) => return true,
+                Some(MediaLoad::Failed(e)) => {
+                    IpcError::new(ErrorCode::DecodeFailed, format!("{}: {}", name, e))
+                }
+                Some(MediaLoad::Replaced) => IpcError::new(
+                    ErrorCode::Superseded,
+                    format!("Other media was set on {} before this was shown", name),
+                ),
+            };
+            failure.get_or_insert(error);
+            false
+        });
+
+        let result = match failure {
+            Some(error) => Err(error),
+            None if self.outputs.is_empty() => Ok(()),
+            None if utils::get_time_millis() >= self.deadline => {
+                let outputs: Vec<&str> = self.outputs.iter().map(|(name, _)| name.as_str()).collect();
+                Err(IpcError::new(
+                    ErrorCode::Unavailable,
+                    format!("Nothing drawn on {} within {:?}", outputs.join(", "), WAIT_TIMEOUT),
+                ))
+            }
+            None => return false,
+        };
+        let _ = self.reply.send(result);
+        true
+    }
+}
 
 struct WaylandManager {
     monitor_manager: MonitorManager,
-    config: WaylandConfig,
+    settings: Settings,
+    conn: Connection,
+    event_queue: EventQueue<AppState>,
+    app_state: AppState,
 }
 
 impl WaylandManager {
-    fn new(config: WaylandConfig) -> Self {
+    fn new(conn: Connection, settings: Settings, state: DaemonState) -> Self {
+        let event_queue = conn.new_event_queue();
         Self {
-            monitor_manager: MonitorManager::new(),
-            config,
+            monitor_manager: MonitorManager::new(state, settings.scaling),
+            settings,
+            conn,
+            event_queue,
+            app_state: AppState::new(),
+        }
+    }
+
+    fn initialize(&mut self) -> Result<()> {
+        let qh = self.event_queue.handle();
+        let _registry = self.conn.display().get_registry(&qh, ());
+        self.event_queue.roundtrip(&mut self.app_state)?;
+        // xdg_output names arrive in response to requests made during the first roundtrip
+        self.event_queue.roundtrip(&mut self.app_state)?;
+
+        self.sync_outputs()?;
+        let total_surfaces = self.monitor_manager.len();
+
+        self.event_queue.roundtrip(&mut self.app_state)?;
+        while self.app_state.configured_count < total_surfaces {
+            tracing::debug!(
+                event = "waiting_layer_config",
+                configured = self.app_state.configured_count,
+                total = total_surfaces,
+                "Awaiting layer surface configuration"
+            );
+            self.event_queue.blocking_dispatch(&mut self.app_state)?;
+        }
+        self.event_queue.roundtrip(&mut self.app_state)?;
+
+        self.apply_surface_configs()
+    }
+
+    /// Reads and dispatches any pending Wayland events without blocking, then
+    /// reconciles surfaces with the current output list. Returns whether any
+    /// surface was created or destroyed.
+    fn dispatch_events(&mut self) -> Result<bool> {
+        self.event_queue.flush()?;
+        if let Some(guard) = self.event_queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(anyhow!("Failed to read Wayland events: {}", e)),
+            }
+        }
+        self.event_queue.dispatch_pending(&mut self.app_state)?;
+
+        for surface_id in std::mem::take(&mut self.app_state.frames_done) {
+            self.monitor_manager.frame_done(surface_id);
         }
+
+        let changed = self.sync_outputs()?;
+        self.apply_surface_configs()?;
+        Ok(changed)
+    }
+
+    /// Shows new media and records it in the saved state.
+    fn apply_media_change(&mut self, change: MediaChange) -> Result<()> {
+        self.monitor_manager.update_media(
+            change.monitors.as_deref(),
+            change.media_type,
+            change.mute,
+            &change.transition,
+        )?;
+        if let Err(e) = self.monitor_manager.state().save() {
+            tracing::warn!(event = "state_save_error", error = %e, "Failed to save daemon state");
+        }
+        Ok(())
+    }
+
+    /// Shows a playlist's next item, announcing it only once it is applied.
+    /// Items are not recorded in the saved state, so `Restore` and the next
+    /// start bring back the media last chosen by hand.
+    fn apply_playlist_change(&mut self, change: MediaChange) -> Result<()> {
+        let event = Event::PlaylistAdvanced {
+            monitors: change.monitors.clone(),
+            media: change.media_type.clone(),
+        };
+        self.monitor_manager.show_media(
+            change.monitors.as_deref(),
+            change.media_type,
+            change.mute,
+            &change.transition,
+        )?;
+        events::publish(event);
+        Ok(())
     }
 
-    fn initialize(&mut self, conn: &Connection) -> Result<()> {
-        let mut event_queue = conn.new_event_queue();
-        let qh = event_queue.handle();
-        let mut app_state = protocol::events::AppState::new();
+    fn sync_outputs(&mut self) -> Result<bool> {
+        let mut changed = false;
 
-        let _registry = conn.display().get_registry(&qh, ());
-        event_queue.roundtrip(&mut app_state)?;
+        for output_name in std::mem::take(&mut self.app_state.removed_outputs) {
+            if let Some(surface_id) = self.monitor_manager.remove_surface(&output_name)? {
+                self.app_state.layer_surface_configs.remove(&surface_id.0);
+                self.app_state.surface_to_output.remove(&surface_id.0);
+                tracing::info!(
+                    event = "output_removed",
+                    output = %output_name,
+                    "Tore down surface for disconnected output"
+                );
+                events::publish(Event::OutputRemoved { output: output_name });
+                changed = true;
+            }
+        }
 
-        if let Some(ref om) = app_state.output_manager {
-            for (id, info) in &app_state.outputs {
-                om.get_xdg_output(&info.output, &qh, *id);
+        let mut new_outputs: Vec<OutputInfo> = Vec::new();
+        for info in self.app_state.outputs.values() {
+            let Some(name) = info.name.as_deref() else {
+                continue;
+            };
+            if info.closed {
+                continue;
             }
+            if self.monitor_manager.contains(name) {
+                self.monitor_manager.update_display_config(name, &info.config);
+            } else {
+                new_outputs.push(info.clone());
+            }
+        }
+
+        if new_outputs.is_empty() {
+            return Ok(changed);
         }
-        event_queue.roundtrip(&mut app_state)?;
 
-        let compositor = app_state
+        let qh = self.event_queue.handle();
+        let compositor = self
+            .app_state
             .compositor
             .as_ref()
             .ok_or_else(|| anyhow!("Compositor not available"))?;
-        let layer_shell = app_state
+        let layer_shell = self
+            .app_state
             .layer_shell
             .as_ref()
             .ok_or_else(|| anyhow!("Layer shell not available"))?;
 
-        let mut total_surfaces = 0;
-        for output_info in app_state.outputs.values() {
-            if output_info.name.is_some() {
-                self.monitor_manager.create_surface(
-                    output_info,
-                    compositor,
-                    layer_shell,
-                    self.config.layer_name.as_deref(),
-                    MediaType::Shader("default".to_string()),
-                    conn,
-                    &qh,
-                    self.config.fps,
-                )?;
-                total_surfaces += 1;
+        for output_info in new_outputs {
+            let output_name = output_info.name.clone().unwrap_or_default();
+            let output_settings = self.settings.output(&output_name);
+            let assignment = self
+                .monitor_manager
+                .media_for(&output_name, output_settings.media.as_ref());
+            let volume = self
+                .monitor_manager
+                .volume_for(&output_name, output_settings.volume);
+            tracing::info!(
+                event = "output_added",
+                output = %output_name,
+                media_type = ?assignment.media_type,
+                fps = output_settings.fps,
+                "Creating surface for output"
+            );
+            self.monitor_manager.create_surface(
+                &output_info,
+                compositor,
+                layer_shell,
+                output_settings.layer.as_ref().map(|l| l.to_string()).as_deref(),
+                assignment,
+                &self.conn,
+                &qh,
+                output_settings.fps,
+                volume,
+            )?;
+            events::publish(Event::OutputAdded { output: output_name });
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    /// Re-reads the state file and shows its media again on the targeted
+    /// outputs, falling back to their config rule as at startup.
+    fn restore(&mut self, monitors: Option<&[String]>) -> Result<()> {
+        let saved = DaemonState::load()?;
+        let mut failures = Vec::new();
+        for output_name in self.monitor_manager.output_names() {
+            if monitors.is_some_and(|targets| !targets.contains(&output_name)) {
+                continue;
+            }
+            let rule = self.settings.output(&output_name).media;
+            let assignment = saved
+                .monitors
+                .get(&output_name)
+                .or(rule.as_ref())
+                .unwrap_or(&saved.default)
+                .clone();
+            let result = self.monitor_manager.update_media(
+                Some(std::slice::from_ref(&output_name)),
+                assignment.media_type,
+                assignment.mute,
+                &Transition::default(),
+            );
+            if let Err(e) = result {
+                tracing::warn!(event = "state_restore_output_error", output = %output_name, error = %e, "Failed to restore media on output");
+                failures.push(format!("{}: {}", output_name, e));
             }
         }
+        // Outputs restored before a failure keep their media
+        if let Err(e) = self.monitor_manager.state().save() {
+            tracing::warn!(event = "state_save_error", error = %e, "Failed to save daemon state");
+        }
+        if !failures.is_empty() {
+            return Err(anyhow!("Failed to restore media: {}", failures.join("; ")));
+        }
+        Ok(())
+    }
 
-        event_queue.roundtrip(&mut app_state)?;
-        while app_state.configured_count < total_surfaces {
-            tracing::debug!(
-                event = "waiting_layer_config",
-                configured = app_state.configured_count,
-                total = total_surfaces,
-                "Awaiting layer surface configuration"
+    /// Re-reads the config file and applies what changed for each connected
+    /// output. Rule media of every changed output is probed and its shader
+    /// compiled first; if any is broken nothing is applied and the running
+    /// settings stay. Otherwise the new settings take over, and outputs that
+    /// still fail to apply them are reported. Outputs whose layer changed are
+    /// recreated, since a layer surface cannot move between layers on every
+    /// compositor.
+    fn reload(&mut self) -> Result<()> {
+        let new_settings = self.settings.reload()?;
+
+        let mut changes = Vec::new();
+        let mut invalid = Vec::new();
+        for output_name in self.monitor_manager.output_names() {
+            let before = self.settings.output(&output_name);
+            let after = new_settings.output(&output_name);
+            if before == after {
+                continue;
+            }
+            if let Some(media) = after.media.as_ref().filter(|_| before.media != after.media) {
+                if let Err(e) = self.check_media(&media.media_type) {
+                    invalid.push(format!("{}: {}", output_name, e));
+                }
+            }
+            changes.push((output_name, before, after));
+        }
+        if !invalid.is_empty() {
+            return Err(anyhow!("Invalid config, nothing applied: {}", invalid.join("; ")));
+        }
+
+        // A changed rule gives outputs whose surface was closed another try
+        for info in self.app_state.outputs.values_mut() {
+            if let (true, Some(name)) = (info.closed, info.name.as_deref()) {
+                info.closed = self.settings.output(name) == new_settings.output(name);
+            }
+        }
+
+        self.settings = new_settings;
+        self.monitor_manager.set_scaling(self.settings.scaling);
+        crate::media::hwaccel::set_mode(self.settings.hwdec);
+
+        let mut failures = Vec::new();
+        for (output_name, before, after) in changes {
+            tracing::info!(
+                event = "config_output_changed",
+                output = %output_name,
+                ?before,
+                ?after,
+                "Applying changed config for output"
             );
-            event_queue.blocking_dispatch(&mut app_state)?;
+            if let Err(e) = self.apply_output_settings(&output_name, before, after) {
+                tracing::warn!(event = "config_output_error", output = %output_name, error = %e, "Failed to apply config for output");
+                failures.push(format!("{}: {}", output_name, e));
+            }
+        }
+
+        // Media set by rules is saved like any other, even if another output failed
+        if let Err(e) = self.monitor_manager.state().save() {
+            tracing::warn!(event = "state_save_error", error = %e, "Failed to save daemon state");
+        }
+
+        // Recreates surfaces removed above with their new layer
+        self.sync_outputs()?;
+        self.apply_surface_configs()?;
+
+        if !failures.is_empty() {
+            return Err(anyhow!("Failed to apply config: {}", failures.join("; ")));
+        }
+        Ok(())
+    }
+
+    /// Probes media and compiles its shader without showing it anywhere.
+    fn check_media(&self, media_type: &MediaType) -> Result<()> {
+        crate::media::probe::probe(media_type)?;
+        self.monitor_manager
+            .check_shaders(media_type, &Transition::default())
+    }
+
+    fn apply_output_settings(
+        &mut self,
+        output_name: &str,
+        before: OutputSettings,
+        after: OutputSettings,
+    ) -> Result<()> {
+        if before.layer != after.layer {
+            if let Some(surface_id) = self.monitor_manager.remove_surface(output_name)? {
+                self.app_state.layer_surface_configs.remove(&surface_id.0);
+                self.app_state.surface_to_output.remove(&surface_id.0);
+            }
+            if let Some(media) = after.media {
+                self.monitor_manager.assign(output_name, media);
+            }
+            return Ok(());
+        }
+
+        self.monitor_manager.set_fps(output_name, after.fps);
+        if before.volume != after.volume {
+            let volume = self.monitor_manager.volume_for(output_name, after.volume);
+            self.monitor_manager.set_surface_volume(output_name, volume);
+        }
+        if before.media != after.media {
+            if let Some(media) = after.media {
+                self.monitor_manager.update_media(
+                    Some(&[output_name.to_string()]),
+                    media.media_type,
+                    media.mute,
+                    &Transition::default(),
+                )?;
+            }
         }
-        event_queue.roundtrip(&mut app_state)?;
+        Ok(())
+    }
 
+    fn apply_surface_configs(&mut self) -> Result<()> {
         for surface in self.monitor_manager.surfaces_mut() {
-            if let Some((width, height)) =
-                app_state.layer_surface_configs.get(&surface.surface_id.0)
+            let Some(&(width, height)) =
+                self.app_state.layer_surface_configs.get(&surface.surface_id.0)
+            else {
+                continue;
+            };
+            if surface.configured
+                && surface.current_width == width
+                && surface.current_height == height
             {
-                tracing::info!(
-                    event = "surface_configured",
-                    output = %surface.output_name,
-                    width, height,
-                    "Applying initial layer surface config"
-                );
-                WaylandSurfaceTrait::resize(surface, *width, *height)?;
+                continue;
             }
-        }
 
+            tracing::info!(
+                event = "surface_configured",
+                output = %surface.output_name,
+                width, height,
+                "Applying layer surface config"
+            );
+            WaylandSurfaceTrait::resize(surface, width, height)?;
+            surface.configured = true;
+        }
         Ok(())
     }
 }
 
 pub fn init(
-    media_type: MediaType,
-    fps: u16,
-    layer_name: Option<&str>,
-    fifo_path: Option<&str>,
-    ipc_receiver: Receiver<MediaChange>,
-    mute: bool,
+    initial_state: DaemonState,
+    settings: Settings,
+    ipc_receiver: Receiver<DaemonRequest>,
 ) -> Result<()> {
     tracing::info!(
         event = "wayland_init",
-        fps,
-        layer = layer_name,
-        fifo = fifo_path,
-        mute,
+        fps = settings.fps,
+        layer = settings.layer_name(),
+        fifo = settings.fifo.as_deref(),
+        mute = settings.mute,
+        rules = settings.outputs.len(),
         "Initializing Wayland stack with audio manager"
     );
 
-    let config = WaylandConfig {
-        fps,
-        layer_name: layer_name.map(String::from),
-    };
-
     let conn = Connection::connect_to_env()?;
-    let mut wayland_manager = WaylandManager::new(config);
-    wayland_manager.initialize(&conn)?;
+    let mut audio_manager = AudioManager::new(settings.mute);
+    let mut fifo_reader = settings.fifo.as_deref().map(FifoReader::new).transpose()?;
+    let mut fps = settings.fps;
+    let mut power_monitor = PowerMonitor::new(settings.power.clone());
+    let mut playlists = PlaylistManager::new(&settings.playlists);
 
-    let mut audio_manager = AudioManager::new(mute);
+    let mut wayland_manager = WaylandManager::new(conn, settings, initial_state);
+    wayland_manager.initialize()?;
 
-    let mut fifo_reader = fifo_path.map(FifoReader::new).transpose()?;
-
-    let mut has_video = matches!(media_type, MediaType::Video { .. });
-    let mut current_media_type = media_type.clone();
-
-    audio_manager.handle_change(&current_media_type, false)?;
-
-    wayland_manager
+    let mut has_video = wayland_manager.monitor_manager.has_video();
+    let mut current_media_type = wayland_manager
         .monitor_manager
-        .set_swap_intervals(has_video, fps)?;
+        .state()
+        .default
+        .media_type
+        .clone();
 
     tracing::info!(
         event = "render_loop_start",
@@ -146,47 +481,213 @@ pub fn init(
         "Starting render loop with audio manager"
     );
 
+    let mut waiters: Vec<MediaWaiter> = Vec::new();
     let mut frame_count = 0u64;
     let mut last_fps_check = utils::get_time_millis();
-    let base_frame_time = if fps > 0 { 1000 / fps as u64 } else { 16 };
+    let mut base_frame_time = if fps > 0 { 1000 / fps as u64 } else { 16 };
 
     loop {
         let frame_start = utils::get_time_millis();
 
-        if let Ok(media_change) = ipc_receiver.try_recv() {
-            let new_has_video = matches!(media_change.media_type, MediaType::Video { .. });
-            if has_video != new_has_video {
-                has_video = new_has_video;
-                wayland_manager
-                    .monitor_manager
-                    .set_swap_intervals(has_video, fps)?;
-                tracing::info!(
-                    event = "swap_interval_reconfigured",
-                    has_video,
-                    "Reconfigured swap intervals due to media type change"
-                );
-            }
+        if wayland_manager.dispatch_events()? {
+            has_video = wayland_manager.monitor_manager.has_video();
+        }
 
-            current_media_type = media_change.media_type.clone();
+        let power_action = power_monitor.poll();
+        wayland_manager
+            .monitor_manager
+            .set_power_action(power_action, power_monitor.throttle_fps());
+
+        for change in playlists.poll(&wayland_manager.monitor_manager.output_names()) {
+            let media_type = change.media_type.clone();
+            // One bad item must not stop the daemon; the next one is tried on schedule
+            match wayland_manager.apply_playlist_change(change) {
+                Ok(()) => current_media_type = media_type,
+                Err(e) => {
+                    tracing::error!(event = "playlist_change_error", error = %e, "Failed to apply playlist item");
+                }
+            }
+            has_video = wayland_manager.monitor_manager.has_video();
+        }
 
-            audio_manager.handle_change(&media_change.media_type, media_change.mute)?;
+        match ipc_receiver.try_recv() {
+            Ok(DaemonRequest::Media { change, wait, reply }) => {
+                let checked = wayland_manager
+                    .monitor_manager
+                    .check_shaders(&change.media_type, &change.transition);
+                if let Err(e) = checked {
+                    tracing::warn!(event = "media_shader_error", error = %e, "Rejecting media whose shader failed to compile");
+                    let _ = reply.send(Err(IpcError::new(ErrorCode::ShaderCompile, e.to_string())));
+                } else {
+                    current_media_type = change.media_type.clone();
+                    // Media chosen by hand takes the outputs away from playlists
+                    playlists.release(change.monitors.as_deref());
+                    let monitors = change.monitors.clone();
+                    match wayland_manager.apply_media_change(change) {
+                        Ok(()) if wait => {
+                            let outputs = wayland_manager
+                                .monitor_manager
+                                .media_generations(monitors.as_deref());
+                            if outputs.is_empty() {
+                                // Nothing would ever be drawn to wait for
+                                let _ = reply.send(Err(IpcError::new(
+                                    ErrorCode::UnknownMonitor,
+                                    "No connected output to show the media on",
+                                )));
+                            } else {
+                                waiters.push(MediaWaiter::new(outputs, reply));
+                            }
+                        }
+                        Ok(()) => {
+                            let _ = reply.send(Ok(()));
+                        }
+                        Err(e) => {
+                            tracing::error!(event = "media_change_error", error = %e, "Failed to apply media change");
+                            // Unknown outputs come back as an `IpcError` with their own code
+                            let error = e
+                                .downcast::<IpcError>()
+                                .unwrap_or_else(|e| IpcError::new(ErrorCode::Internal, e.to_string()));
+                            let _ = reply.send(Err(error));
+                        }
+                    }
+                    has_video = wayland_manager.monitor_manager.has_video();
+                }
+            }
+            Ok(DaemonRequest::Playlist(request, reply)) => {
+                let outputs = wayland_manager.monitor_manager.output_names();
+                let result = match request {
+                    PlaylistRequest::Start(config) => playlists.start(config).map(|_| Vec::new()),
+                    PlaylistRequest::Step { delta, monitors } => {
+                        playlists.step(monitors.as_deref(), delta, &outputs)
+                    }
+                    PlaylistRequest::Pause { paused, monitors } => playlists
+                        .set_paused(monitors.as_deref(), paused)
+                        .map(|_| Vec::new()),
+                };
+                match result {
+                    Ok(changes) => {
+                        let mut result = Ok(());
+                        for change in changes {
+                            let media_type = change.media_type.clone();
+                            match wayland_manager.apply_playlist_change(change) {
+                                Ok(()) => current_media_type = media_type,
+                                Err(e) => {
+                                    tracing::error!(event = "playlist_change_error", error = %e, "Failed to apply playlist item");
+                                    result = result.and(Err(e.to_string()));
+                                }
+                            }
+                        }
+                        has_video = wayland_manager.monitor_manager.has_video();
+                        let _ = reply.send(result);
+                    }
+                    Err(e) => {
+                        tracing::warn!(event = "playlist_request_error", error = %e, "Playlist request failed");
+                        let _ = reply.send(Err(e.to_string()));
+                    }
+                }
+            }
+            Ok(DaemonRequest::Volume { volume, monitors, reply }) => {
+                let result = wayland_manager
+                    .monitor_manager
+                    .update_volume(monitors.as_deref(), volume);
+                if result.is_ok() {
+                    if let Err(e) = wayland_manager.monitor_manager.state().save() {
+                        tracing::warn!(event = "state_save_error", error = %e, "Failed to save daemon state");
+                    }
+                }
+                let _ = reply.send(result);
+            }
+            Ok(DaemonRequest::Uniforms { uniforms, monitors, reply }) => {
+                let result = wayland_manager
+                    .monitor_manager
+                    .update_uniforms(monitors.as_deref(), &uniforms);
+                if result.is_ok() {
+                    if let Err(e) = wayland_manager.monitor_manager.state().save() {
+                        tracing::warn!(event = "state_save_error", error = %e, "Failed to save daemon state");
+                    }
+                }
+                let _ = reply.send(result);
+            }
+            Ok(DaemonRequest::Pause { paused, monitors, reply }) => {
+                let result = wayland_manager
+                    .monitor_manager
+                    .set_paused(monitors.as_deref(), paused);
+                let _ = reply.send(result);
+            }
+            Ok(DaemonRequest::Status(reply)) => {
+                let mut status = wayland_manager.monitor_manager.status();
+                for monitor in &mut status {
+                    monitor.playlist = playlists.is_running_on(&monitor.output.name);
+                }
+                let _ = reply.send(status);
+            }
+            Ok(DaemonRequest::Reload(reply)) => {
+                let old_fifo = wayland_manager.settings.fifo.clone();
+                let result = wayland_manager.reload();
+                if let Err(ref e) = result {
+                    tracing::error!(event = "config_reload_error", error = %e, "Failed to reload config");
+                }
 
-            let target_monitors = media_change.monitors.as_deref();
-            wayland_manager.monitor_manager.update_media(
-                target_monitors,
-                media_change.media_type,
-                fps,
-            )?;
+                // Settings may have taken over even when some output failed
+                // to apply them, and surfaces may have been recreated
+                let settings = &wayland_manager.settings;
+                fps = settings.fps;
+                base_frame_time = if fps > 0 { 1000 / fps as u64 } else { 16 };
+                audio_manager.set_global_mute(settings.mute);
+                if settings.fifo != old_fifo {
+                    fifo_reader = match settings.fifo.as_deref().map(FifoReader::new).transpose() {
+                        Ok(reader) => reader,
+                        Err(e) => {
+                            tracing::warn!(event = "fifo_open_error", error = %e, "Failed to open FIFO");
+                            None
+                        }
+                    };
+                }
+                power_monitor.set_policy(settings.power.clone());
+                playlists.set_config(&settings.playlists);
+                has_video = wayland_manager.monitor_manager.has_video();
+                if result.is_ok() {
+                    tracing::info!(event = "config_reloaded", fps, "Config reloaded");
+                }
+                let _ = reply.send(result.map_err(|e| e.to_string()));
+            }
+            Ok(DaemonRequest::Restore { monitors, reply }) => {
+                playlists.release(monitors.as_deref());
+                let result = wayland_manager.restore(monitors.as_deref());
+                if let Err(ref e) = result {
+                    tracing::error!(event = "state_restore_error", error = %e, "Failed to restore saved media");
+                }
+                has_video = wayland_manager.monitor_manager.has_video();
+                let _ = reply.send(result.map_err(|e| e.to_string()));
+            }
+            Ok(DaemonRequest::Shutdown) => {
+                tracing::info!(event = "daemon_shutdown", "Shutting down on request");
+                return Ok(());
+            }
+            Err(_) => {}
         }
 
+        let qh = wayland_manager.event_queue.handle();
         let any_video_updated = wayland_manager
             .monitor_manager
-            .render_all(fifo_reader.as_mut(), &mut audio_manager)?;
+            .render_all(fifo_reader.as_mut(), &audio_manager, &qh)?;
+        waiters.retain_mut(|waiter| !waiter.poll(&wayland_manager.monitor_manager));
+        let idle = wayland_manager.monitor_manager.is_idle();
 
         frame_count += 1;
 
         let elapsed = utils::get_time_millis() - frame_start;
+        // A video on any output sets the pace for all of them
         let target_time = match &current_media_type {
+            // Paused or hidden everywhere: only wake for IPC and frame callbacks
+            _ if idle => IDLE_FRAME_TIME_MS,
+            _ if has_video => {
+                if fps == 0 {
+                    if any_video_updated { 16 } else { 33 }
+                } else {
+                    base_frame_time
+                }
+            }
             MediaType::Shader(_) => {
                 if fps == 0 {
                     16
@@ -194,7 +695,7 @@ pub fn init(
                     base_frame_time
                 }
             }
-            MediaType::Video { .. } => {
+            MediaType::Video { .. } | MediaType::Animation { .. } => {
                 if fps == 0 {
                     if any_video_updated { 16 } else { 33 }
                 } else {
@@ -217,13 +718,13 @@ pub fn init(
         if frame_count % 300 == 0 {
             let now = utils::get_time_millis();
             let actual_fps = 300000 / (now - last_fps_check + 1);
-            if actual_fps < (fps as u64 * 80 / 100) && fps > 0 {
+            if actual_fps < (fps as u64 * 80 / 100) && fps > 0 && !idle {
                 tracing::warn!(
                     event = "performance_degradation",
                     expected_fps = fps,
                     actual_fps,
                     media_type = ?current_media_type,
-                    audio_playing = audio_manager.is_playing(),
+                    has_video,
                     "Performance below expected threshold"
                 );
             }
@@ -1,53 +1,290 @@
-use crate::media::MediaType;
+use crate::events::{self, Event};
+use crate::gl_bindings as gl;
+use crate::ipc::{ErrorCode, IpcError, IpcResult, MonitorInfo, MonitorStatus};
+use crate::media::passes::Uniforms;
+use crate::media::transition::{TransitionEffect, TransitionKind};
+use crate::media::{self, BaseMediaHandler, FitMode, MediaType, ScalingMode, Transition};
+use crate::power::PowerAction;
+use crate::state::{DaemonState, MediaAssignment};
+use crate::utils;
 use crate::wayland::audio::{AudioManager, FifoReader};
 use crate::wayland::protocol::events::AppState;
+use crate::wayland::rendering::{MediaCache, MediaLoad};
+use crate::wayland::rendering::cache::{SourceKey, SourceUse};
 use crate::wayland::rendering::surface::WaylandSurface;
 use crate::wayland::traits::WaylandSurface as WaylandSurfaceTrait;
-use crate::wayland::types::{OutputInfo, RenderContext};
-use anyhow::Result;
+use crate::wayland::types::{
+    DisplayConfig, EglResources, OutputInfo, RenderContext, SpanView, SurfaceId,
+};
+use anyhow::{Result, anyhow};
 use khronos_egl as egl;
 use std::collections::HashMap;
 use wayland_client::protocol::wl_compositor;
 use wayland_client::{Connection, QueueHandle};
 use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1;
 
+/// How long a frame callback may be outstanding before the surface counts as
+/// hidden and its playback is paused.
+const HIDDEN_AFTER_MS: u64 = 500;
+
 pub struct MonitorManager {
     surfaces: HashMap<String, WaylandSurface>,
     egl_instance: egl::Instance<egl::Static>,
+    state: DaemonState,
+    scaling: ScalingMode,
+    /// Set by the power policy: every output stops drawing
+    frozen: bool,
+    /// Set by the power policy: every output draws at most this often
+    fps_cap: Option<u16>,
+    /// Surfaceless context that every surface's context shares objects with,
+    /// so media decoded once can be drawn on every output. It outlives the
+    /// surfaces, keeping the share group alive as outputs come and go.
+    share_context: Option<EglResources>,
+    /// Videos and spanned images, decoded in the share context
+    media_cache: MediaCache,
 }
 
 impl MonitorManager {
-    pub fn new() -> Self {
+    pub fn new(state: DaemonState, scaling: ScalingMode) -> Self {
         Self {
             surfaces: HashMap::new(),
             egl_instance: egl::Instance::new(egl::Static),
+            state,
+            scaling,
+            frozen: false,
+            fps_cap: None,
+            share_context: None,
+            media_cache: MediaCache::new(),
+        }
+    }
+
+    pub fn set_power_action(&mut self, action: Option<PowerAction>, throttle_fps: u16) {
+        self.frozen = action == Some(PowerAction::Freeze);
+        self.fps_cap = (action == Some(PowerAction::Throttle)).then_some(throttle_fps.max(1));
+    }
+
+    pub fn set_paused(&mut self, target_monitors: Option<&[String]>, paused: bool) -> IpcResult<()> {
+        self.check_targets(target_monitors)?;
+        let targets: Vec<String> = match target_monitors {
+            Some(names) => names.to_vec(),
+            None => self.surfaces.keys().cloned().collect(),
+        };
+        tracing::info!(event = "playback_pause", targets = ?targets, paused, "Changing playback state");
+        for name in targets {
+            match self.surfaces.get_mut(&name) {
+                Some(surface) => {
+                    if surface.paused != paused {
+                        let output = name.clone();
+                        let event = if paused { Event::Paused { output } } else { Event::Resumed { output } };
+                        events::publish(event);
+                    }
+                    surface.paused = paused;
+                }
+                None => tracing::warn!(event = "monitors_not_found", monitor = %name, "Cannot pause unknown monitor"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a frame callback for the surface with protocol id `surface_id`.
+    pub fn frame_done(&mut self, surface_id: u32) {
+        let Some(surface) = self.surfaces.values_mut().find(|s| s.surface_id.0 == surface_id) else {
+            return;
+        };
+        surface.frame_pending = false;
+        if surface.hidden {
+            surface.hidden = false;
+            tracing::info!(event = "surface_visible", output = %surface.output_name, "Surface visible again, resuming");
+        }
+    }
+
+    /// Whether no output is drawing, so the render loop can sleep longer.
+    pub fn is_idle(&self) -> bool {
+        self.surfaces.values().all(|s| s.renderer.is_paused() && !s.renderer.has_pending_media())
+    }
+
+    /// Filter applied to media that is set without one from now on.
+    pub fn set_scaling(&mut self, scaling: ScalingMode) {
+        self.scaling = scaling;
+    }
+
+    pub fn state(&self) -> &DaemonState {
+        &self.state
+    }
+
+    pub fn contains(&self, output_name: &str) -> bool {
+        self.surfaces.contains_key(output_name)
+    }
+
+    pub fn output_names(&self) -> Vec<String> {
+        self.surfaces.keys().cloned().collect()
+    }
+
+    /// Media that a surface for `output_name` should show. An explicit runtime
+    /// assignment wins over the config rule, which wins over the last
+    /// all-monitors assignment. Outputs targeted by name before they were
+    /// connected keep that assignment.
+    pub fn media_for(&self, output_name: &str, rule: Option<&MediaAssignment>) -> MediaAssignment {
+        self.state
+            .monitors
+            .get(output_name)
+            .or(rule)
+            .unwrap_or(&self.state.default)
+            .clone()
+    }
+
+    pub fn assign(&mut self, output_name: &str, assignment: MediaAssignment) {
+        self.state.monitors.insert(output_name.to_string(), assignment);
+    }
+
+    /// Whether any output shows video or an animation, which sets the pace of
+    /// the render loop.
+    pub fn has_video(&self) -> bool {
+        self.surfaces.values().any(|surface| {
+            matches!(
+                surface.renderer.media_type(),
+                MediaType::Video { .. } | MediaType::Animation { .. }
+            )
+        })
+    }
+
+    /// Volume for `output_name`: a runtime `SetVolume` wins over the config rule.
+    pub fn volume_for(&self, output_name: &str, rule_volume: f32) -> f32 {
+        self.state.volumes.get(output_name).copied().unwrap_or(rule_volume)
+    }
+
+    pub fn set_surface_volume(&mut self, output_name: &str, volume: f32) {
+        if let Some(surface) = self.surfaces.get_mut(output_name) {
+            surface.volume = volume;
         }
     }
 
+    pub fn update_volume(&mut self, target_monitors: Option<&[String]>, volume: f32) -> IpcResult<()> {
+        self.check_targets(target_monitors)?;
+        let volume = volume.clamp(0.0, 1.0);
+        let targets: Vec<String> = match target_monitors {
+            Some(names) => names.to_vec(),
+            None => self.surfaces.keys().cloned().collect(),
+        };
+        tracing::info!(event = "volume_update", targets = ?targets, volume, "Updating monitor volume");
+        for name in targets {
+            self.set_surface_volume(&name, volume);
+            self.state.volumes.insert(name, volume);
+        }
+        Ok(())
+    }
+
+    /// Replaces the shader uniforms of the targeted outputs.
+    pub fn update_uniforms(
+        &mut self,
+        target_monitors: Option<&[String]>,
+        uniforms: &Uniforms,
+    ) -> IpcResult<()> {
+        self.check_targets(target_monitors)?;
+        let targets: Vec<String> = match target_monitors {
+            Some(names) => names.to_vec(),
+            None => self.surfaces.keys().cloned().collect(),
+        };
+        tracing::info!(event = "uniforms_update", targets = ?targets, count = uniforms.len(), "Updating shader uniforms");
+        for name in targets {
+            if let Some(surface) = self.surfaces.get_mut(&name) {
+                surface.uniforms.clone_from(uniforms);
+            }
+            if uniforms.is_empty() {
+                self.state.uniforms.remove(&name);
+            } else {
+                self.state.uniforms.insert(name, uniforms.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with `UnknownMonitor` when outputs are named and none of them is
+    /// connected. Commands naming some outputs that are not still apply to
+    /// the others.
+    pub fn check_targets(&self, target_monitors: Option<&[String]>) -> IpcResult<()> {
+        match target_monitors {
+            Some(targets) if !targets.iter().any(|name| self.surfaces.contains_key(name)) => {
+                let mut available: Vec<&String> = self.surfaces.keys().collect();
+                available.sort();
+                Err(IpcError::new(
+                    ErrorCode::UnknownMonitor,
+                    format!(
+                        "No connected output among [{}]; connected: [{}]",
+                        targets.join(", "),
+                        available.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_fps(&mut self, output_name: &str, fps: u16) {
+        if let Some(surface) = self.surfaces.get_mut(output_name) {
+            surface.fps = fps;
+        }
+    }
+
+    pub fn update_display_config(&mut self, output_name: &str, config: &DisplayConfig) {
+        if let Some(surface) = self.surfaces.get_mut(output_name) {
+            surface.display_config.clone_from(config);
+        }
+    }
+
+    pub fn remove_surface(&mut self, output_name: &str) -> Result<Option<SurfaceId>> {
+        let Some(surface) = self.surfaces.remove(output_name) else {
+            return Ok(None);
+        };
+        let surface_id = surface.surface_id;
+        surface.destroy(&self.egl_instance)?;
+        Ok(Some(surface_id))
+    }
+
     pub fn create_surface(
         &mut self,
         output_info: &OutputInfo,
         compositor: &wl_compositor::WlCompositor,
         layer_shell: &zwlr_layer_shell_v1::ZwlrLayerShellV1,
         layer_name: Option<&str>,
-        media_type: MediaType,
+        assignment: MediaAssignment,
         conn: &Connection,
         qh: &QueueHandle<AppState>,
         fps: u16,
+        volume: f32,
     ) -> Result<()> {
-        let surface = WaylandSurface::new(
+        let share_context = match &self.share_context {
+            Some(resources) => resources.context,
+            None => {
+                let resources = WaylandSurface::create_egl_resources(&self.egl_instance, conn, None)?;
+                let context = resources.context;
+                self.share_context = Some(resources);
+                context
+            }
+        };
+        let mut surface = WaylandSurface::new(
             output_info,
             compositor,
             layer_shell,
             layer_name,
-            media_type,
+            media::with_animation(assignment.media_type.with_default_filter(self.scaling)),
             &self.egl_instance,
             conn,
             qh,
             fps,
+            Some(share_context),
         )?;
+        surface.mute = assignment.mute;
+        surface.volume = volume;
+        // Frame callbacks pace drawing; EGL must not also block in
+        // eglSwapBuffers waiting for its own
+        self.egl_instance
+            .swap_interval(surface.egl_resources.display, 0)?;
 
         let output_name = surface.get_output_name().to_string();
+        if let Some(uniforms) = self.state.uniforms.get(&output_name) {
+            surface.uniforms.clone_from(uniforms);
+        }
         self.surfaces.insert(output_name, surface);
         Ok(())
     }
@@ -56,13 +293,74 @@ impl MonitorManager {
         self.surfaces.values_mut()
     }
 
+    /// Shows `media_type` on the targeted outputs, all of them when `None`.
+    /// Fails with an [`IpcError`] when none of the named outputs is connected.
+    /// Only outputs that switched have the media recorded in the state.
     pub fn update_media(
         &mut self,
         target_monitors: Option<&[String]>,
         media_type: MediaType,
-        fps: u16,
+        mute: bool,
+        transition: &Transition,
     ) -> Result<()> {
+        self.check_targets(target_monitors)?;
+        let assignment = MediaAssignment::new(media_type.clone(), mute);
+        let (switched, result) = self.apply_media(target_monitors, media_type, mute, transition);
         match target_monitors {
+            // Pin the connected outputs so config rules do not override this
+            // choice after a restart; outputs connected later fall back to
+            // their rule or this default.
+            None if result.is_ok() => {
+                self.state.monitors = switched
+                    .into_iter()
+                    .map(|name| (name, assignment.clone()))
+                    .collect();
+                self.state.default = assignment;
+            }
+            None => {
+                for name in switched {
+                    self.state.monitors.insert(name, assignment.clone());
+                }
+            }
+            // Outputs named before they are connected keep the assignment
+            // for when they are
+            Some(target_names) => {
+                for name in target_names {
+                    if switched.contains(name) || !self.surfaces.contains_key(name) {
+                        self.state.monitors.insert(name.clone(), assignment.clone());
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Shows `media_type` like [`Self::update_media`] without recording it in
+    /// the state, for media such as playlist items that is not to be restored.
+    pub fn show_media(
+        &mut self,
+        target_monitors: Option<&[String]>,
+        media_type: MediaType,
+        mute: bool,
+        transition: &Transition,
+    ) -> Result<()> {
+        self.check_targets(target_monitors)?;
+        let (_, result) = self.apply_media(target_monitors, media_type, mute, transition);
+        result
+    }
+
+    /// Switches the renderers of the targeted outputs to `media_type`. An
+    /// output that fails does not stop the others; returns the outputs that
+    /// switched along with the failures.
+    fn apply_media(
+        &mut self,
+        target_monitors: Option<&[String]>,
+        media_type: MediaType,
+        mute: bool,
+        transition: &Transition,
+    ) -> (Vec<String>, Result<()>) {
+        let media_type = media::with_animation(media_type.with_default_filter(self.scaling));
+        let targets: Vec<String> = match target_monitors {
             None => {
                 tracing::info!(
                     event = "media_update_all",
@@ -71,14 +369,7 @@ impl MonitorManager {
                     available_monitors = ?self.surfaces.keys().collect::<Vec<_>>(),
                     "Updating media on all monitors"
                 );
-                for (monitor_name, surface) in &mut self.surfaces {
-                    tracing::debug!(
-                        event = "media_update_monitor",
-                        monitor = %monitor_name,
-                        "Applying media to monitor"
-                    );
-                    surface.renderer.update_media(media_type.clone(), fps)?;
-                }
+                self.surfaces.keys().cloned().collect()
             }
             Some(target_names) => {
                 tracing::info!(
@@ -88,79 +379,157 @@ impl MonitorManager {
                     available_monitors = ?self.surfaces.keys().collect::<Vec<_>>(),
                     "Updating media on specific monitors"
                 );
-
-                let mut found_monitors = Vec::new();
-                let mut missing_monitors = Vec::new();
-
-                for target_name in target_names {
-                    if let Some(surface) = self.surfaces.get_mut(target_name) {
-                        tracing::debug!(
-                            event = "media_update_monitor",
-                            monitor = %target_name,
-                            "Applying media to target monitor"
-                        );
-                        surface.renderer.update_media(media_type.clone(), fps)?;
-                        found_monitors.push(target_name);
-                    } else {
-                        missing_monitors.push(target_name);
-                    }
-                }
-
-                if !missing_monitors.is_empty() {
+                let (found, missing): (Vec<&String>, Vec<&String>) = target_names
+                    .iter()
+                    .partition(|name| self.surfaces.contains_key(*name));
+                if !missing.is_empty() {
                     tracing::warn!(
                         event = "monitors_not_found",
-                        missing = ?missing_monitors,
-                        found = ?found_monitors,
+                        ?missing,
+                        ?found,
                         available = ?self.surfaces.keys().collect::<Vec<_>>(),
                         "Some target monitors were not found"
                     );
                 }
+                found.into_iter().cloned().collect()
+            }
+        };
 
-                if found_monitors.is_empty() {
-                    tracing::error!(
-                        event = "no_monitors_updated",
-                        targets = ?target_names,
-                        available = ?self.surfaces.keys().collect::<Vec<_>>(),
-                        "No target monitors were found - no media was updated"
-                    );
+        let mut switched = Vec::new();
+        let mut failures = Vec::new();
+        for name in targets {
+            let Some(surface) = self.surfaces.get_mut(&name) else {
+                continue;
+            };
+            tracing::debug!(
+                event = "media_update_monitor",
+                monitor = %name,
+                "Applying media to monitor"
+            );
+            match surface
+                .renderer
+                .update_media(media_type.clone(), surface.fps, transition.clone())
+            {
+                Ok(()) => {
+                    surface.mute = mute;
+                    events::publish(Event::MediaChanged {
+                        output: name.clone(),
+                        media: media_type.clone(),
+                    });
+                    switched.push(name);
+                }
+                Err(e) => {
+                    tracing::warn!(event = "media_update_error", monitor = %name, error = %e, "Failed to update media on monitor");
+                    failures.push(format!("{}: {}", name, e));
                 }
             }
         }
-        Ok(())
-    }
 
-    pub fn set_swap_intervals(&self, has_video: bool, fps: u16) -> Result<()> {
-        for surface in self.surfaces.values() {
-            let interval = match (has_video, fps) {
-                (true, _) => 1,                         // Video content always uses vsync
-                (false, 0) => 0,                        // Unlimited FPS for shaders when fps=0
-                (false, fps_val) if fps_val >= 60 => 1, // Use vsync for high FPS
-                (false, _) => 0,                        // Lower FPS can run unlocked
-            };
+        let result = if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to update media on {}", failures.join("; ")))
+        };
+        (switched, result)
+    }
 
-            self.egl_instance
-                .swap_interval(surface.egl_resources.display, interval)?;
+    /// Compiles the custom shader and transition of a media change in the
+    /// share context, so a broken one is reported before any output switches
+    /// to it.
+    pub fn check_shaders(&self, media_type: &MediaType, transition: &Transition) -> Result<()> {
+        let Some(share) = &self.share_context else {
+            // Nothing to compile with before the first output connects
+            return Ok(());
+        };
+        let shader = match media_type {
+            MediaType::Shader(path) => Some(path),
+            MediaType::Image { shader, .. }
+            | MediaType::Video { shader, .. }
+            | MediaType::Animation { shader, .. } => shader.as_ref(),
+        };
+        let custom_transition = matches!(transition.kind, TransitionKind::Custom(_));
+        if shader.is_none_or(|path| path == "default") && !custom_transition {
+            return Ok(());
+        }
 
-            tracing::debug!(
-                event = "swap_interval_set",
-                monitor = %surface.output_name,
-                interval,
-                has_video,
-                fps,
-                "Set swap interval for optimal performance"
-            );
+        let failed = |path: &str, e: anyhow::Error| {
+            events::publish(Event::ShaderError {
+                path: path.to_string(),
+                error: e.to_string(),
+            });
+            e
+        };
+        self.egl_instance
+            .make_current(share.display, None, None, Some(share.context))?;
+        if let Some(path) = shader.filter(|path| *path != "default") {
+            BaseMediaHandler::check_shader(path).map_err(|e| failed(path, e))?;
+        }
+        if let TransitionKind::Custom(path) = &transition.kind {
+            TransitionEffect::new(transition.clone()).map_err(|e| failed(path, e))?;
         }
         Ok(())
     }
 
-    pub fn render_all(&mut self, mut fifo_reader: Option<&mut FifoReader>, audio_manager: &mut AudioManager) -> Result<bool> {
-        let mut any_updated = false;
-        let mut video_restarted = false;
+    /// Media generation of each connected output among the targets, to
+    /// follow a media change until it is shown.
+    pub fn media_generations(&self, target_monitors: Option<&[String]>) -> Vec<(String, u64)> {
+        self.surfaces
+            .iter()
+            .filter(|(name, _)| target_monitors.is_none_or(|targets| targets.contains(name)))
+            .map(|(name, surface)| (name.clone(), surface.renderer.generation()))
+            .collect()
+    }
 
-        let surface_names: Vec<String> = self.surfaces.keys().cloned().collect();
+    /// `None` once the output is disconnected.
+    pub fn media_load(&self, output_name: &str, generation: u64) -> Option<MediaLoad> {
+        let surface = self.surfaces.get(output_name)?;
+        Some(surface.renderer.media_load(generation))
+    }
 
+    pub fn render_all(
+        &mut self,
+        mut fifo_reader: Option<&mut FifoReader>,
+        audio_manager: &AudioManager,
+        qh: &QueueHandle<AppState>,
+    ) -> Result<bool> {
+        let now = utils::get_time_millis();
+        let span_views = self.span_views();
+
+        // Pause state is settled first, since shared video only pauses once
+        // every surface showing it is paused
+        for surface in self.surfaces.values_mut() {
+            // Drawing before the first layer surface configure is a protocol error
+            if !surface.configured {
+                continue;
+            }
+            if surface.frame_pending
+                && !surface.hidden
+                && now.saturating_sub(surface.last_draw) > HIDDEN_AFTER_MS
+            {
+                surface.hidden = true;
+                tracing::info!(event = "surface_hidden", output = %surface.output_name, "No frame callback, pausing hidden surface");
+            }
+            let paused = surface.paused || surface.hidden || self.frozen;
+            surface.renderer.set_paused(paused);
+        }
+
+        let mut any_updated = self.update_media_cache(audio_manager, &span_views)?;
+
+        let mut surface_names: Vec<String> = self.surfaces.keys().cloned().collect();
+        surface_names.sort();
         for surface_name in surface_names {
             if let Some(surface) = self.surfaces.get_mut(&surface_name) {
+                if !surface.configured || surface.frame_pending {
+                    continue;
+                }
+                if surface.renderer.is_paused() && !surface.renderer.has_pending_media() {
+                    continue;
+                }
+                if !surface.frame_due(now, self.fps_cap) {
+                    continue;
+                }
+                surface.last_draw = now;
+
                 self.egl_instance.make_current(
                     surface.egl_resources.display,
                     Some(surface.egl_resources.surface),
@@ -172,21 +541,21 @@ impl MonitorManager {
                     any_updated = true;
                 }
 
-                // Check for video restart
-                if surface.renderer.check_video_restart() {
-                    video_restarted = true;
-                }
-
                 let mut surface_context = RenderContext {
                     width: surface.current_width as i32,
                     height: surface.current_height as i32,
                     fifo_reader: fifo_reader.as_deref_mut(),
+                    span: span_views.get(&surface_name).copied(),
+                    media_cache: &self.media_cache,
+                    uniforms: &surface.uniforms,
                 };
 
                 surface.renderer.draw(&mut surface_context)?;
 
+                surface.request_frame(qh);
                 self.egl_instance
                     .swap_buffers(surface.egl_resources.display, surface.egl_resources.surface)?;
+                surface.fps_counter.tick();
 
                 tracing::trace!(
                     event = "surface_rendered",
@@ -198,11 +567,158 @@ impl MonitorManager {
             }
         }
 
-        if video_restarted {
-            audio_manager.handle_video_restart()?;
+        Ok(any_updated)
+    }
+
+    /// Decodes the next frame of every video and spanned image once, in the
+    /// share context, for all the surfaces showing it. When several outputs
+    /// show the same video only the first, by name, that can play its audio
+    /// does, so the track is not heard twice.
+    fn update_media_cache(
+        &mut self,
+        audio_manager: &AudioManager,
+        span_views: &HashMap<String, SpanView>,
+    ) -> Result<bool> {
+        let mut uses: HashMap<SourceKey, SourceUse> = HashMap::new();
+        let mut names: Vec<&String> = self.surfaces.keys().collect();
+        names.sort();
+        for name in names {
+            let surface = &self.surfaces[name];
+            // Spanned media is resampled for the whole layout
+            let size = match span_views.get(name) {
+                Some(view) => (view.width, view.height),
+                None => (surface.current_width, surface.current_height),
+            };
+            let paused = surface.renderer.is_paused();
+            let audible = !audio_manager.is_muted() && !surface.mute && surface.volume > 0.0 && !paused;
+            let current = SourceKey::for_media(surface.renderer.media_type());
+
+            for media_type in surface.renderer.cached_sources() {
+                let Some(key) = SourceKey::for_media(media_type) else {
+                    continue;
+                };
+                let source = uses
+                    .entry(key.clone())
+                    .or_insert_with(|| SourceUse::new(media_type.clone()));
+                source.add_user(media_type.fit().unwrap_or_default(), surface.fps);
+                source.size = (source.size.0.max(size.0), source.size.1.max(size.1));
+                source.paused &= paused;
+                // Media being transitioned away from stays silent
+                if audible && source.audio.is_none() && current.as_ref() == Some(&key) {
+                    source.audio = Some((name.clone(), surface.volume, audio_manager.sink()));
+                }
+            }
         }
 
-        Ok(any_updated)
+        if uses.is_empty() && self.media_cache.is_empty() {
+            return Ok(false);
+        }
+        let Some(share) = &self.share_context else {
+            return Ok(false);
+        };
+        self.egl_instance
+            .make_current(share.display, None, None, Some(share.context))?;
+        let updated = self.media_cache.update(uses);
+        // Uploads must reach the other contexts of the share group before they draw
+        unsafe {
+            gl::Flush();
+        }
+        Ok(updated)
+    }
+
+    /// Places each output showing spanned media in the bounding box of the
+    /// layout of all outputs showing the same media.
+    fn span_views(&self) -> HashMap<String, SpanView> {
+        let mut groups: Vec<(&MediaType, Vec<&WaylandSurface>)> = Vec::new();
+        for surface in self.surfaces.values() {
+            let media_type = surface.renderer.media_type();
+            let spanned = matches!(
+                media_type,
+                MediaType::Image { fit, .. } | MediaType::Video { fit, .. } | MediaType::Animation { fit, .. }
+                    if fit.mode == FitMode::Span
+            );
+            if !spanned {
+                continue;
+            }
+            match groups.iter_mut().find(|(m, _)| *m == media_type) {
+                Some((_, members)) => members.push(surface),
+                None => groups.push((media_type, vec![surface])),
+            }
+        }
+
+        let mut views = HashMap::new();
+        for (_, mut members) in groups {
+            members.sort_by(|a, b| a.output_name.cmp(&b.output_name));
+
+            // Outputs without an xdg-output position are laid out left to right
+            let mut next_x = 0;
+            let rects: Vec<(i32, i32, i32, i32)> = members
+                .iter()
+                .map(|surface| {
+                    let config = &surface.display_config;
+                    let width = config.logical_width.unwrap_or(surface.current_width) as i32;
+                    let height = config.logical_height.unwrap_or(surface.current_height) as i32;
+                    let x = config.logical_x.unwrap_or(next_x);
+                    let y = config.logical_y.unwrap_or(0);
+                    next_x = next_x.max(x + width);
+                    (x, y, width.max(1), height.max(1))
+                })
+                .collect();
+
+            let left = rects.iter().map(|r| r.0).min().unwrap_or(0);
+            let top = rects.iter().map(|r| r.1).min().unwrap_or(0);
+            let right = rects.iter().map(|r| r.0 + r.2).max().unwrap_or(1);
+            let bottom = rects.iter().map(|r| r.1 + r.3).max().unwrap_or(1);
+            let (span_w, span_h) = ((right - left).max(1) as f32, (bottom - top).max(1) as f32);
+
+            for (surface, (x, y, width, height)) in members.iter().zip(rects) {
+                // Layout size in this output's pixels
+                let density_x = surface.current_width as f32 / width as f32;
+                let density_y = surface.current_height as f32 / height as f32;
+                let view = SpanView {
+                    rect: [
+                        (x - left) as f32 / span_w,
+                        (y - top) as f32 / span_h,
+                        (x + width - left) as f32 / span_w,
+                        (y + height - top) as f32 / span_h,
+                    ],
+                    width: (span_w * density_x).round() as u32,
+                    height: (span_h * density_y).round() as u32,
+                };
+                views.insert(surface.output_name.clone(), view);
+            }
+        }
+        views
+    }
+
+    pub fn status(&self) -> Vec<MonitorStatus> {
+        let mut statuses: Vec<MonitorStatus> = self
+            .surfaces
+            .values()
+            .map(|surface| MonitorStatus {
+                output: MonitorInfo {
+                    name: surface.output_name.clone(),
+                    width: surface.current_width,
+                    height: surface.current_height,
+                    scale: surface.display_config.scale,
+                    transform: surface.display_config.transform_name().to_string(),
+                    x: surface.display_config.logical_x,
+                    y: surface.display_config.logical_y,
+                    logical_width: surface.display_config.logical_width,
+                    logical_height: surface.display_config.logical_height,
+                },
+                media: surface.renderer.media_type().clone(),
+                playback: surface.renderer.playback_state(),
+                fps: surface.fps_counter.fps(),
+                volume: surface.volume,
+                audio: SourceKey::for_media(surface.renderer.media_type())
+                    .is_some_and(|key| self.media_cache.plays_audio_for(&key, &surface.output_name)),
+                playlist: false,
+                shader_error: surface.renderer.shader_error().map(str::to_string),
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.output.name.cmp(&b.output.name));
+        statuses
     }
 
     pub fn len(&self) -> usize {
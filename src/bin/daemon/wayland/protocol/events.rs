@@ -1,7 +1,9 @@
 use crate::wayland::types::{DisplayConfig, OutputInfo};
 use std::collections::HashMap;
 use tracing::{debug, info};
-use wayland_client::protocol::{wl_compositor, wl_output, wl_region, wl_registry, wl_surface};
+use wayland_client::protocol::{
+    wl_callback, wl_compositor, wl_output, wl_region, wl_registry, wl_surface,
+};
 use wayland_client::{Connection, Dispatch, Proxy, QueueHandle};
 use wayland_protocols::xdg::xdg_output::zv1::client::{zxdg_output_manager_v1, zxdg_output_v1};
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
@@ -14,6 +16,9 @@ pub struct AppState {
     pub configured_count: usize,
     pub layer_surface_configs: HashMap<u32, (u32, u32)>,
     pub surface_to_output: HashMap<u32, String>,
+    pub removed_outputs: Vec<String>,
+    /// Surfaces whose frame callback fired since the last dispatch
+    pub frames_done: Vec<u32>,
 }
 
 impl AppState {
@@ -26,6 +31,19 @@ impl AppState {
             configured_count: 0,
             layer_surface_configs: HashMap::new(),
             surface_to_output: HashMap::new(),
+            removed_outputs: Vec::new(),
+            frames_done: Vec::new(),
+        }
+    }
+
+    fn request_xdg_outputs(&mut self, qh: &QueueHandle<AppState>) {
+        let Some(ref om) = self.output_manager else {
+            return;
+        };
+        for (id, info) in self.outputs.iter_mut() {
+            if info.xdg_output.is_none() {
+                info.xdg_output = Some(om.get_xdg_output(&info.output, qh, *id));
+            }
         }
     }
 }
@@ -61,10 +79,15 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
                                     transform: wl_output::Transform::Normal,
                                     logical_width: None,
                                     logical_height: None,
+                                    logical_x: None,
+                                    logical_y: None,
                                 },
                                 name: None,
+                                xdg_output: None,
+                                closed: false,
                             },
                         );
+                        state.request_xdg_outputs(qh);
                     }
                     "wl_compositor" => {
                         state.compositor =
@@ -94,12 +117,24 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
                                 (),
                             ),
                         );
+                        state.request_xdg_outputs(qh);
                     }
                     _ => {}
                 }
             }
             wl_registry::Event::GlobalRemove { name } => {
-                state.outputs.remove(&name);
+                if let Some(info) = state.outputs.remove(&name) {
+                    let output_name = info.name.unwrap_or_else(|| format!("unknown-{}", name));
+                    info!("Output {} ({}) removed", output_name, name);
+
+                    if let Some(xdg_output) = info.xdg_output {
+                        xdg_output.destroy();
+                    }
+                    if info.output.version() >= 3 {
+                        info.output.release();
+                    }
+                    state.removed_outputs.push(output_name);
+                }
             }
             _ => {}
         }
@@ -138,6 +173,11 @@ impl Dispatch<wl_output::WlOutput, u32> for AppState {
                         }
                     }
                 }
+                // Sent after a batch of changes, which may let a closed
+                // surface be shown again
+                wl_output::Event::Done => {
+                    info.closed = false;
+                }
                 _ => {}
             }
         }
@@ -159,6 +199,18 @@ impl Dispatch<zxdg_output_v1::ZxdgOutputV1, u32> for AppState {
                     output_info.name = Some(name);
                 }
             }
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                if let Some(output_info) = state.outputs.get_mut(output_id) {
+                    output_info.config.logical_x = Some(x);
+                    output_info.config.logical_y = Some(y);
+                    debug!(
+                        "Output {} logical position: {},{}",
+                        output_info.name.as_deref().unwrap_or("unknown"),
+                        x,
+                        y
+                    );
+                }
+            }
             zxdg_output_v1::Event::LogicalSize { width, height } => {
                 if let Some(output_info) = state.outputs.get_mut(output_id) {
                     output_info.config.logical_width = Some(width as u32);
@@ -208,6 +260,23 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, Option<String>> for App
                 state.surface_to_output.insert(surface_id, output_name);
                 state.configured_count += 1;
             }
+            zwlr_layer_surface_v1::Event::Closed => {
+                let surface_id = surface.id().protocol_id();
+                info!("Layer surface {} closed by compositor", surface_id);
+
+                if let Some(output_name) = state.surface_to_output.remove(&surface_id) {
+                    // Keeps the surface from being recreated straight away,
+                    // which would loop if the compositor closes it again
+                    if let Some(info) = state
+                        .outputs
+                        .values_mut()
+                        .find(|info| info.name.as_deref() == Some(output_name.as_str()))
+                    {
+                        info.closed = true;
+                    }
+                    state.removed_outputs.push(output_name);
+                }
+            }
             _ => {}
         }
     }
@@ -262,6 +331,21 @@ impl Dispatch<wl_surface::WlSurface, ()> for AppState {
     }
 }
 
+impl Dispatch<wl_callback::WlCallback, u32> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        surface_id: &u32,
+        _: &Connection,
+        _: &QueueHandle<AppState>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            state.frames_done.push(*surface_id);
+        }
+    }
+}
+
 impl Dispatch<wl_region::WlRegion, ()> for AppState {
     fn event(
         _: &mut Self,
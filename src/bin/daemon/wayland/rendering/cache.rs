@@ -0,0 +1,258 @@
+use crate::media::{
+    AnimationHandler, Fit, FitMode, ImageHandler, MediaHandler, MediaType, ScalingMode, VideoHandler,
+};
+use crate::wayland::audio::SinkKind;
+use crate::wayland::types::SharedTexture;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Identifies media decoded once for every output showing it. Shaders are
+/// applied by each surface, so outputs drawing the same file with different
+/// shaders still share one decoder.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceKey {
+    pub path: String,
+}
+
+impl SourceKey {
+    /// Videos, animations and images spanning several outputs are decoded by
+    /// the cache.
+    /// Other media is loaded by each renderer.
+    pub fn for_media(media_type: &MediaType) -> Option<Self> {
+        match media_type {
+            MediaType::Video { path, .. } | MediaType::Animation { path, .. } => Some(Self { path: path.clone() }),
+            MediaType::Image { path, fit, .. } if fit.mode == FitMode::Span => Some(Self { path: path.clone() }),
+            _ => None,
+        }
+    }
+}
+
+/// How a decoder resamples and paces frames, agreed between every surface
+/// showing its source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DecodeParams {
+    fit: Fit,
+    /// Frame rate forced on videos, 0 for their own timing
+    fps: u16,
+}
+
+/// What the surfaces showing one source need from it this frame.
+pub struct SourceUse {
+    /// Media of the first surface to use the source, which picks the decoder
+    pub media_type: MediaType,
+    params: DecodeParams,
+    users: usize,
+    /// Largest size any surface draws the media at
+    pub size: (u32, u32),
+    /// Every surface showing the source is paused
+    pub paused: bool,
+    /// Output whose volume and sink the audio plays at
+    pub audio: Option<(String, f32, SinkKind)>,
+}
+
+impl SourceUse {
+    pub fn new(media_type: MediaType) -> Self {
+        Self {
+            params: DecodeParams {
+                fit: media_type.fit().unwrap_or_default(),
+                fps: 0,
+            },
+            media_type,
+            users: 0,
+            size: (0, 0),
+            paused: true,
+            audio: None,
+        }
+    }
+
+    /// Adds a surface drawing the source with `fit` at `fps`, 0 meaning
+    /// uncapped. Frames are resampled on the CPU only while every surface
+    /// wants the same fit mode and filter; otherwise they are uploaded at
+    /// their own size and each surface scales them on the GPU. Videos keep
+    /// their own timing if any surface is uncapped, and otherwise run at the
+    /// highest rate asked for.
+    pub fn add_user(&mut self, fit: Fit, fps: u16) {
+        let params = &mut self.params;
+        if self.users == 0 {
+            params.fit = fit;
+        } else if (params.fit.mode, params.fit.filter) != (fit.mode, fit.filter) {
+            params.fit.filter = Some(ScalingMode::None);
+        }
+        if matches!(self.media_type, MediaType::Video { .. }) {
+            params.fps = match self.users {
+                0 => fps,
+                _ if params.fps == 0 || fps == 0 => 0,
+                _ => params.fps.max(fps),
+            };
+        }
+        self.users += 1;
+    }
+}
+
+enum Decoder {
+    Image(ImageHandler),
+    Video(VideoHandler),
+    Animation(AnimationHandler),
+}
+
+impl Decoder {
+    /// Decoders only fill a texture, so they use the default program whatever
+    /// shader the outputs draw with.
+    fn new(media_type: &MediaType, params: DecodeParams) -> Result<Self> {
+        let DecodeParams { fit, fps } = params;
+        match media_type {
+            MediaType::Video { path, .. } => {
+                let forced_fps = if fps > 0 { Some(fps as f64) } else { None };
+                Ok(Decoder::Video(VideoHandler::new(path, None, forced_fps, fit)?))
+            }
+            MediaType::Image { path, .. } => Ok(Decoder::Image(ImageHandler::new(path, None, fit)?)),
+            MediaType::Animation { path, .. } => {
+                Ok(Decoder::Animation(AnimationHandler::new(path, None, fit)?))
+            }
+            MediaType::Shader(path) => Err(anyhow::anyhow!("Shader {} is not decoded", path)),
+        }
+    }
+
+    fn as_handler(&self) -> &dyn MediaHandler {
+        match self {
+            Decoder::Image(h) => h,
+            Decoder::Video(h) => h,
+            Decoder::Animation(h) => h,
+        }
+    }
+
+    fn as_handler_mut(&mut self) -> &mut dyn MediaHandler {
+        match self {
+            Decoder::Image(h) => h,
+            Decoder::Video(h) => h,
+            Decoder::Animation(h) => h,
+        }
+    }
+}
+
+struct Entry {
+    decoder: Decoder,
+    params: DecodeParams,
+    audio_owner: Option<String>,
+}
+
+/// Decoders shared by every surface showing the same media, so each file is
+/// decoded once and all outputs show the same frame. Textures live in the
+/// share group of the surfaces' EGL contexts, and the cache must only be
+/// updated or dropped with one of those contexts current.
+#[derive(Default)]
+pub struct MediaCache {
+    entries: HashMap<SourceKey, Entry>,
+    /// Sources that failed to load, with why, not retried until no surface
+    /// uses them
+    failed: HashMap<SourceKey, String>,
+}
+
+impl MediaCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the next frame of every source in `uses`, creating decoders
+    /// for new sources and dropping those no surface shows anymore. Returns
+    /// whether any source has a new frame.
+    pub fn update(&mut self, mut uses: HashMap<SourceKey, SourceUse>) -> bool {
+        self.entries.retain(|key, _| {
+            let used = uses.contains_key(key);
+            if !used {
+                tracing::info!(event = "media_cache_release", path = %key.path, "Releasing shared media");
+            }
+            used
+        });
+        self.failed.retain(|key, _| uses.contains_key(key));
+
+        let mut any_updated = false;
+        for (key, source) in uses.drain() {
+            if self.failed.contains_key(&key) {
+                continue;
+            }
+            // Surfaces joining or leaving may change how frames are decoded
+            if self.entries.get(&key).is_some_and(|entry| entry.params != source.params) {
+                tracing::info!(event = "media_cache_reconfigure", path = %key.path, params = ?source.params, "Recreating shared decoder for its current users");
+                self.entries.remove(&key);
+            }
+            if !self.entries.contains_key(&key) {
+                match Decoder::new(&source.media_type, source.params) {
+                    Ok(decoder) => {
+                        tracing::info!(event = "media_cache_create", path = %key.path, params = ?source.params, "Decoding shared media");
+                        self.entries.insert(
+                            key.clone(),
+                            Entry {
+                                decoder,
+                                params: source.params,
+                                audio_owner: None,
+                            },
+                        );
+                    }
+                    Err(e) => {
+                        tracing::error!(event = "media_cache_error", path = %key.path, error = %e, "Failed to load shared media");
+                        self.failed.insert(key, e.to_string());
+                        continue;
+                    }
+                }
+            }
+            let Some(entry) = self.entries.get_mut(&key) else {
+                continue;
+            };
+
+            match &mut entry.decoder {
+                Decoder::Video(video_handler) => {
+                    video_handler.set_paused(source.paused);
+                    video_handler.set_audio(source.audio.as_ref().map(|(_, volume, sink)| (*volume, *sink)));
+                }
+                Decoder::Animation(animation_handler) => animation_handler.set_paused(source.paused),
+                Decoder::Image(_) => {}
+            }
+            entry.audio_owner = source.audio.map(|(owner, _, _)| owner);
+
+            let handler = entry.decoder.as_handler_mut();
+            let result = handler
+                .set_output_size(source.size.0, source.size.1)
+                .and_then(|_| handler.update());
+            match result {
+                Ok(updated) => any_updated |= updated,
+                Err(e) => {
+                    tracing::error!(event = "media_cache_error", path = %key.path, error = %e, "Failed to decode shared media");
+                    self.entries.remove(&key);
+                    self.failed.insert(key, e.to_string());
+                }
+            }
+        }
+        any_updated
+    }
+
+    /// Texture holding the source's current frame, once one is decoded.
+    pub fn texture(&self, key: &SourceKey) -> Option<SharedTexture> {
+        let handler = self.entries.get(key)?.decoder.as_handler();
+        let texture = handler.get_texture()?;
+        let (width, height) = handler.get_dimensions();
+        Some(SharedTexture {
+            id: texture.id,
+            width,
+            height,
+        })
+    }
+
+    /// Whether `output_name` is the output whose audio settings the source's
+    /// sound plays with.
+    pub fn plays_audio_for(&self, key: &SourceKey, output_name: &str) -> bool {
+        self.entries.get(key).is_some_and(|entry| {
+            entry.audio_owner.as_deref() == Some(output_name)
+                && matches!(&entry.decoder, Decoder::Video(h) if h.has_audio())
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.failed.is_empty()
+    }
+
+    /// Why the source failed to load, if it did.
+    pub fn failure(&self, key: &SourceKey) -> Option<&str> {
+        self.failed.get(key).map(String::as_str)
+    }
+}
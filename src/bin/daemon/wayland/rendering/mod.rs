@@ -1,4 +1,6 @@
+pub mod cache;
 pub mod renderer;
 pub mod surface;
 
-pub use renderer::MediaRenderer;
+pub use cache::MediaCache;
+pub use renderer::{MediaLoad, MediaRenderer};
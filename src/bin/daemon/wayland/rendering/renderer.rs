@@ -1,14 +1,21 @@
 use crate::gl_bindings as gl;
-use crate::media::{ImageHandler, MediaHandler, MediaType, ShaderHandler, VideoHandler};
+use crate::ipc::PlaybackState;
+use crate::media::passes::{ChannelTexture, MEDIA_TEXTURE_UNIT, ShaderInputs};
+use crate::media::transition::TransitionEffect;
+use crate::media::{
+    FitMode, ImageHandler, MediaHandler, MediaType, MirrorHandler, ShaderHandler, Transition,
+};
 use crate::utils;
-use crate::wayland::types::RenderContext;
-use anyhow::Result;
+use crate::wayland::rendering::cache::{MediaCache, SourceKey};
+use crate::wayland::types::{RenderContext, SpanView};
+use anyhow::{Result, anyhow};
 use std::ffi::CString;
 
 pub enum MediaObject {
     Shader(ShaderHandler),
     Image(ImageHandler),
-    Video(VideoHandler),
+    /// Video or spanned image decoded by the media cache
+    Mirror(MirrorHandler),
 }
 
 impl MediaObject {
@@ -16,7 +23,7 @@ impl MediaObject {
         match self {
             MediaObject::Shader(h) => h,
             MediaObject::Image(h) => h,
-            MediaObject::Video(h) => h,
+            MediaObject::Mirror(h) => h,
         }
     }
 
@@ -24,28 +31,62 @@ impl MediaObject {
         match self {
             MediaObject::Shader(h) => h,
             MediaObject::Image(h) => h,
-            MediaObject::Video(h) => h,
+            MediaObject::Mirror(h) => h,
         }
     }
 
-    // Add method to get video handler specifically
-    fn as_video_handler_mut(&mut self) -> Option<&mut VideoHandler> {
+    /// Whether the media has something to show yet.
+    fn is_ready(&self) -> bool {
         match self {
-            MediaObject::Video(h) => Some(h),
-            _ => None,
+            MediaObject::Shader(_) => true,
+            MediaObject::Image(h) => h.get_texture().is_some(),
+            MediaObject::Mirror(h) => h.get_texture().is_some(),
         }
     }
+
+    /// Why the media failed to load; such media never becomes ready.
+    fn load_error(&self, media_cache: &MediaCache) -> Option<String> {
+        match self {
+            MediaObject::Mirror(mirror) => SourceKey::for_media(mirror.media_type())
+                .and_then(|key| media_cache.failure(&key).map(str::to_string)),
+            media => media.as_handler().load_error(),
+        }
+    }
+}
+
+/// How far a renderer got with the media it was given, for callers waiting on
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaLoad {
+    Loading,
+    /// A frame of the media has been drawn
+    Shown,
+    Failed(String),
+    /// Other media was set before this was shown
+    Replaced,
 }
 
 pub struct MediaRenderer {
     current_media: Option<MediaObject>,
     loading_media: Option<MediaObject>,
-    pending_media_type: Option<(MediaType, u16)>,
+    pending_media_type: Option<MediaType>,
+    pending_transition: Transition,
+    /// Media being replaced, kept alive until the transition away from it ends
+    transition: Option<(MediaObject, TransitionEffect)>,
+    media_type: MediaType,
     vbo: u32,
     ebo: u32,
     vao: u32,
     start_time: u64,
+    last_draw_time: u64,
+    frame: i32,
     loading_in_background: bool,
+    paused_at: Option<u64>,
+    /// Counts media changes, so a change can be followed until it is shown
+    generation: u64,
+    /// Generation whose media was last drawn
+    shown: u64,
+    load_error: Option<(u64, String)>,
 }
 
 impl MediaRenderer {
@@ -64,12 +105,21 @@ impl MediaRenderer {
         let mut renderer = Self {
             current_media: None,
             loading_media: None,
-            pending_media_type: Some((media_type, fps)),
+            pending_media_type: Some(media_type.clone()),
+            pending_transition: Transition::default(),
+            transition: None,
+            media_type,
             vbo,
             ebo,
             vao,
             start_time,
+            last_draw_time: start_time,
+            frame: 0,
             loading_in_background: false,
+            paused_at: None,
+            generation: 0,
+            shown: 0,
+            load_error: None,
         };
 
         renderer.ensure_resources()?;
@@ -98,7 +148,10 @@ impl MediaRenderer {
         Ok(())
     }
 
-    fn create_media_object(media_type: MediaType, fps: u16) -> Result<MediaObject> {
+    fn create_media_object(media_type: MediaType) -> Result<MediaObject> {
+        if SourceKey::for_media(&media_type).is_some() {
+            return Ok(MediaObject::Mirror(MirrorHandler::new(media_type)?));
+        }
         match media_type {
             MediaType::Shader(path) => {
                 let shader_path = if path == "default" {
@@ -108,17 +161,13 @@ impl MediaRenderer {
                 };
                 Ok(MediaObject::Shader(ShaderHandler::new(shader_path)?))
             }
-            MediaType::Image { path, shader } => Ok(MediaObject::Image(ImageHandler::new(
+            MediaType::Image { path, shader, fit } => Ok(MediaObject::Image(ImageHandler::new(
                 &path,
                 shader.as_deref(),
+                fit,
             )?)),
-            MediaType::Video { path, shader } => {
-                let forced_fps = if fps > 0 { Some(fps as f64) } else { None };
-                Ok(MediaObject::Video(VideoHandler::new(
-                    &path,
-                    shader.as_deref(),
-                    forced_fps,
-                )?))
+            MediaType::Video { path, .. } | MediaType::Animation { path, .. } => {
+                Err(anyhow!("{} must be decoded by the media cache", path))
             }
         }
     }
@@ -133,49 +182,125 @@ impl MediaRenderer {
         }
     }
 
-    // Add method to check for video restarts
-    pub fn check_video_restart(&mut self) -> bool {
-        if let Some(ref mut media) = self.current_media {
-            if let Some(video_handler) = media.as_video_handler_mut() {
-                return video_handler.take_restart_flag();
+    /// Freezes shader time, or resumes it from where it stopped. Shared video
+    /// pauses in the media cache once every surface showing it is paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        let now = utils::get_time_millis();
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(now),
+            (false, Some(paused_at)) => {
+                self.start_time += now.saturating_sub(paused_at);
+                self.last_draw_time = now;
+                self.paused_at = None;
             }
+            _ => {}
         }
-        false
     }
 
-    pub fn update_media(&mut self, new_media_type: MediaType, fps: u16) -> Result<()> {
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Media set since the last draw, or a transition still running, which a
+    /// paused surface keeps drawing so the new wallpaper shows.
+    pub fn has_pending_media(&self) -> bool {
+        self.pending_media_type.is_some() || self.loading_in_background || self.transition.is_some()
+    }
+
+    pub fn media_type(&self) -> &MediaType {
+        &self.media_type
+    }
+
+    /// Media this renderer draws from the media cache: what is pending,
+    /// loading, shown and transitioning away.
+    pub fn cached_sources(&self) -> Vec<&MediaType> {
+        let outgoing = self.transition.as_ref().map(|(outgoing, _)| outgoing);
+        let mirrors = [self.current_media.as_ref(), self.loading_media.as_ref(), outgoing]
+            .into_iter()
+            .flatten()
+            .filter_map(|media| match media {
+                MediaObject::Mirror(mirror) => Some(mirror.media_type()),
+                _ => None,
+            });
+        self.pending_media_type
+            .iter()
+            .filter(|media_type| SourceKey::for_media(media_type).is_some())
+            .chain(mirrors)
+            .collect()
+    }
+
+    pub fn playback_state(&self) -> PlaybackState {
+        if self.pending_media_type.is_some() || self.loading_in_background {
+            return PlaybackState::Loading;
+        }
+        if self.is_paused() {
+            return PlaybackState::Paused;
+        }
+        match &self.current_media {
+            Some(media) if !media.is_ready() => PlaybackState::Loading,
+            Some(MediaObject::Image(_)) => PlaybackState::Static,
+            Some(MediaObject::Mirror(mirror)) if matches!(mirror.media_type(), MediaType::Image { .. }) => {
+                PlaybackState::Static
+            }
+            Some(_) => PlaybackState::Playing,
+            None => PlaybackState::Loading,
+        }
+    }
+
+    /// Generation of the media last given to `update_media`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn media_load(&self, generation: u64) -> MediaLoad {
+        if generation != self.generation {
+            return MediaLoad::Replaced;
+        }
+        match &self.load_error {
+            Some((failed, error)) if *failed == generation => MediaLoad::Failed(error.clone()),
+            _ if self.shown == generation => MediaLoad::Shown,
+            _ => MediaLoad::Loading,
+        }
+    }
+
+    pub fn shader_error(&self) -> Option<&str> {
+        let media = self.current_media.as_ref().or(self.loading_media.as_ref())?;
+        media.as_handler().get_base().shader_error.as_deref()
+    }
+
+    pub fn update_media(
+        &mut self,
+        new_media_type: MediaType,
+        fps: u16,
+        transition: Transition,
+    ) -> Result<()> {
         tracing::info!(
             event = "renderer_media_update",
             ?new_media_type,
             fps,
+            transition = ?transition.kind,
             loading_in_background = self.loading_in_background,
             "Updating renderer media"
         );
 
-        self.pending_media_type = Some((new_media_type, fps));
+        self.pending_media_type = Some(new_media_type);
+        self.pending_transition = transition;
         self.loading_in_background = true;
+        self.generation += 1;
 
         Ok(())
     }
 
     fn ensure_resources(&mut self) -> Result<()> {
-        if let Some((media_type, fps)) = self.pending_media_type.take() {
-            match Self::create_media_object(media_type.clone(), fps) {
+        if let Some(media_type) = self.pending_media_type.take() {
+            match Self::create_media_object(media_type.clone()) {
                 Ok(new_media) => {
-                    let is_ready = match &new_media {
-                        MediaObject::Shader(_) => true, 
-                        MediaObject::Image(img_handler) => {
-                            img_handler.get_texture().is_some()
-                        }
-                        MediaObject::Video(vid_handler) => {
-                            let (w, h) = vid_handler.get_dimensions();
-                            w > 0 && h > 0
-                        }
-                    };
+                    self.media_type = media_type.clone();
 
-                    if is_ready || self.current_media.is_none() {
-                        self.current_media = Some(new_media);
+                    if new_media.is_ready() || self.current_media.is_none() {
+                        self.replace_current(new_media);
                         self.loading_media = None;
+                        self.frame = 0;
                         self.loading_in_background = false;
 
                         tracing::info!(
@@ -194,12 +319,6 @@ impl MediaRenderer {
                     }
                 }
                 Err(e) => {
-                    tracing::error!(
-                        event = "media_load_error",
-                        error = %e,
-                        ?media_type,
-                        "Failed to load new media"
-                    );
                     self.loading_in_background = false;
                     return Err(e);
                 }
@@ -207,25 +326,16 @@ impl MediaRenderer {
         }
 
         if self.loading_in_background && self.loading_media.is_some() {
-            let should_transition = if let Some(ref loading_media) = self.loading_media {
-                match loading_media {
-                    MediaObject::Image(img_handler) => {
-                        img_handler.get_texture().is_some()
-                    }
-                    MediaObject::Video(vid_handler) => {
-                        let (w, h) = vid_handler.get_dimensions();
-                        w > 0 && h > 0 && vid_handler.get_texture().is_some()
-                    }
-                    MediaObject::Shader(_) => true, 
-                }
-            } else {
-                false
-            };
+            let should_transition = self
+                .loading_media
+                .as_ref()
+                .is_some_and(MediaObject::is_ready);
 
             if should_transition {
                 if let Some(new_media) = self.loading_media.take() {
-                    self.current_media = Some(new_media);
+                    self.replace_current(new_media);
                     self.loading_in_background = false;
+                    self.frame = 0;
 
                     tracing::info!(
                         event = "media_transition_complete",
@@ -238,6 +348,34 @@ impl MediaRenderer {
         Ok(())
     }
 
+    /// Makes `new_media` current, transitioning away from the media it replaces
+    /// when a transition was requested.
+    fn replace_current(&mut self, new_media: MediaObject) {
+        let outgoing = self.current_media.replace(new_media);
+        let transition = std::mem::take(&mut self.pending_transition);
+        self.transition = match outgoing {
+            Some(outgoing) if !transition.is_none() => {
+                let kind = transition.kind.clone();
+                match TransitionEffect::new(transition) {
+                    Ok(effect) => {
+                        tracing::info!(event = "transition_start", ?kind, "Starting transition");
+                        Some((outgoing, effect))
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            event = "transition_error",
+                            ?kind,
+                            error = %e,
+                            "Failed to set up transition, switching immediately"
+                        );
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+    }
+
     fn setup_geometry() -> Result<(u32, u32, u32)> {
         let vertices: [f32; 16] = [
             -1.0, 1.0, 0.0, 0.0, -1.0, -1.0, 0.0, 1.0, 1.0, -1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0,
@@ -297,17 +435,114 @@ impl MediaRenderer {
     }
 
     pub fn draw(&mut self, context: &mut RenderContext) -> Result<()> {
-        self.ensure_resources()?;
+        // Whatever was shown before stays up; the failure is reported through
+        // `media_load`
+        if let Err(e) = self.ensure_resources() {
+            tracing::error!(
+                event = "media_load_error",
+                error = %e,
+                "Failed to load new media"
+            );
+            self.load_error = Some((self.generation, e.to_string()));
+        }
 
-        if let Some(ref mut media) = self.current_media {
-            let _ = media.as_handler_mut().update()?;
+        // Media that failed to load never becomes ready; keep showing what
+        // was there before
+        let failed = self
+            .loading_media
+            .as_ref()
+            .and_then(|media| media.load_error(context.media_cache));
+        if let Some(error) = failed {
+            tracing::warn!(event = "media_load_abandoned", media_type = ?self.media_type, %error, "Media failed to load");
+            self.loading_media = None;
+            self.loading_in_background = false;
+            self.load_error = Some((self.generation, error));
+        } else if self.loading_media.is_none() && self.media_load(self.generation) == MediaLoad::Loading {
+            // With nothing to fall back to, new media is current while it loads
+            let error = self
+                .current_media
+                .as_ref()
+                .and_then(|media| media.load_error(context.media_cache));
+            if let Some(error) = error {
+                self.load_error = Some((self.generation, error));
+            }
         }
 
-        if let Some(ref mut loading_media) = self.loading_media {
-            let _ = loading_media.as_handler_mut().update()?;
+        let (output_width, output_height) = (context.width.max(0) as u32, context.height.max(0) as u32);
+        // Spanned media is resampled for the whole layout
+        let media_size = match context.span {
+            Some(span) => (span.width, span.height),
+            None => (output_width, output_height),
+        };
+        let outgoing = self.transition.as_mut().map(|(outgoing, _)| outgoing);
+        for media in [self.current_media.as_mut(), self.loading_media.as_mut(), outgoing]
+            .into_iter()
+            .flatten()
+        {
+            if let MediaObject::Mirror(mirror) = media {
+                let key = SourceKey::for_media(mirror.media_type());
+                match key.and_then(|key| context.media_cache.texture(&key)) {
+                    Some(source) => mirror.set_source(source.id, source.width, source.height),
+                    None => mirror.clear_source(),
+                }
+            }
+            let handler = media.as_handler_mut();
+            handler.set_output_size(media_size.0, media_size.1)?;
+            let _ = handler.update()?;
+        }
+
+        let settled = self.pending_media_type.is_none() && !self.loading_in_background;
+        if settled && self.current_media.as_ref().is_some_and(MediaObject::is_ready) {
+            self.shown = self.generation;
+        }
+
+        let now = utils::get_time_millis();
+        let inputs = ShaderInputs {
+            time: ((now - self.start_time) as f32 / 1000.0) % 3600.0,
+            time_delta: now.saturating_sub(self.last_draw_time) as f32 / 1000.0,
+            frame: self.frame,
+            date: utils::local_date(),
+            width: context.width as f32,
+            height: context.height as f32,
+            uniforms: context.uniforms,
+        };
+
+        if let Some((outgoing, effect)) = self.transition.as_mut() {
+            let progress = effect.progress(now);
+            if progress >= 1.0 {
+                tracing::debug!(event = "transition_finished", "Transition finished");
+                self.transition = None;
+            } else if let Some(incoming) = self.current_media.as_mut() {
+                let [from, to] = effect.targets(output_width, output_height)?;
+                let (from, to) = (from.id, to.id);
+                let mut outgoing_context = RenderContext {
+                    width: context.width,
+                    height: context.height,
+                    fifo_reader: None,
+                    span: context.span,
+                    media_cache: context.media_cache,
+                    uniforms: context.uniforms,
+                };
+                Self::draw_media(outgoing, &inputs, &mut outgoing_context, self.vbo, self.vao, from)?;
+                Self::draw_media(incoming, &inputs, context, self.vbo, self.vao, to)?;
+
+                unsafe {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                }
+                Self::update_geometry(self.vbo, context.width, context.height, 0, 0, FitMode::Stretch, None);
+                let vao = self.vao;
+                effect.composite(progress, context.width, context.height, || unsafe {
+                    gl::BindVertexArray(vao);
+                    gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+                    gl::BindVertexArray(0);
+                });
+                self.last_draw_time = now;
+                self.frame = self.frame.wrapping_add(1);
+                return Ok(());
+            }
         }
 
-        let media_to_render = self.current_media.as_ref().or(self.loading_media.as_ref());
+        let media_to_render = self.current_media.as_mut().or(self.loading_media.as_mut());
 
         let Some(media_object) = media_to_render else {
             unsafe {
@@ -316,26 +551,47 @@ impl MediaRenderer {
             return Ok(());
         };
 
-        let handler = media_object.as_handler();
+        Self::draw_media(media_object, &inputs, context, self.vbo, self.vao, 0)?;
+        self.last_draw_time = now;
+        self.frame = self.frame.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Draws one media object with its shader into `target`, 0 being the
+    /// surface itself.
+    fn draw_media(
+        media_object: &mut MediaObject,
+        inputs: &ShaderInputs,
+        context: &mut RenderContext,
+        vbo: u32,
+        vao: u32,
+        target: u32,
+    ) -> Result<()> {
+        let handler = media_object.as_handler_mut();
+        handler.get_base_mut().reload_shader_if_changed();
+        let media = handler.get_texture().map(ChannelTexture::from);
+        if handler.get_base().passes.has_buffers() {
+            Self::update_geometry(vbo, context.width, context.height, 0, 0, FitMode::Stretch, None);
+            handler.get_base_mut().passes.render(inputs, media, || unsafe {
+                gl::BindVertexArray(vao);
+                gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+                gl::BindVertexArray(0);
+            })?;
+        }
+
+        let handler = &*handler;
         let program = handler.get_shader_program();
+        let fit = handler.get_fit();
 
         unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target);
             program.use_program();
-
+            let (r, g, b) = fit.letterbox_rgb();
+            gl::ClearColor(r, g, b, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
             gl::Viewport(0, 0, context.width, context.height);
 
-            let time_loc = program.get_uniform_location("time");
-            if time_loc != -1 {
-                let raw_time = (utils::get_time_millis() - self.start_time) as f32 / 1000.0;
-                let time = raw_time % 3600.0;
-                gl::Uniform1f(time_loc, time);
-            }
-
-            let resolution_loc = program.get_uniform_location("resolution");
-            if resolution_loc != -1 {
-                gl::Uniform2f(resolution_loc, context.width as f32, context.height as f32);
-            }
+            inputs.apply(program);
 
             if let Some(ref mut reader) = context.fifo_reader {
                 let fifo_loc = program.get_uniform_location("fifo");
@@ -357,81 +613,138 @@ impl MediaRenderer {
             }
 
             if let Some(texture) = handler.get_texture() {
-                gl::ActiveTexture(gl::TEXTURE0);
+                gl::ActiveTexture(gl::TEXTURE0 + MEDIA_TEXTURE_UNIT);
                 texture.bind();
+                texture.set_repeat(fit.mode == FitMode::Tile);
 
                 let media_loc = program.get_uniform_location("u_media");
                 if media_loc != -1 {
-                    gl::Uniform1i(media_loc, 0);
+                    gl::Uniform1i(media_loc, MEDIA_TEXTURE_UNIT as i32);
                 }
+                gl::ActiveTexture(gl::TEXTURE0);
             }
+            handler.get_base().passes.bind_image_channels(program, media);
 
             let (media_width, media_height) = handler.get_dimensions();
-            self.update_geometry(context.width, context.height, media_width, media_height);
+            Self::update_geometry(
+                vbo,
+                context.width,
+                context.height,
+                media_width,
+                media_height,
+                fit.mode,
+                context.span.as_ref(),
+            );
 
-            gl::BindVertexArray(self.vao);
+            gl::BindVertexArray(vao);
             gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
             gl::BindVertexArray(0);
+
+            // A red strip along the top edge flags a shader that failed to
+            // reload; the info log is in the logs and `GetStatus`
+            if handler.get_base().shader_error.is_some() {
+                let strip = (context.height / 100).max(4);
+                gl::Enable(gl::SCISSOR_TEST);
+                gl::Scissor(0, context.height - strip, context.width, strip);
+                gl::ClearColor(0.85, 0.1, 0.1, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+                gl::Disable(gl::SCISSOR_TEST);
+            }
         }
         Ok(())
     }
 
     fn update_geometry(
-        &self,
+        vbo: u32,
         output_width: i32,
         output_height: i32,
         media_width: u32,
         media_height: u32,
+        mode: FitMode,
+        span: Option<&SpanView>,
     ) {
         let output_w = output_width as f32;
         let output_h = output_height as f32;
         let media_w = media_width as f32;
         let media_h = media_height as f32;
 
-        if media_w <= 0.0 || media_h <= 0.0 {
-            let verts: [f32; 16] = [
-                -1.0, 1.0, 0.0, 0.0, -1.0, -1.0, 0.0, 1.0, 1.0, -1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0,
-            ];
-
-            unsafe {
-                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-                gl::BufferData(
-                    gl::ARRAY_BUFFER,
-                    (verts.len() * std::mem::size_of::<f32>()) as isize,
-                    verts.as_ptr() as *const _,
-                    gl::STATIC_DRAW,
-                );
-            }
-            return;
-        }
+        // Half extents of the quad in clip space and the texture window shown on it
+        let (x, y, u_min, u_max, v_min, v_max) = if media_w <= 0.0 || media_h <= 0.0 {
+            (1.0, 1.0, 0.0, 1.0, 0.0, 1.0)
+        } else {
+            let media_aspect = media_w / media_h;
+            let output_aspect = output_w / output_h;
+
+            // Texture window covering an area of the given size with the media
+            let cover = |area_w: f32, area_h: f32| {
+                let area_aspect = area_w / area_h;
+                let (scale_x, scale_y) = if media_aspect > area_aspect {
+                    let scale = area_h / media_h;
+                    let scaled_width = media_w * scale;
+                    let overflow = (scaled_width - area_w) / area_w;
+                    (1.0 + overflow, 1.0)
+                } else {
+                    let scale = area_w / media_w;
+                    let scaled_height = media_h * scale;
+                    let overflow = (scaled_height - area_h) / area_h;
+                    (1.0, 1.0 + overflow)
+                };
 
-        let media_aspect = media_w / media_h;
-        let output_aspect = output_w / output_h;
+                let u_min = (1.0 - 1.0 / scale_x) * 0.5;
+                let v_min = (1.0 - 1.0 / scale_y) * 0.5;
+                (u_min, 1.0 - u_min, v_min, 1.0 - v_min)
+            };
 
-        let (scale_x, scale_y) = if media_aspect > output_aspect {
-            let scale = output_h / media_h;
-            let scaled_width = media_w * scale;
-            let overflow = (scaled_width - output_w) / output_w;
-            (1.0 + overflow, 1.0)
-        } else {
-            let scale = output_w / media_w;
-            let scaled_height = media_h * scale;
-            let overflow = (scaled_height - output_h) / output_h;
-            (1.0, 1.0 + overflow)
+            match mode {
+                FitMode::Cover => {
+                    let (u_min, u_max, v_min, v_max) = cover(output_w, output_h);
+                    (1.0, 1.0, u_min, u_max, v_min, v_max)
+                }
+                FitMode::Span => {
+                    // The layout is covered as a whole and this output shows
+                    // its own part of that window
+                    let (layout_w, layout_h, [left, top, right, bottom]) = match span {
+                        Some(span) => (span.width as f32, span.height as f32, span.rect),
+                        None => (output_w, output_h, [0.0, 0.0, 1.0, 1.0]),
+                    };
+                    let (u_min, u_max, v_min, v_max) = cover(layout_w, layout_h);
+                    let u = |t: f32| u_min + (u_max - u_min) * t;
+                    let v = |t: f32| v_min + (v_max - v_min) * t;
+                    (1.0, 1.0, u(left), u(right), v(top), v(bottom))
+                }
+                FitMode::Contain => {
+                    if media_aspect > output_aspect {
+                        (1.0, output_aspect / media_aspect, 0.0, 1.0, 0.0, 1.0)
+                    } else {
+                        (media_aspect / output_aspect, 1.0, 0.0, 1.0, 0.0, 1.0)
+                    }
+                }
+                FitMode::Stretch => (1.0, 1.0, 0.0, 1.0, 0.0, 1.0),
+                FitMode::Center => {
+                    // Media larger than the output is cropped around its middle
+                    let u_span = (output_w / media_w).min(1.0);
+                    let v_span = (output_h / media_h).min(1.0);
+                    let u_min = (1.0 - u_span) * 0.5;
+                    let v_min = (1.0 - v_span) * 0.5;
+                    (
+                        (media_w / output_w).min(1.0),
+                        (media_h / output_h).min(1.0),
+                        u_min,
+                        u_min + u_span,
+                        v_min,
+                        v_min + v_span,
+                    )
+                }
+                FitMode::Tile => (1.0, 1.0, 0.0, output_w / media_w, 0.0, output_h / media_h),
+            }
         };
 
-        let u_min = (1.0 - 1.0 / scale_x) * 0.5;
-        let u_max = 1.0 - u_min;
-        let v_min = (1.0 - 1.0 / scale_y) * 0.5;
-        let v_max = 1.0 - v_min;
-
         let verts: [f32; 16] = [
-            -1.0, 1.0, u_min, v_min, -1.0, -1.0, u_min, v_max, 1.0, -1.0, u_max, v_max, 1.0, 1.0,
-            u_max, v_min,
+            -x, y, u_min, v_min, -x, -y, u_min, v_max, x, -y, u_max, v_max, x, y, u_max, v_min,
         ];
 
         unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
             gl::BufferData(
                 gl::ARRAY_BUFFER,
                 (verts.len() * std::mem::size_of::<f32>()) as isize,
@@ -1,11 +1,13 @@
 use anyhow::{Result, anyhow};
 use khronos_egl as egl;
-use wayland_client::protocol::wl_compositor;
+use wayland_client::protocol::{wl_compositor, wl_surface};
 use wayland_client::{Connection, Proxy, QueueHandle};
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 use crate::media::MediaType;
+use crate::media::passes::Uniforms;
 use crate::wayland::rendering::MediaRenderer;
-use crate::wayland::types::{OutputInfo, EglResources, SurfaceId};
+use crate::utils::FpsCounter;
+use crate::wayland::types::{DisplayConfig, OutputInfo, EglResources, SurfaceId};
 use crate::wayland::protocol::events::AppState;
 
 pub struct WaylandSurface {
@@ -16,6 +18,24 @@ pub struct WaylandSurface {
     pub current_height: u32,
     pub surface_id: SurfaceId,
     pub output_name: String,
+    pub display_config: DisplayConfig,
+    pub fps_counter: FpsCounter,
+    pub configured: bool,
+    pub fps: u16,
+    pub last_draw: u64,
+    pub mute: bool,
+    pub volume: f32,
+    /// Shader uniforms set with `SetUniforms`
+    pub uniforms: Uniforms,
+    /// Paused with the `Pause` command
+    pub paused: bool,
+    /// The compositor stopped sending frame callbacks, so nothing of the
+    /// surface is visible
+    pub hidden: bool,
+    /// A frame was committed and its frame callback has not fired yet
+    pub frame_pending: bool,
+    wl_surface: wl_surface::WlSurface,
+    layer_surface: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
 }
 
 impl WaylandSurface {
@@ -29,6 +49,7 @@ impl WaylandSurface {
         conn: &Connection,
         qh: &QueueHandle<AppState>,
         fps: u16,
+        share_context: Option<egl::Context>,
     ) -> Result<Self> {
         let surface = compositor.create_surface(qh, ());
         let input_region = compositor.create_region(qh, ());
@@ -75,7 +96,7 @@ impl WaylandSurface {
 
         surface.commit();
 
-        let egl_resources = Self::create_egl_resources(egl_instance, conn)?;
+        let egl_resources = Self::create_egl_resources(egl_instance, conn, share_context)?;
         let initial_width = 100;
         let initial_height = 100;
 
@@ -121,12 +142,89 @@ impl WaylandSurface {
             current_height: initial_height as u32,
             surface_id,
             output_name,
+            display_config: output_info.config.clone(),
+            fps_counter: FpsCounter::new(),
+            configured: false,
+            fps,
+            last_draw: 0,
+            mute: false,
+            volume: 1.0,
+            uniforms: Uniforms::new(),
+            paused: false,
+            hidden: false,
+            frame_pending: false,
+            wl_surface: surface,
+            layer_surface,
         })
     }
 
-    fn create_egl_resources(
+    /// Whether the per-output fps cap, lowered to `fps_cap` when one is given,
+    /// allows drawing at `now`. A quarter frame of slack keeps loop jitter from
+    /// skipping every other frame.
+    pub fn frame_due(&self, now: u64, fps_cap: Option<u16>) -> bool {
+        let fps = match (self.fps, fps_cap) {
+            (0, cap) => cap.unwrap_or(0),
+            (fps, Some(cap)) => fps.min(cap),
+            (fps, None) => fps,
+        };
+        if fps == 0 {
+            return true;
+        }
+        let interval = 1000 / fps as u64;
+        now + interval / 4 >= self.last_draw + interval
+    }
+
+    /// Asks for a frame callback on the next commit. The compositor only sends
+    /// it when the surface is about to be shown, which is what paces drawing.
+    pub fn request_frame(&mut self, qh: &QueueHandle<AppState>) {
+        self.wl_surface.frame(qh, self.surface_id.0);
+        self.frame_pending = true;
+    }
+
+    pub fn destroy(self, egl_instance: &egl::Instance<egl::Static>) -> Result<()> {
+        tracing::info!(
+            event = "layer_surface_destroy",
+            output = %self.output_name,
+            surface_id = self.surface_id.0,
+            "Destroying layer surface"
+        );
+
+        let Self {
+            egl_resources,
+            renderer,
+            egl_window,
+            wl_surface,
+            layer_surface,
+            ..
+        } = self;
+
+        // GL objects owned by the renderer must be deleted with their context current
+        egl_instance.make_current(
+            egl_resources.display,
+            Some(egl_resources.surface),
+            Some(egl_resources.surface),
+            Some(egl_resources.context),
+        )?;
+        drop(renderer);
+
+        egl_instance.make_current(egl_resources.display, None, None, None)?;
+        egl_instance.destroy_surface(egl_resources.display, egl_resources.surface)?;
+        egl_instance.destroy_context(egl_resources.display, egl_resources.context)?;
+        drop(egl_window);
+
+        layer_surface.destroy();
+        wl_surface.destroy();
+        Ok(())
+    }
+
+    /// Creates an EGL context, sharing textures and buffers with
+    /// `share_context` when one is given. The returned surface is null; output
+    /// contexts get their window surface once the `wl_egl_window` exists, and
+    /// the share context stays surfaceless.
+    pub fn create_egl_resources(
         egl_instance: &egl::Instance<egl::Static>,
         conn: &Connection,
+        share_context: Option<egl::Context>,
     ) -> Result<EglResources> {
         let display_ptr = conn.display().id().as_ptr();
         let egl_display = unsafe { egl_instance.get_display(display_ptr as *mut _) }
@@ -139,7 +237,7 @@ impl WaylandSurface {
             egl::SURFACE_TYPE,
             egl::WINDOW_BIT,
             egl::RENDERABLE_TYPE,
-            egl::OPENGL_ES2_BIT,
+            egl::OPENGL_ES3_BIT,
             egl::RED_SIZE,
             8,
             egl::GREEN_SIZE,
@@ -157,14 +255,17 @@ impl WaylandSurface {
             .first()
             .ok_or_else(|| anyhow!("No suitable EGL config"))?;
 
+        // Sized texture formats, half-float buffers and `#version 300 es`
+        // shaders all need ES 3
         let context_attribs = [
             egl::CONTEXT_MAJOR_VERSION,
-            2,
+            3,
             egl::CONTEXT_MINOR_VERSION,
             0,
             egl::NONE,
         ];
-        let context = egl_instance.create_context(egl_display, *config, None, &context_attribs)?;
+        let context =
+            egl_instance.create_context(egl_display, *config, share_context, &context_attribs)?;
 
         Ok(EglResources {
             display: egl_display,
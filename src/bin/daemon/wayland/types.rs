@@ -1,6 +1,9 @@
 use super::audio::fifo::FifoReader;
+use super::rendering::MediaCache;
+use crate::media::passes::Uniforms;
 use khronos_egl as egl;
 use wayland_client::protocol::wl_output;
+use wayland_protocols::xdg::xdg_output::zv1::client::zxdg_output_v1;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OutputId(pub u32);
@@ -16,6 +19,25 @@ pub struct DisplayConfig {
     pub transform: wl_output::Transform,
     pub logical_width: Option<u32>,
     pub logical_height: Option<u32>,
+    /// Position in the compositor's global space, from xdg-output
+    pub logical_x: Option<i32>,
+    pub logical_y: Option<i32>,
+}
+
+impl DisplayConfig {
+    pub fn transform_name(&self) -> &'static str {
+        match self.transform {
+            wl_output::Transform::Normal => "normal",
+            wl_output::Transform::_90 => "90",
+            wl_output::Transform::_180 => "180",
+            wl_output::Transform::_270 => "270",
+            wl_output::Transform::Flipped => "flipped",
+            wl_output::Transform::Flipped90 => "flipped-90",
+            wl_output::Transform::Flipped180 => "flipped-180",
+            wl_output::Transform::Flipped270 => "flipped-270",
+            _ => "unknown",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -23,12 +45,39 @@ pub struct OutputInfo {
     pub output: wl_output::WlOutput,
     pub config: DisplayConfig,
     pub name: Option<String>,
+    pub xdg_output: Option<zxdg_output_v1::ZxdgOutputV1>,
+    /// The compositor closed this output's layer surface, so none is created
+    /// for it again until the output changes or is announced anew
+    pub closed: bool,
+}
+
+/// The part of a spanned layout one surface shows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpanView {
+    /// Left, top, right and bottom edges of the surface within the layout's
+    /// bounding box, from 0.0 to 1.0
+    pub rect: [f32; 4],
+    /// Size of the whole layout in this surface's pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Texture in the shared EGL context group holding a frame decoded by the
+/// media cache, which surfaces sample instead of decoding the media again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedTexture {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
 pub struct RenderContext<'a> {
     pub width: i32,
     pub height: i32,
     pub fifo_reader: Option<&'a mut FifoReader>,
+    pub span: Option<SpanView>,
+    pub media_cache: &'a MediaCache,
+    pub uniforms: &'a Uniforms,
 }
 
 pub struct EglResources {
@@ -37,9 +86,3 @@ pub struct EglResources {
     pub context: egl::Context,
     pub config: egl::Config,
 }
-
-#[derive(Debug, Clone)]
-pub struct WaylandConfig {
-    pub fps: u16,
-    pub layer_name: Option<String>,
-}
@@ -0,0 +1,5 @@
+//! Code shared by the papyrust GUI and daemon.
+
+pub mod protocol;
+pub mod socket;
+pub mod wallpaper_engine;
@@ -9,13 +9,21 @@ mod ui;
 use ui::pages::library::Library;
 use ui::{state::Page, view};
 
+use crate::ui::ipc::{Event, MonitorInfo};
 use crate::ui::loader::project::Project;
+use crate::ui::loader::properties::{Property, PropertyValue};
 
 pub struct Papyrust {
     pub current_page: Page,
     pub library: Library,
     pub animation_state: usize,
     pub popup_state: Option<Project>,
+    /// User properties of the project in the popup, as edited
+    pub popup_properties: Vec<Property>,
+    /// Outputs reported by the daemon
+    pub monitors: Vec<MonitorInfo>,
+    /// Outputs the popup applies to; empty means all of them
+    pub selected_monitors: Vec<String>,
     pub videos: HashMap<String, Video>,
 }
 
@@ -27,6 +35,13 @@ pub enum Message {
     OpenPopup(Project),
     ClosePopup,
     ApplyProject(Project),
+    SceneExtracted(Option<Vec<String>>, Result<String, String>),
+    /// Outcome of a command sent to the daemon off the UI thread
+    WallpaperSent(Result<(), String>),
+    PropertyChanged(String, PropertyValue),
+    MonitorsLoaded(Result<Vec<MonitorInfo>, String>),
+    ToggleMonitor(Option<String>),
+    DaemonEvent(Event),
     Tick,
     LoadVideo(String),
     VideoLoaded(String),
@@ -50,6 +65,9 @@ impl Papyrust {
                 library,
                 animation_state: 0,
                 popup_state: None,
+                popup_properties: Vec::new(),
+                monitors: Vec::new(),
+                selected_monitors: Vec::new(),
                 videos: HashMap::new(),
             },
             first,
@@ -104,7 +122,10 @@ impl Papyrust {
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        iced::time::every(std::time::Duration::from_millis(300)).map(|_| Message::Tick)
+        Subscription::batch([
+            iced::time::every(std::time::Duration::from_millis(300)).map(|_| Message::Tick),
+            Subscription::run(ui::ipc::events).map(Message::DaemonEvent),
+        ])
     }
 }
 
@@ -0,0 +1,113 @@
+use super::{Event, IpcCommand, IpcError, IpcResponse, PROTOCOL_VERSION, Request, Response};
+use anyhow::{Result, anyhow};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// A blocking connection to the daemon that has completed the `Hello`
+/// handshake.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    /// Protocol version the daemon answered with
+    pub version: u32,
+    /// Version of the daemon itself
+    pub daemon: String,
+}
+
+impl Client {
+    /// Connects to the socket at `path`, introducing the client as `name`.
+    pub fn connect(path: &Path, name: &str) -> Result<Self> {
+        let writer = UnixStream::connect(path).map_err(|e| {
+            anyhow!(
+                "Failed to connect to papyrust-daemon at {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        let reader = BufReader::new(writer.try_clone()?);
+        let mut client = Self {
+            reader,
+            writer,
+            next_id: 1,
+            version: 0,
+            daemon: String::new(),
+        };
+
+        let hello = IpcCommand::Hello {
+            version: PROTOCOL_VERSION,
+            client: Some(name.to_string()),
+        };
+        match client.call(hello)? {
+            IpcResponse::Hello { version, daemon } => {
+                client.version = version;
+                client.daemon = daemon;
+            }
+            IpcResponse::Error { code, message } => return Err(IpcError { code, message }.into()),
+            response => return Err(anyhow!("Unexpected reply to Hello: {:?}", response)),
+        }
+        Ok(client)
+    }
+
+    /// Sends `command` and returns the daemon's reply, `Error` replies
+    /// included.
+    pub fn call(&mut self, command: IpcCommand) -> Result<IpcResponse> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let line = serde_json::to_string(&Request { id, command })?;
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()?;
+
+        let mut reply = String::new();
+        if self.reader.read_line(&mut reply)? == 0 {
+            // Daemons from before the versioned protocol hang up on requests
+            return Err(anyhow!("Daemon closed the connection"));
+        }
+        let response: Response = serde_json::from_str(reply.trim())
+            .map_err(|e| anyhow!("Invalid reply from daemon: {}", e))?;
+        match response.id {
+            Some(reply_id) if reply_id != id => Err(anyhow!(
+                "Reply to request {} received while waiting for {}",
+                reply_id,
+                id
+            )),
+            _ => Ok(response.response),
+        }
+    }
+
+    /// Sends `command`, turning `Error` replies into an [`IpcError`].
+    pub fn request(&mut self, command: IpcCommand) -> Result<IpcResponse> {
+        Ok(self.call(command)?.into_result()?)
+    }
+
+    /// Turns the connection into a stream of the daemon's events.
+    pub fn subscribe(mut self) -> Result<Subscription> {
+        self.request(IpcCommand::Subscribe)?;
+        Ok(Subscription {
+            reader: self.reader,
+        })
+    }
+}
+
+/// Events from [`Client::subscribe`], ending when the daemon exits.
+pub struct Subscription {
+    reader: BufReader<UnixStream>,
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(
+                serde_json::from_str(line.trim())
+                    .map_err(|e| anyhow!("Invalid event from daemon: {}", e)),
+            ),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
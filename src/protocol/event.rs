@@ -0,0 +1,49 @@
+use super::MediaType;
+use serde::{Deserialize, Serialize};
+
+/// Something that happened in the daemon, pushed to clients after
+/// [`super::IpcCommand::Subscribe`]. Each is one line tagged with its kind:
+///
+/// ```text
+/// {"event":"output_added","output":"DP-1"}
+/// {"event":"paused","output":"DP-1"}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// An output was given new media, by a command, a playlist or a config
+    /// reload
+    MediaChanged {
+        output: String,
+        media: MediaType,
+    },
+    OutputAdded {
+        output: String,
+    },
+    OutputRemoved {
+        output: String,
+    },
+    /// A video reached its end and started over
+    VideoLooped {
+        path: String,
+        loop_count: u64,
+    },
+    /// A playlist moved to another item. `monitors` is `None` when the
+    /// playlist covers every output.
+    PlaylistAdvanced {
+        monitors: Option<Vec<String>>,
+        media: MediaType,
+    },
+    /// A shader or transition failed to compile, when set or when its file
+    /// changed on disk
+    ShaderError {
+        path: String,
+        error: String,
+    },
+    Paused {
+        output: String,
+    },
+    Resumed {
+        output: String,
+    },
+}
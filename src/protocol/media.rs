@@ -0,0 +1,381 @@
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MediaType {
+    Shader(String),
+    Image {
+        path: String,
+        shader: Option<String>,
+        #[serde(default)]
+        fit: Fit,
+    },
+    Video {
+        path: String,
+        shader: Option<String>,
+        #[serde(default)]
+        fit: Fit,
+    },
+    /// Animated GIF, APNG or WebP
+    Animation {
+        path: String,
+        shader: Option<String>,
+        #[serde(default)]
+        fit: Fit,
+    },
+}
+
+impl MediaType {
+    /// Fills in the resampling filter for media that did not choose one.
+    pub fn with_default_filter(mut self, filter: ScalingMode) -> Self {
+        if let MediaType::Image { fit, .. }
+        | MediaType::Video { fit, .. }
+        | MediaType::Animation { fit, .. } = &mut self
+        {
+            fit.filter.get_or_insert(filter);
+        }
+        self
+    }
+
+    /// How the media is fitted to outputs; shaders have no fit.
+    pub fn fit(&self) -> Option<Fit> {
+        match self {
+            MediaType::Image { fit, .. }
+            | MediaType::Video { fit, .. }
+            | MediaType::Animation { fit, .. } => Some(*fit),
+            MediaType::Shader(_) => None,
+        }
+    }
+}
+
+/// How media is fitted to an output whose aspect ratio differs from its own.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Fill the output and crop the overflow
+    #[default]
+    Cover,
+    /// Show the whole media and fill the rest with the letterbox colour
+    Contain,
+    /// Fill the output, ignoring the aspect ratio
+    Stretch,
+    /// Show the media at its native size in the middle of the output
+    Center,
+    /// Repeat the media at its native size
+    Tile,
+    /// Cover the bounding box of every output showing the same media, each
+    /// output drawing its own part of it
+    Span,
+}
+
+/// Resampling filter used when media is resized to its on-screen size.
+/// `None` uploads media at its native size and lets the GPU sample it
+/// bilinearly.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScalingMode {
+    /// Lanczos resampling followed by a contrast-adaptive sharpening pass
+    #[value(name = "fsr")]
+    FSR,
+    Lanczos,
+    Mitchell,
+    Bicubic,
+    #[default]
+    None,
+}
+
+impl ScalingMode {
+    pub fn sharpens(&self) -> bool {
+        matches!(self, ScalingMode::FSR)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fit {
+    pub mode: FitMode,
+    /// Falls back to the daemon's `--scaling` filter when unset
+    pub filter: Option<ScalingMode>,
+    /// Accepts `"#rrggbb"` as well as `[r, g, b]`
+    #[serde(deserialize_with = "deserialize_color")]
+    pub letterbox: [u8; 3],
+}
+
+impl Fit {
+    pub fn filter(&self) -> ScalingMode {
+        self.filter.unwrap_or_default()
+    }
+
+    pub fn letterbox_rgb(&self) -> (f32, f32, f32) {
+        let [r, g, b] = self.letterbox;
+        (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    }
+
+    /// Size the media should be resampled to on the CPU before upload, or
+    /// `None` when it is drawn at its native size.
+    pub fn scaled_size(
+        &self,
+        media_width: u32,
+        media_height: u32,
+        output_width: u32,
+        output_height: u32,
+    ) -> Option<(u32, u32)> {
+        if self.filter() == ScalingMode::None
+            || media_width == 0
+            || media_height == 0
+            || output_width == 0
+            || output_height == 0
+        {
+            return None;
+        }
+
+        let scale_x = output_width as f64 / media_width as f64;
+        let scale_y = output_height as f64 / media_height as f64;
+        let size = |scale: f64| {
+            (
+                ((media_width as f64 * scale).round() as u32).max(1),
+                ((media_height as f64 * scale).round() as u32).max(1),
+            )
+        };
+
+        let size = match self.mode {
+            // Spanned media is sized against the whole layout
+            FitMode::Cover | FitMode::Span => size(scale_x.max(scale_y)),
+            FitMode::Contain => size(scale_x.min(scale_y)),
+            FitMode::Stretch => (output_width, output_height),
+            FitMode::Center | FitMode::Tile => return None,
+        };
+        (size != (media_width, media_height)).then_some(size)
+    }
+}
+
+/// Parses `#rrggbb` or `rrggbb` into a letterbox colour.
+pub fn parse_color(value: &str) -> Result<[u8; 3]> {
+    let hex = value.trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(anyhow!("Invalid colour {}: expected #rrggbb", value));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|e| anyhow!("Invalid colour {}: {}", value, e))
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Color {
+        Hex(String),
+        Rgb([u8; 3]),
+    }
+
+    match Color::deserialize(deserializer)? {
+        Color::Hex(value) => parse_color(&value).map_err(serde::de::Error::custom),
+        Color::Rgb(rgb) => Ok(rgb),
+    }
+}
+
+/// Effect played when an output switches media.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionKind {
+    /// Hard cut
+    #[default]
+    None,
+    Fade,
+    /// Sweep from left to right
+    Wipe,
+    /// Circle growing from `position`
+    Grow,
+    Pixelate,
+    /// Path to a GLSL file in the gl-transitions format, defining
+    /// `vec4 transition(vec2 uv)` with `getFromColor`, `getToColor`,
+    /// `progress`, `ratio` and `resolution` predeclared
+    Custom(String),
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transition {
+    #[serde(rename = "type")]
+    pub kind: TransitionKind,
+    /// Seconds
+    pub duration: f32,
+    pub easing: Easing,
+    /// Origin of `grow`, from the top left of the output in 0..1
+    pub position: [f32; 2],
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            kind: TransitionKind::None,
+            duration: 1.0,
+            easing: Easing::default(),
+            position: [0.5, 0.5],
+        }
+    }
+}
+
+impl Transition {
+    pub fn is_none(&self) -> bool {
+        self.kind == TransitionKind::None || self.duration <= 0.0
+    }
+}
+
+/// Value of a uniform set with `SetUniforms`. Booleans are sent as `int` or
+/// `bool`, numbers as `float` and lists of two to four numbers as `vecN`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UniformValue {
+    Bool(bool),
+    Float(f32),
+    Vector(Vec<f32>),
+}
+
+/// Uniforms set at runtime, by name.
+pub type Uniforms = BTreeMap<String, UniformValue>;
+
+/// Checks that `name` is a GLSL identifier outside the reserved `gl_`
+/// namespace and that `value` fits a uniform.
+pub fn validate_uniform(name: &str, value: &UniformValue) -> Result<()> {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !identifier || name.starts_with("gl_") {
+        return Err(anyhow!("Invalid uniform name {:?}", name));
+    }
+    match value {
+        UniformValue::Float(v) if !v.is_finite() => {
+            Err(anyhow!("Uniform {} is not a finite number", name))
+        }
+        UniformValue::Vector(v) if !(2..=4).contains(&v.len()) => Err(anyhow!(
+            "Uniform {} has {} components: expected 2 to 4",
+            name,
+            v.len()
+        )),
+        UniformValue::Vector(v) if v.iter().any(|c| !c.is_finite()) => Err(anyhow!(
+            "Uniform {} has a component that is not a finite number",
+            name
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(mode: FitMode) -> Fit {
+        Fit {
+            mode,
+            filter: Some(ScalingMode::Lanczos),
+            ..Fit::default()
+        }
+    }
+
+    #[test]
+    fn scales_to_fit() {
+        assert_eq!(fit(FitMode::Cover).scaled_size(1000, 500, 1920, 1080), Some((2160, 1080)));
+        assert_eq!(fit(FitMode::Span).scaled_size(1000, 500, 1920, 1080), Some((2160, 1080)));
+        assert_eq!(fit(FitMode::Contain).scaled_size(1000, 500, 1920, 1080), Some((1920, 960)));
+        assert_eq!(fit(FitMode::Stretch).scaled_size(1000, 500, 1920, 1080), Some((1920, 1080)));
+        assert_eq!(fit(FitMode::Center).scaled_size(1000, 500, 1920, 1080), None);
+        assert_eq!(fit(FitMode::Tile).scaled_size(1000, 500, 1920, 1080), None);
+    }
+
+    #[test]
+    fn skips_scaling_when_not_needed() {
+        // Already the right size
+        assert_eq!(fit(FitMode::Cover).scaled_size(1920, 1080, 1920, 1080), None);
+        // No filter: the GPU samples the native size
+        assert_eq!(Fit::default().scaled_size(1000, 500, 1920, 1080), None);
+        for (mw, mh, ow, oh) in [(0, 500, 1920, 1080), (1000, 0, 1920, 1080), (1000, 500, 0, 1080), (1000, 500, 1920, 0)] {
+            assert_eq!(fit(FitMode::Cover).scaled_size(mw, mh, ow, oh), None);
+        }
+    }
+
+    #[test]
+    fn never_scales_to_nothing() {
+        assert_eq!(fit(FitMode::Contain).scaled_size(10000, 10, 100, 100), Some((100, 1)));
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#102030").unwrap(), [0x10, 0x20, 0x30]);
+        assert_eq!(parse_color("ffFF00").unwrap(), [255, 255, 0]);
+        for color in ["", "#12345", "#1234567", "#gg0000", "red"] {
+            assert!(parse_color(color).is_err(), "{:?} should not parse", color);
+        }
+    }
+
+    #[test]
+    fn reads_letterbox_colors() {
+        let hex: Fit = serde_json::from_str(r##"{"letterbox":"#102030"}"##).unwrap();
+        assert_eq!(hex.letterbox, [0x10, 0x20, 0x30]);
+        let rgb: Fit = serde_json::from_str(r#"{"letterbox":[1,2,3]}"#).unwrap();
+        assert_eq!(rgb.letterbox, [1, 2, 3]);
+
+        for letterbox in [r#""red""#, "[1,2]", "[256,0,0]", "7"] {
+            let json = format!(r#"{{"letterbox":{}}}"#, letterbox);
+            assert!(serde_json::from_str::<Fit>(&json).is_err(), "{} should not parse", letterbox);
+        }
+    }
+
+    #[test]
+    fn eases_between_endpoints() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert!(close(easing.apply(0.0), 0.0), "{:?} at 0", easing);
+            assert!(close(easing.apply(1.0), 1.0), "{:?} at 1", easing);
+            // Clamped outside 0..1
+            assert!(close(easing.apply(-0.5), 0.0), "{:?} below 0", easing);
+            assert!(close(easing.apply(1.5), 1.0), "{:?} above 1", easing);
+        }
+        assert!(close(Easing::Linear.apply(0.5), 0.5));
+        assert!(close(Easing::EaseIn.apply(0.5), 0.125));
+        assert!(close(Easing::EaseOut.apply(0.5), 0.875));
+        assert!(close(Easing::EaseInOut.apply(0.5), 0.5));
+    }
+
+    #[test]
+    fn treats_zero_length_transitions_as_none() {
+        let fade = |duration: f32| Transition {
+            kind: TransitionKind::Fade,
+            duration,
+            ..Transition::default()
+        };
+        assert!(!fade(1.0).is_none());
+        assert!(fade(0.0).is_none());
+        assert!(fade(-1.0).is_none());
+        assert!(Transition::default().is_none());
+    }
+}
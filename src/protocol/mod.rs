@@ -0,0 +1,395 @@
+//! The daemon's IPC protocol.
+//!
+//! Clients connect to the socket from [`crate::socket::path`] and exchange
+//! newline-delimited JSON. Each line sent is a [`Request`], and each line
+//! received the [`Response`] carrying the same `id`:
+//!
+//! ```text
+//! > {"id":1,"command":{"Hello":{"version":1,"client":"my-script"}}}
+//! < {"id":1,"response":{"Hello":{"version":1,"daemon":"0.1.0"}}}
+//! > {"id":2,"command":{"SetImage":{"path":"/tmp/a.png","shader":null,"monitors":["DP-1"]}}}
+//! < {"id":2,"response":"Success"}
+//! > {"id":3,"command":"GetStatus"}
+//! < {"id":3,"response":{"Status":{"monitors":[...]}}}
+//! ```
+//!
+//! A client should open with [`IpcCommand::Hello`] giving the highest
+//! [`PROTOCOL_VERSION`] it speaks; the daemon answers with its own version or
+//! with [`ErrorCode::UnsupportedVersion`]. Failures are reported as
+//! [`IpcResponse::Error`] with an [`ErrorCode`] to branch on and a message for
+//! people. A line that cannot be parsed is answered with
+//! [`ErrorCode::InvalidRequest`] and the connection stays open.
+//!
+//! `SetImage`, `SetVideo` and `SetShader` succeed once the media has been
+//! probed and its shaders compiled. With `wait` set, the reply comes once the
+//! first frame is on every targeted output, or carries the error that kept it
+//! off screen.
+//!
+//! After `Subscribe` the connection carries [`Event`]s, one per line, so
+//! clients can follow the daemon without polling `GetStatus`.
+//!
+//! Bare commands without the envelope, such as `{"SetImage":{...}}`, are
+//! still accepted and answered with a bare [`IpcResponse`], so scripts
+//! written before the protocol was versioned keep working.
+
+pub mod client;
+pub mod event;
+pub mod media;
+pub mod playlist;
+
+pub use client::{Client, Subscription};
+pub use event::Event;
+pub use media::*;
+pub use playlist::{PlaylistConfig, PlaylistOrder, Schedule};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the protocol described here. It is raised when a change would
+/// break existing clients.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    /// Chosen by the client and echoed in the response
+    pub id: u64,
+    pub command: IpcCommand,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    /// `id` of the request answered; absent when the request was too
+    /// malformed to read one
+    pub id: Option<u64>,
+    pub response: IpcResponse,
+}
+
+/// A command, serialized externally tagged: `{"SetImage": {...}}`, or a
+/// bare string for commands without fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IpcCommand {
+    /// Negotiates the protocol version; answered with [`IpcResponse::Hello`]
+    Hello {
+        version: u32,
+        /// Name of the client, for the daemon's log
+        #[serde(default)]
+        client: Option<String>,
+    },
+    /// Shows an image, or the first frame of an animation
+    SetImage {
+        path: String,
+        shader: Option<String>,
+        monitors: Option<Vec<String>>,
+        #[serde(default)]
+        fit: Fit,
+        #[serde(default)]
+        transition: Transition,
+        /// Reply once the image is on screen instead of once it is accepted
+        #[serde(default)]
+        wait: bool,
+    },
+    SetVideo {
+        path: String,
+        shader: Option<String>,
+        monitors: Option<Vec<String>>,
+        #[serde(default)]
+        mute: bool,
+        #[serde(default)]
+        fit: Fit,
+        #[serde(default)]
+        transition: Transition,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// Runs a fragment shader, or `"default"` for the built-in one
+    SetShader {
+        path: String,
+        monitors: Option<Vec<String>>,
+        #[serde(default)]
+        transition: Transition,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// Sets the audio volume, from 0.0 to 1.0, of videos on the given outputs
+    SetVolume {
+        volume: f32,
+        monitors: Option<Vec<String>>,
+    },
+    /// Sets shader uniforms on the given outputs, replacing any set before.
+    /// An empty map clears them.
+    SetUniforms {
+        uniforms: Uniforms,
+        monitors: Option<Vec<String>>,
+    },
+    /// Stops drawing and freezes playback
+    Pause {
+        monitors: Option<Vec<String>>,
+    },
+    Resume {
+        monitors: Option<Vec<String>>,
+    },
+    /// Rotates media on the playlist's outputs, replacing any playlist there
+    SetPlaylist {
+        playlist: PlaylistConfig,
+    },
+    Next {
+        monitors: Option<Vec<String>>,
+    },
+    Previous {
+        monitors: Option<Vec<String>>,
+    },
+    /// Stops a playlist from advancing on its own; `Next` and `Previous` still work
+    PausePlaylist {
+        monitors: Option<Vec<String>>,
+    },
+    ResumePlaylist {
+        monitors: Option<Vec<String>>,
+    },
+    /// Answered with [`IpcResponse::Status`]
+    GetStatus,
+    /// Answered with [`IpcResponse::Monitors`]
+    ListMonitors,
+    /// Rereads the config file
+    Reload,
+    /// Shows the media saved in the state file again on the given outputs,
+    /// taking them away from any playlist. Playlist items are never saved, so
+    /// this is the media last set with `SetImage`, `SetVideo` or `SetShader`.
+    Restore {
+        monitors: Option<Vec<String>>,
+    },
+    /// Stops the daemon once the reply is sent
+    Shutdown,
+    /// Answered with `Success`, after which the daemon sends an [`Event`] per
+    /// line for as long as the connection is open, and reads no more commands
+    Subscribe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IpcResponse {
+    Success,
+    Hello {
+        version: u32,
+        /// Version of the daemon itself
+        daemon: String,
+    },
+    Error {
+        /// Missing in replies from daemons older than the versioned protocol
+        #[serde(default)]
+        code: ErrorCode,
+        message: String,
+    },
+    Status {
+        monitors: Vec<MonitorStatus>,
+    },
+    Monitors {
+        monitors: Vec<MonitorInfo>,
+    },
+}
+
+/// Why a request failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The line was not valid JSON or not a known command
+    InvalidRequest,
+    /// The client's `Hello` asked for a version the daemon does not speak
+    UnsupportedVersion,
+    /// A value was out of range or malformed
+    InvalidArgument,
+    /// None of the targeted outputs is connected
+    UnknownMonitor,
+    /// Media, a shader or a playlist directory does not exist
+    FileNotFound,
+    /// Media exists but could not be decoded
+    DecodeFailed,
+    /// A shader or transition failed to compile
+    ShaderCompile,
+    /// The config file could not be reloaded
+    ConfigError,
+    /// The render loop did not answer in time
+    Unavailable,
+    /// Other media was set on an output before the media waited on was shown
+    Superseded,
+    #[default]
+    Internal,
+}
+
+/// An [`IpcResponse::Error`] as a Rust error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpcError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl IpcError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+impl From<IpcError> for IpcResponse {
+    fn from(error: IpcError) -> Self {
+        IpcResponse::Error {
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
+
+impl IpcResponse {
+    /// Turns an `Error` response into an [`IpcError`].
+    pub fn into_result(self) -> Result<Self, IpcError> {
+        match self {
+            IpcResponse::Error { code, message } => Err(IpcError { code, message }),
+            response => Ok(response),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackState {
+    Playing,
+    Static,
+    Loading,
+    Paused,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub scale: i32,
+    pub transform: String,
+    /// Position in the compositor's layout, when it reports one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+    /// Size in the compositor's layout, in logical pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logical_width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logical_height: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorStatus {
+    #[serde(flatten)]
+    pub output: MonitorInfo,
+    pub media: MediaType,
+    pub playback: PlaybackState,
+    pub fps: f32,
+    pub volume: f32,
+    /// Whether this output's video is currently playing its audio track
+    pub audio: bool,
+    /// Whether a running playlist is rotating this output's media
+    #[serde(default)]
+    pub playlist: bool,
+    /// Compile error of a shader edited on disk while the last good version keeps running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shader_error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> serde_json::Value {
+        let json = serde_json::to_string(value).unwrap();
+        let parsed: T = serde_json::from_str(&json).unwrap();
+        serde_json::to_value(parsed).unwrap()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request {
+                id: 1,
+                command: IpcCommand::Hello {
+                    version: PROTOCOL_VERSION,
+                    client: Some("test".to_string()),
+                },
+            },
+            Request {
+                id: 2,
+                command: IpcCommand::SetImage {
+                    path: "/tmp/a.png".to_string(),
+                    shader: None,
+                    monitors: Some(vec!["DP-1".to_string()]),
+                    fit: Fit::default(),
+                    transition: Transition::default(),
+                    wait: true,
+                },
+            },
+            Request {
+                id: 3,
+                command: IpcCommand::GetStatus,
+            },
+        ];
+        for request in &requests {
+            assert_eq!(round_trip(request), serde_json::to_value(request).unwrap());
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses = [
+            Response {
+                id: Some(1),
+                response: IpcResponse::Success,
+            },
+            Response {
+                id: None,
+                response: IpcError::new(ErrorCode::InvalidRequest, "bad").into(),
+            },
+            Response {
+                id: Some(2),
+                response: IpcResponse::Hello {
+                    version: PROTOCOL_VERSION,
+                    daemon: "0.1.0".to_string(),
+                },
+            },
+        ];
+        for response in &responses {
+            assert_eq!(round_trip(response), serde_json::to_value(response).unwrap());
+        }
+    }
+
+    #[test]
+    fn reads_documented_lines() {
+        let request: Request = serde_json::from_value(json!({
+            "id": 2,
+            "command": {"SetImage": {"path": "/tmp/a.png", "shader": null, "monitors": ["DP-1"]}}
+        }))
+        .unwrap();
+        assert_eq!(request.id, 2);
+        assert!(matches!(request.command, IpcCommand::SetImage { wait: false, .. }));
+
+        let response: Response =
+            serde_json::from_str(r#"{"id":3,"response":"Success"}"#).unwrap();
+        assert_eq!(response.id, Some(3));
+        assert!(matches!(response.response, IpcResponse::Success));
+    }
+
+    #[test]
+    fn errors_without_code_are_internal() {
+        let response: IpcResponse =
+            serde_json::from_str(r#"{"Error":{"message":"old daemon"}}"#).unwrap();
+        let error = response.into_result().unwrap_err();
+        assert_eq!(error, IpcError::new(ErrorCode::Internal, "old daemon"));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::protocol::{Fit, MediaType, Transition};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// File extensions a playlist picks up, by the media they are played as
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "apng", "jpg", "jpeg", "bmp", "gif", "webp", "tif", "tiff", "tex",
+];
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov", "avi", "m4v"];
+pub const SHADER_EXTENSIONS: &[&str] = &["frag", "glsl"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistOrder {
+    #[default]
+    Sequential,
+    /// Random order, reshuffled each time the list is exhausted
+    Shuffle,
+}
+
+/// A `[[playlist]]` table, or the playlist of a `SetPlaylist` command. Media
+/// comes from `directory` and `media`, whose entries may be files or
+/// directories; directories are read without recursing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaylistConfig {
+    /// Outputs to rotate; all outputs when absent
+    pub monitors: Option<Vec<String>>,
+    pub directory: Option<String>,
+    pub media: Vec<String>,
+    /// Seconds between items
+    pub interval: u64,
+    pub order: PlaylistOrder,
+    /// Give every output its own position in the list instead of showing the
+    /// same item everywhere
+    pub per_output: bool,
+    /// Shader applied to images and videos
+    pub shader: Option<String>,
+    pub fit: Fit,
+    pub mute: bool,
+    pub transition: Transition,
+    pub schedule: Vec<Schedule>,
+}
+
+impl Default for PlaylistConfig {
+    fn default() -> Self {
+        Self {
+            monitors: None,
+            directory: None,
+            media: Vec::new(),
+            interval: 300,
+            order: PlaylistOrder::default(),
+            per_output: false,
+            shader: None,
+            fit: Fit::default(),
+            mute: false,
+            transition: Transition::default(),
+            schedule: Vec::new(),
+        }
+    }
+}
+
+/// A `[[playlist.schedule]]` table: between `from` and `to`, as local `HH:MM`,
+/// the playlist draws from this media instead. A range may wrap past midnight,
+/// and the first matching entry wins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub from: String,
+    pub to: String,
+    pub directory: Option<String>,
+    #[serde(default)]
+    pub media: Vec<String>,
+}
+
+impl Schedule {
+    fn contains(&self, minute: u32) -> Result<bool> {
+        let from = parse_time(&self.from)?;
+        let to = parse_time(&self.to)?;
+        Ok(if from <= to {
+            from == to || (from..to).contains(&minute)
+        } else {
+            minute >= from || minute < to
+        })
+    }
+}
+
+/// Minutes since midnight of an `HH:MM` time.
+fn parse_time(time: &str) -> Result<u32> {
+    let parsed = time
+        .split_once(':')
+        .and_then(|(h, m)| Some((h.trim().parse::<u32>().ok()?, m.trim().parse::<u32>().ok()?)));
+    match parsed {
+        Some((hour, minute)) if hour < 24 && minute < 60 => Ok(hour * 60 + minute),
+        _ => Err(anyhow!("Invalid time {:?}: expected HH:MM", time)),
+    }
+}
+
+impl PlaylistConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.interval == 0 {
+            return Err(anyhow!("Playlist interval must be at least 1 second"));
+        }
+        if self.directory.is_none() && self.media.is_empty() {
+            return Err(anyhow!("Playlist needs a directory or media list"));
+        }
+        for entry in &self.schedule {
+            parse_time(&entry.from)?;
+            parse_time(&entry.to)?;
+            if entry.directory.is_none() && entry.media.is_empty() {
+                return Err(anyhow!(
+                    "Playlist schedule {}-{} needs a directory or media list",
+                    entry.from,
+                    entry.to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Index of the schedule entry in force at `minute`, minutes since
+    /// midnight, if any.
+    pub fn active_schedule(&self, minute: u32) -> Option<usize> {
+        self.schedule
+            .iter()
+            .position(|entry| entry.contains(minute).unwrap_or(false))
+    }
+
+    pub fn media_type(&self, path: &Path) -> Option<MediaType> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        let path = path.to_string_lossy().into_owned();
+        let shader = self
+            .shader
+            .as_ref()
+            .map(|s| shellexpand::tilde(s).to_string());
+        if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(MediaType::Image {
+                path,
+                shader,
+                fit: self.fit,
+            })
+        } else if VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            Some(MediaType::Video {
+                path,
+                shader,
+                fit: self.fit,
+            })
+        } else if SHADER_EXTENSIONS.contains(&extension.as_str()) {
+            Some(MediaType::Shader(path))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(from: &str, to: &str) -> Schedule {
+        Schedule {
+            from: from.to_string(),
+            to: to.to_string(),
+            directory: Some("~/dark".to_string()),
+            media: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("00:00").unwrap(), 0);
+        assert_eq!(parse_time("20:00").unwrap(), 20 * 60);
+        assert_eq!(parse_time(" 7:05 ").unwrap(), 7 * 60 + 5);
+        assert_eq!(parse_time("23:59").unwrap(), 23 * 60 + 59);
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        for time in ["24:00", "12:60", "12", "12:xx", "", "-1:00"] {
+            assert!(parse_time(time).is_err(), "{:?} should not parse", time);
+        }
+    }
+
+    #[test]
+    fn matches_ranges() {
+        let day = schedule("08:00", "20:00");
+        assert!(!day.contains(7 * 60 + 59).unwrap());
+        assert!(day.contains(8 * 60).unwrap());
+        assert!(day.contains(19 * 60 + 59).unwrap());
+        assert!(!day.contains(20 * 60).unwrap());
+
+        let night = schedule("20:00", "06:00");
+        assert!(night.contains(20 * 60).unwrap());
+        assert!(night.contains(23 * 60 + 59).unwrap());
+        assert!(night.contains(0).unwrap());
+        assert!(!night.contains(6 * 60).unwrap());
+        assert!(!night.contains(12 * 60).unwrap());
+
+        // Equal ends cover the whole day
+        let always = schedule("10:00", "10:00");
+        assert!(always.contains(0).unwrap());
+        assert!(always.contains(23 * 60 + 59).unwrap());
+    }
+
+    #[test]
+    fn first_matching_schedule_wins() {
+        let config = PlaylistConfig {
+            directory: Some("~/walls".to_string()),
+            schedule: vec![
+                schedule("20:00", "06:00"),
+                schedule("18:00", "23:00"),
+                schedule("bad", "23:00"),
+            ],
+            ..PlaylistConfig::default()
+        };
+        assert_eq!(config.active_schedule(12 * 60), None);
+        assert_eq!(config.active_schedule(19 * 60), Some(1));
+        assert_eq!(config.active_schedule(21 * 60), Some(0));
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable overriding the socket path.
+pub const SOCKET_ENV: &str = "PAPYRUST_SOCKET";
+
+/// `$XDG_RUNTIME_DIR/papyrust-$WAYLAND_DISPLAY.sock`, so every user and every
+/// Wayland session talks to its own daemon.
+pub fn default_path() -> PathBuf {
+    let display = env::var("WAYLAND_DISPLAY")
+        .ok()
+        .filter(|display| !display.is_empty())
+        .unwrap_or_else(|| "wayland-0".to_string());
+    // WAYLAND_DISPLAY may also be the absolute path of the compositor's socket
+    let display = Path::new(&display)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or(display);
+
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => {
+            PathBuf::from(dir).join(format!("papyrust-{}.sock", display))
+        }
+        _ => {
+            let uid = unsafe { libc::getuid() };
+            env::temp_dir().join(format!("papyrust-{}-{}.sock", uid, display))
+        }
+    }
+}
+
+/// The socket given on the command line, else `PAPYRUST_SOCKET`, else
+/// [`default_path`].
+pub fn path(flag: Option<PathBuf>) -> PathBuf {
+    flag.or_else(|| {
+        env::var_os(SOCKET_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+    .unwrap_or_else(default_path)
+}
@@ -1,11 +1,27 @@
 use iced::{
     alignment::{Horizontal, Vertical},
-    widget::{button, mouse_area, text, Button, Column, Container, Row, Space},
+    widget::{
+        button, checkbox, image, mouse_area, pick_list, scrollable, slider, text, text_input,
+        Button, Column, Container, Row, Space, Stack,
+    },
     Background, Border, Color, Element, Length, Padding, Shadow, Vector,
 };
 use iced_video_player::VideoPlayer;
 
-use crate::{ui::loader::project::Project, Message, Papyrust};
+use crate::{
+    ui::{
+        ipc::MonitorInfo,
+        loader::{
+            project::{Project, ProjectType},
+            properties::{ComboOption, Property, PropertyKind, PropertyValue},
+        },
+    },
+    Message, Papyrust,
+};
+
+const PROPERTIES_HEIGHT: f32 = 180.0;
+const LAYOUT_WIDTH: f32 = 600.0;
+const LAYOUT_HEIGHT: f32 = 100.0;
 
 pub fn build<'a>(app: &'a Papyrust, project: &'a Project) -> Element<'a, Message> {
     let title = project.meta.title.as_deref().unwrap_or("Untitled");
@@ -81,9 +97,17 @@ pub fn build<'a>(app: &'a Papyrust, project: &'a Project) -> Element<'a, Message
         .align_x(Horizontal::Center)
         .width(Length::Fill);
 
-    let popup_content = Column::new()
-        .push(header)
-        .push(video_preview)
+    let mut popup_content = Column::new().push(header).push(video_preview);
+    let mut popup_height = 600.0;
+    if !app.monitors.is_empty() {
+        popup_content = popup_content.push(create_monitor_picker(app));
+        popup_height += LAYOUT_HEIGHT + 20.0;
+    }
+    if !app.popup_properties.is_empty() {
+        popup_content = popup_content.push(create_properties(&app.popup_properties));
+        popup_height += PROPERTIES_HEIGHT + 20.0;
+    }
+    let popup_content = popup_content
         .push(Space::new(Length::Fill, Length::Fill))
         .push(footer)
         .spacing(20)
@@ -93,7 +117,7 @@ pub fn build<'a>(app: &'a Papyrust, project: &'a Project) -> Element<'a, Message
 
     let popup = Container::new(popup_content)
         .width(Length::Fixed(800.0))
-        .height(Length::Fixed(600.0))
+        .height(Length::Fixed(popup_height))
         .style(|_theme| iced::widget::container::Style {
             background: Some(Background::Color(Color::from_rgba(0.05, 0.05, 0.05, 0.98))),
             border: Border {
@@ -129,6 +153,10 @@ pub fn build<'a>(app: &'a Papyrust, project: &'a Project) -> Element<'a, Message
 }
 
 fn create_preview<'a>(app: &'a Papyrust, project: &'a Project) -> Element<'a, Message> {
+    if project.meta.file_type != Some(ProjectType::Video) {
+        return create_image_preview(project);
+    }
+
     if let Some(file_name) = &project.meta.file {
         let video_path = format!("{}/{}", project.path, file_name);
 
@@ -220,3 +248,247 @@ fn create_preview<'a>(app: &'a Papyrust, project: &'a Project) -> Element<'a, Me
         .into()
     }
 }
+
+/// Scene and web projects show their preview image rather than a player.
+fn create_image_preview<'a>(project: &'a Project) -> Element<'a, Message> {
+    let video_width = 720.0;
+    let video_height = 405.0;
+
+    let content: Element<'a, Message> = if let Some(preview) = &project.meta.preview {
+        let preview_path = format!("{}/{}", project.path, preview);
+        image(image::Handle::from_path(preview_path))
+            .width(Length::Fixed(video_width))
+            .height(Length::Fixed(video_height))
+            .into()
+    } else {
+        text("No preview available")
+            .size(18)
+            .style(|_theme| iced::widget::text::Style {
+                color: Some(Color::from_rgba(1.0, 1.0, 1.0, 0.8)),
+                ..Default::default()
+            })
+            .into()
+    };
+
+    Container::new(content)
+        .width(Length::Fixed(video_width))
+        .height(Length::Fixed(video_height))
+        .style(|_theme| iced::widget::container::Style {
+            background: Some(Background::Color(Color::from_rgba(0.15, 0.15, 0.15, 0.9))),
+            border: Border {
+                radius: 12.0.into(),
+                width: 2.0,
+                color: Color::from_rgba(0.4, 0.4, 0.4, 0.5),
+            },
+            ..Default::default()
+        })
+        .align_x(Horizontal::Center)
+        .align_y(Vertical::Center)
+        .into()
+}
+
+fn monitor_button<'a>(label: &str, selected: bool, message: Message) -> Button<'a, Message> {
+    Button::new(
+        text(label.to_string())
+            .size(12)
+            .align_x(Horizontal::Center)
+            .align_y(Vertical::Center)
+            .width(Length::Fill)
+            .height(Length::Fill),
+    )
+    .on_press(message)
+    .padding(4)
+    .style(move |_theme, status| {
+        let base = if selected {
+            Color::from_rgba(0.35, 0.35, 0.35, 0.95)
+        } else {
+            Color::from_rgba(0.2, 0.2, 0.2, 0.8)
+        };
+        let hover = Color::from_rgba(0.3, 0.3, 0.3, 0.9);
+        let border_color = if selected {
+            Color::from_rgba(0.9, 0.9, 0.9, 0.8)
+        } else {
+            Color::from_rgba(0.6, 0.6, 0.6, 0.5)
+        };
+
+        button::Style {
+            background: Some(Background::Color(
+                if !selected && matches!(status, iced::widget::button::Status::Hovered) {
+                    hover
+                } else {
+                    base
+                },
+            )),
+            border: Border {
+                radius: 4.0.into(),
+                width: 1.0,
+                color: border_color,
+            },
+            text_color: Color::WHITE,
+            ..Default::default()
+        }
+    })
+}
+
+/// Rectangles of the outputs in the compositor's layout. Outputs the daemon
+/// reports no position for are placed left to right after the others.
+fn monitor_rects(monitors: &[MonitorInfo]) -> Vec<(f32, f32, f32, f32)> {
+    let mut next_x = monitors
+        .iter()
+        .filter_map(|m| Some(m.x? as f32 + m.logical_width.unwrap_or(m.width) as f32))
+        .fold(0.0, f32::max);
+
+    monitors
+        .iter()
+        .map(|m| {
+            let width = m.logical_width.unwrap_or(m.width) as f32;
+            let height = m.logical_height.unwrap_or(m.height) as f32;
+            match (m.x, m.y) {
+                (Some(x), Some(y)) => (x as f32, y as f32, width, height),
+                _ => {
+                    let x = next_x;
+                    next_x += width;
+                    (x, 0.0, width, height)
+                }
+            }
+        })
+        .collect()
+}
+
+/// "All" and a scaled preview of the output layout, where each output can be
+/// toggled on its own.
+fn create_monitor_picker<'a>(app: &'a Papyrust) -> Element<'a, Message> {
+    let all = monitor_button("All", app.selected_monitors.is_empty(), Message::ToggleMonitor(None))
+        .width(Length::Fixed(80.0))
+        .height(Length::Fixed(40.0));
+
+    let rects = monitor_rects(&app.monitors);
+    let min_x = rects.iter().map(|r| r.0).fold(f32::INFINITY, f32::min);
+    let min_y = rects.iter().map(|r| r.1).fold(f32::INFINITY, f32::min);
+    let max_x = rects.iter().map(|r| r.0 + r.2).fold(f32::NEG_INFINITY, f32::max);
+    let max_y = rects.iter().map(|r| r.1 + r.3).fold(f32::NEG_INFINITY, f32::max);
+    let scale = (LAYOUT_WIDTH / (max_x - min_x).max(1.0))
+        .min(LAYOUT_HEIGHT / (max_y - min_y).max(1.0));
+
+    let outputs = app.monitors.iter().zip(rects).map(|(monitor, (x, y, w, h))| {
+        let selected = app.selected_monitors.contains(&monitor.name);
+        let output = monitor_button(
+            &monitor.name,
+            selected,
+            Message::ToggleMonitor(Some(monitor.name.clone())),
+        )
+        .width(Length::Fixed((w * scale - 2.0).max(1.0)))
+        .height(Length::Fixed((h * scale - 2.0).max(1.0)));
+
+        Container::new(output)
+            .padding(Padding {
+                top: (y - min_y) * scale,
+                right: 0.0,
+                bottom: 0.0,
+                left: (x - min_x) * scale,
+            })
+            .into()
+    });
+
+    let layout = Stack::with_children(outputs)
+        .width(Length::Fixed(LAYOUT_WIDTH))
+        .height(Length::Fixed(LAYOUT_HEIGHT));
+
+    Row::new()
+        .push(all)
+        .push(layout)
+        .spacing(20)
+        .width(Length::Fixed(720.0))
+        .align_y(Vertical::Center)
+        .into()
+}
+
+fn create_properties<'a>(properties: &'a [Property]) -> Element<'a, Message> {
+    let rows = properties.iter().map(|property| {
+        Row::new()
+            .push(
+                text(&property.label)
+                    .size(14)
+                    .width(Length::Fixed(220.0))
+                    .style(|_theme| iced::widget::text::Style {
+                        color: Some(Color::from_rgba(1.0, 1.0, 1.0, 0.8)),
+                        ..Default::default()
+                    }),
+            )
+            .push(create_control(property))
+            .spacing(12)
+            .align_y(Vertical::Center)
+            .into()
+    });
+
+    scrollable(Column::with_children(rows).spacing(10).padding(Padding::from([0, 16])))
+        .height(Length::Fixed(PROPERTIES_HEIGHT))
+        .width(Length::Fixed(720.0))
+        .into()
+}
+
+fn create_control<'a>(property: &'a Property) -> Element<'a, Message> {
+    let key = property.key.clone();
+    match (&property.kind, &property.value) {
+        (PropertyKind::Bool, PropertyValue::Bool(checked)) => checkbox("", *checked)
+            .on_toggle(move |checked| {
+                Message::PropertyChanged(key.clone(), PropertyValue::Bool(checked))
+            })
+            .into(),
+        (PropertyKind::Slider { min, max, step }, PropertyValue::Number(value)) => Row::new()
+            .push(
+                slider(*min..=*max, *value, move |value| {
+                    Message::PropertyChanged(key.clone(), PropertyValue::Number(value))
+                })
+                .step(*step)
+                .width(Length::Fixed(320.0)),
+            )
+            .push(text(format!("{:.2}", value)).size(14))
+            .spacing(10)
+            .align_y(Vertical::Center)
+            .into(),
+        (PropertyKind::Color, PropertyValue::Color(rgb)) => {
+            let rgb = *rgb;
+            let channels = (0..3).map(|channel| {
+                let key = key.clone();
+                slider(0.0..=1.0, rgb[channel], move |value| {
+                    let mut rgb = rgb;
+                    rgb[channel] = value;
+                    Message::PropertyChanged(key.clone(), PropertyValue::Color(rgb))
+                })
+                .step(0.01)
+                .width(Length::Fixed(100.0))
+                .into()
+            });
+            let swatch = Container::new(Space::new(Length::Fixed(24.0), Length::Fixed(24.0)))
+                .style(move |_theme| iced::widget::container::Style {
+                    background: Some(Background::Color(Color::from_rgb(rgb[0], rgb[1], rgb[2]))),
+                    border: Border {
+                        radius: 4.0.into(),
+                        width: 1.0,
+                        color: Color::from_rgba(0.6, 0.6, 0.6, 0.5),
+                    },
+                    ..Default::default()
+                });
+            Row::with_children(channels)
+                .push(swatch)
+                .spacing(8)
+                .align_y(Vertical::Center)
+                .into()
+        }
+        (PropertyKind::Combo(options), PropertyValue::Text(value)) => {
+            let selected = options.iter().find(|option| &option.value == value).cloned();
+            pick_list(options.as_slice(), selected, move |option: ComboOption| {
+                Message::PropertyChanged(key.clone(), PropertyValue::Text(option.value))
+            })
+            .text_size(14)
+            .into()
+        }
+        (PropertyKind::Text, PropertyValue::Text(value)) => text_input("", value)
+            .on_input(move |value| Message::PropertyChanged(key.clone(), PropertyValue::Text(value)))
+            .size(14)
+            .width(Length::Fixed(320.0))
+            .into(),
+        _ => Space::new(Length::Shrink, Length::Shrink).into(),
+    }
+}
@@ -1,57 +1,123 @@
-use anyhow::Result;
-use serde_json::json;
-use std::io::{BufRead, BufReader, Write};
-use std::os::unix::net::UnixStream;
+use anyhow::{anyhow, Result};
+use futures::channel::mpsc;
+use futures::Stream;
+use papyrust::protocol::{Client, Fit, IpcCommand, IpcResponse, Transition, Uniforms};
+use std::thread;
+use std::time::Duration;
 
 use tracing::info;
-const SOCKET_PATH: &str = "/tmp/papyrust-daemon.sock";
-
-pub fn _set_image(monitor: String, path: String, shader: Option<String>) -> Result<()> {
-    let cmd = json!({
-        "SetImage": {
-            "path": path,
-            "shader": shader,
-            "monitor": monitor
-        }
-    });
-    send_command(cmd)
+
+pub use papyrust::protocol::{Event, MonitorInfo};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Commands target every output when `monitors` is `None`.
+pub fn set_image(
+    monitors: Option<Vec<String>>,
+    path: String,
+    shader: Option<String>,
+) -> Result<()> {
+    send_command(IpcCommand::SetImage {
+        path,
+        shader,
+        monitors,
+        fit: Fit::default(),
+        transition: Transition::default(),
+        wait: false,
+    })
 }
 
-pub fn set_video(_monitor: String, path: String, shader: Option<String>) -> Result<()> {
-    let cmd = json!({
-        "SetVideo": {
-            "path": path,
-            "shader": shader,
-            // "monitor": monitor
-        }
-    });
-    send_command(cmd)
+pub fn set_video(
+    monitors: Option<Vec<String>>,
+    path: String,
+    shader: Option<String>,
+) -> Result<()> {
+    send_command(IpcCommand::SetVideo {
+        path,
+        shader,
+        monitors,
+        mute: false,
+        fit: Fit::default(),
+        transition: Transition::default(),
+        wait: false,
+    })
 }
 
-pub fn _set_shader(monitor: String, path: String) -> Result<()> {
-    let cmd = json!({
-        "SetShader": {
-            "path": path,
-            "monitor": monitor
-        }
-    });
-    send_command(cmd)
+/// Sets shader uniforms, replacing those set before.
+pub fn set_uniforms(monitors: Option<Vec<String>>, uniforms: Uniforms) -> Result<()> {
+    send_command(IpcCommand::SetUniforms { uniforms, monitors })
 }
 
+pub fn _set_shader(monitors: Option<Vec<String>>, path: String) -> Result<()> {
+    send_command(IpcCommand::SetShader {
+        path,
+        monitors,
+        transition: Transition::default(),
+        wait: false,
+    })
+}
 
-fn send_command(cmd: serde_json::Value) -> Result<()> {
-    tracing::debug!(event = "ui_send_cmd", cmd = %cmd, "Sending IPC command");
+pub fn list_monitors() -> Result<Vec<MonitorInfo>> {
+    match request(IpcCommand::ListMonitors)? {
+        IpcResponse::Monitors { monitors } => Ok(monitors),
+        response => Err(anyhow!("Unexpected reply to ListMonitors: {:?}", response)),
+    }
+}
 
-    let mut stream = UnixStream::connect(SOCKET_PATH)?;
-    writeln!(stream, "{}", cmd)?;
-    stream.flush()?;
+/// Events from the daemon, reconnecting while it is not running.
+pub fn events() -> impl Stream<Item = Event> {
+    let (tx, rx) = mpsc::unbounded();
+    thread::spawn(move || loop {
+        match connect().and_then(Client::subscribe) {
+            Ok(events) => {
+                for event in events {
+                    let event = match event {
+                        Ok(event) => event,
+                        // The connection broke; reconnect
+                        Err(e) if e.is::<std::io::Error>() => break,
+                        // An event this GUI does not know, say from a newer daemon
+                        Err(e) => {
+                            tracing::warn!(event = "ui_event_invalid", error = %e, "Skipping unreadable event");
+                            continue;
+                        }
+                    };
+                    // The GUI stopped listening
+                    if tx.unbounded_send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) => tracing::debug!(
+                event = "ui_subscribe_error",
+                error = %e,
+                "Cannot subscribe to daemon events"
+            ),
+        }
+        if tx.is_closed() {
+            return;
+        }
+        thread::sleep(RECONNECT_DELAY);
+    });
+    rx
+}
 
-    let mut reader = BufReader::new(stream);
-    let mut response = String::new();
-    reader.read_line(&mut response)?;
+fn connect() -> Result<Client> {
+    Client::connect(&papyrust::socket::path(None), "papyrust")
+}
 
-    info!("{}", response.trim());
-    tracing::debug!(event = "ui_recv_reply", reply = %response.trim(), "Received IPC reply");
+fn send_command(cmd: IpcCommand) -> Result<()> {
+    let reply = request(cmd)?;
+    info!("{:?}", reply);
     Ok(())
 }
 
+/// Sends one command and returns the daemon's reply, turning `Error` replies
+/// into errors.
+fn request(cmd: IpcCommand) -> Result<IpcResponse> {
+    tracing::debug!(event = "ui_send_cmd", cmd = ?cmd, "Sending IPC command");
+
+    let reply = connect()?.request(cmd)?;
+
+    tracing::debug!(event = "ui_recv_reply", reply = ?reply, "Received IPC reply");
+    Ok(reply)
+}
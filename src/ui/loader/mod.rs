@@ -2,6 +2,8 @@ use crate::ui::loader::project::{Project, ProjectMeta};
 use std::{fs, path::PathBuf};
 
 pub mod project;
+pub mod properties;
+pub mod scene;
 
 const WALLPAPER_ENGINE_ID: &str = "431960";
 const WORKSHOP_PATHS: [&str; 4] = [
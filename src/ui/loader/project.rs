@@ -22,6 +22,9 @@ pub struct ProjectMeta {
     pub file_type: Option<ProjectType>,
     pub preview: Option<String>,
     pub file: Option<String>,
+    /// Holds `properties`, the user properties read by
+    /// [`properties::load`](super::properties::load)
+    pub general: Option<serde_json::Value>,
 }
 
 #[derive(Clone, Debug)]
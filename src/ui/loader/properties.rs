@@ -0,0 +1,426 @@
+use papyrust::protocol::{validate_uniform, UniformValue, Uniforms};
+use serde_json::{Map, Value, json};
+use std::{fmt, fs, path::PathBuf};
+
+use crate::ui::loader::project::Project;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComboOption {
+    pub label: String,
+    pub value: String,
+}
+
+impl fmt::Display for ComboOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyKind {
+    Bool,
+    Slider { min: f32, max: f32, step: f32 },
+    Color,
+    Combo(Vec<ComboOption>),
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Number(f32),
+    /// Red, green and blue from 0.0 to 1.0
+    Color([f32; 3]),
+    Text(String),
+}
+
+/// A user property declared in `general.properties` of a project.
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub key: String,
+    pub label: String,
+    pub kind: PropertyKind,
+    pub value: PropertyValue,
+}
+
+impl Property {
+    fn parse(key: &str, raw: &Value) -> Option<Self> {
+        let number = |name: &str| raw.get(name).and_then(as_number);
+        let value = raw.get("value").unwrap_or(&Value::Null);
+
+        let (kind, value) = match raw.get("type")?.as_str()? {
+            "bool" => (PropertyKind::Bool, PropertyValue::Bool(as_bool(value)?)),
+            "slider" => {
+                let min = number("min").unwrap_or(0.0);
+                let max = number("max").unwrap_or(100.0).max(min);
+                let fraction = raw.get("fraction").and_then(as_bool) == Some(true);
+                let step = number("step")
+                    .filter(|s| *s > 0.0)
+                    .unwrap_or(if fraction { 0.01 } else { 1.0 });
+                let value = as_number(value).unwrap_or(min).clamp(min, max);
+                (PropertyKind::Slider { min, max, step }, PropertyValue::Number(value))
+            }
+            "color" => (PropertyKind::Color, PropertyValue::Color(as_color(value)?)),
+            "combo" => {
+                let options: Vec<ComboOption> = raw
+                    .get("options")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|option| {
+                        let value = as_string(option.get("value")?)?;
+                        let label = option
+                            .get("label")
+                            .and_then(Value::as_str)
+                            .map(clean_label)
+                            .filter(|label| !label.is_empty())
+                            .unwrap_or_else(|| value.clone());
+                        Some(ComboOption { label, value })
+                    })
+                    .collect();
+                let value = as_string(value).or_else(|| options.first().map(|o| o.value.clone()))?;
+                (PropertyKind::Combo(options), PropertyValue::Text(value))
+            }
+            "textinput" => (PropertyKind::Text, PropertyValue::Text(as_string(value).unwrap_or_default())),
+            // Group headings, files, directories and shortcuts have no value to edit
+            _ => return None,
+        };
+
+        let label = raw
+            .get("text")
+            .and_then(Value::as_str)
+            .map(clean_label)
+            .filter(|label| !label.is_empty())
+            .unwrap_or_else(|| key.to_string());
+        Some(Self {
+            key: key.to_string(),
+            label,
+            kind,
+            value,
+        })
+    }
+
+    /// Value sent to the daemon as a shader uniform, if it has a numeric form.
+    pub fn uniform(&self) -> Option<UniformValue> {
+        match &self.value {
+            PropertyValue::Bool(b) => Some(UniformValue::Bool(*b)),
+            PropertyValue::Number(n) => Some(UniformValue::Float(*n)),
+            PropertyValue::Color(rgb) => Some(UniformValue::Vector(rgb.to_vec())),
+            PropertyValue::Text(text) => text.trim().parse().ok().map(UniformValue::Float),
+        }
+    }
+
+    fn saved_value(&self, saved: &Value) -> Option<PropertyValue> {
+        match self.kind {
+            PropertyKind::Bool => as_bool(saved).map(PropertyValue::Bool),
+            PropertyKind::Slider { min, max, .. } => {
+                as_number(saved).map(|n| PropertyValue::Number(n.clamp(min, max)))
+            }
+            PropertyKind::Color => as_color(saved).map(PropertyValue::Color),
+            PropertyKind::Combo(_) | PropertyKind::Text => as_string(saved).map(PropertyValue::Text),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match &self.value {
+            PropertyValue::Bool(b) => json!(b),
+            PropertyValue::Number(n) => json!(n),
+            PropertyValue::Color(rgb) => json!(rgb),
+            PropertyValue::Text(text) => json!(text),
+        }
+    }
+}
+
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_f64().map(|n| n != 0.0),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_number(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(*b as u8 as f32),
+        _ => None,
+    }
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Colours are written as `"r g b"` from 0 to 1, though some projects use 0
+/// to 255.
+fn as_color(value: &Value) -> Option<[f32; 3]> {
+    let components: Vec<f32> = match value {
+        Value::String(s) => s.split_whitespace().filter_map(|c| c.parse().ok()).collect(),
+        Value::Array(a) => a.iter().filter_map(as_number).collect(),
+        _ => return None,
+    };
+    let [r, g, b] = components.get(..3)?.try_into().ok()?;
+    let scale = if r.max(g).max(b) > 1.0 { 255.0 } else { 1.0 };
+    Some([r, g, b].map(|c| (c / scale).clamp(0.0, 1.0)))
+}
+
+/// Labels are often HTML or Wallpaper Engine translation keys such as
+/// `ui_browse_properties_scheme_color`.
+fn clean_label(text: &str) -> String {
+    if let Some(key) = text.strip_prefix("ui_browse_properties_") {
+        let words = key.replace('_', " ");
+        let mut chars = words.chars();
+        return chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+    }
+
+    let mut label = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => label.push(c),
+            _ => {}
+        }
+    }
+    label.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Editable properties of `project` in declaration order, with the values
+/// saved for it applied.
+pub fn load(project: &Project) -> Vec<Property> {
+    let Some(raw) = project
+        .meta
+        .general
+        .as_ref()
+        .and_then(|general| general.get("properties"))
+        .and_then(Value::as_object)
+    else {
+        return Vec::new();
+    };
+
+    let mut properties: Vec<(i64, Property)> = raw
+        .iter()
+        .filter_map(|(key, raw)| {
+            let order = raw.get("order").and_then(Value::as_i64).unwrap_or(i64::MAX);
+            Some((order, Property::parse(key, raw)?))
+        })
+        .collect();
+    properties.sort_by_key(|(order, _)| *order);
+    let mut properties: Vec<Property> = properties.into_iter().map(|(_, p)| p).collect();
+
+    if let Some(saved) = read_saved(project) {
+        for property in &mut properties {
+            if let Some(value) = saved.get(&property.key).and_then(|v| property.saved_value(v)) {
+                property.value = value;
+            }
+        }
+    }
+    properties
+}
+
+/// Uniforms for the daemon, by property key.
+pub fn uniforms(properties: &[Property]) -> Uniforms {
+    properties
+        .iter()
+        .filter_map(|property| Some((property.key.clone(), property.uniform()?)))
+        // Keys that are not GLSL identifiers would make the daemon reject them all
+        .filter(|(key, value)| validate_uniform(key, value).is_ok())
+        .collect()
+}
+
+fn saved_path(project: &Project) -> Option<PathBuf> {
+    let name = PathBuf::from(&project.path).file_name()?.to_string_lossy().to_string();
+    let base = std::env::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| shellexpand::tilde("~/.config").to_string());
+    Some(
+        PathBuf::from(base)
+            .join("papyrust")
+            .join("projects")
+            .join(format!("{}.json", name)),
+    )
+}
+
+fn read_saved(project: &Project) -> Option<Map<String, Value>> {
+    let content = fs::read_to_string(saved_path(project)?).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Saves the property values chosen for `project`.
+pub fn save(project: &Project, properties: &[Property]) -> Result<(), String> {
+    let path = saved_path(project).ok_or("Project has no directory name")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let values: Map<String, Value> = properties
+        .iter()
+        .map(|property| (property.key.clone(), property.to_json()))
+        .collect();
+    let content = serde_json::to_string_pretty(&values).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: Value) -> Option<Property> {
+        Property::parse("key", &raw)
+    }
+
+    #[test]
+    fn parses_bool() {
+        let property = parse(json!({"type": "bool", "value": true, "text": "Show clock"})).unwrap();
+        assert_eq!(property.kind, PropertyKind::Bool);
+        assert_eq!(property.value, PropertyValue::Bool(true));
+        assert_eq!(property.label, "Show clock");
+    }
+
+    #[test]
+    fn parses_slider() {
+        let property = parse(json!({
+            "type": "slider", "min": 0, "max": 10, "value": 20, "fraction": true
+        }))
+        .unwrap();
+        assert_eq!(
+            property.kind,
+            PropertyKind::Slider { min: 0.0, max: 10.0, step: 0.01 }
+        );
+        assert_eq!(property.value, PropertyValue::Number(10.0));
+
+        let property = parse(json!({"type": "slider", "min": "5", "value": "7", "step": 2})).unwrap();
+        assert_eq!(
+            property.kind,
+            PropertyKind::Slider { min: 5.0, max: 100.0, step: 2.0 }
+        );
+        assert_eq!(property.value, PropertyValue::Number(7.0));
+    }
+
+    #[test]
+    fn parses_color() {
+        let property = parse(json!({"type": "color", "value": "0 0.5 1"})).unwrap();
+        assert_eq!(property.kind, PropertyKind::Color);
+        assert_eq!(property.value, PropertyValue::Color([0.0, 0.5, 1.0]));
+        assert!(parse(json!({"type": "color", "value": "0 0.5"})).is_none());
+    }
+
+    #[test]
+    fn parses_combo() {
+        let property = parse(json!({
+            "type": "combo",
+            "options": [
+                {"label": "<b>Red</b>", "value": 1},
+                {"label": "", "value": "blue"},
+                {"label": "No value"}
+            ]
+        }))
+        .unwrap();
+        let PropertyKind::Combo(options) = &property.kind else {
+            panic!("expected a combo, got {:?}", property.kind);
+        };
+        let options: Vec<(&str, &str)> = options
+            .iter()
+            .map(|o| (o.label.as_str(), o.value.as_str()))
+            .collect();
+        assert_eq!(options, [("Red", "1"), ("blue", "blue")]);
+        // Without a value the first option is selected
+        assert_eq!(property.value, PropertyValue::Text("1".to_string()));
+    }
+
+    #[test]
+    fn parses_textinput() {
+        let property = parse(json!({"type": "textinput", "value": "hello"})).unwrap();
+        assert_eq!(property.kind, PropertyKind::Text);
+        assert_eq!(property.value, PropertyValue::Text("hello".to_string()));
+        // The key stands in for a missing label
+        assert_eq!(property.label, "key");
+    }
+
+    #[test]
+    fn skips_properties_without_value() {
+        assert!(parse(json!({"type": "group", "text": "Colours"})).is_none());
+        assert!(parse(json!({"type": "file"})).is_none());
+        assert!(parse(json!({"value": true})).is_none());
+    }
+
+    #[test]
+    fn reads_colors_in_both_ranges() {
+        assert_eq!(as_color(&json!("1 0.5 0")), Some([1.0, 0.5, 0.0]));
+        assert_eq!(as_color(&json!("255 51 0")), Some([1.0, 0.2, 0.0]));
+        assert_eq!(as_color(&json!([0, "0", 255])), Some([0.0, 0.0, 1.0]));
+        assert_eq!(as_color(&json!("1 1")), None);
+        assert_eq!(as_color(&json!(1)), None);
+    }
+
+    #[test]
+    fn cleans_labels() {
+        assert_eq!(clean_label("ui_browse_properties_scheme_color"), "Scheme color");
+        assert_eq!(clean_label("ui_browse_properties_"), "");
+        assert_eq!(
+            clean_label("<span style=\"color: red\">Clock</span>  <br>size"),
+            "Clock size"
+        );
+        assert_eq!(clean_label("Plain"), "Plain");
+    }
+
+    #[test]
+    fn clamps_saved_values() {
+        let slider = parse(json!({"type": "slider", "min": 0, "max": 10, "value": 5})).unwrap();
+        assert_eq!(slider.saved_value(&json!(50)), Some(PropertyValue::Number(10.0)));
+        assert_eq!(slider.saved_value(&json!(-1)), Some(PropertyValue::Number(0.0)));
+        assert_eq!(slider.saved_value(&json!([1])), None);
+
+        let color = parse(json!({"type": "color", "value": "0 0 0"})).unwrap();
+        assert_eq!(
+            color.saved_value(&json!([0.0, 2.0, 0.5])),
+            Some(PropertyValue::Color([0.0, 2.0 / 255.0, 0.5 / 255.0]))
+        );
+
+        let toggle = parse(json!({"type": "bool", "value": false})).unwrap();
+        assert_eq!(toggle.saved_value(&json!("true")), Some(PropertyValue::Bool(true)));
+        assert_eq!(toggle.saved_value(&Value::Null), None);
+    }
+
+    #[test]
+    fn sends_only_valid_uniforms() {
+        let property = |key: &str, value: PropertyValue| Property {
+            key: key.to_string(),
+            label: key.to_string(),
+            kind: PropertyKind::Text,
+            value,
+        };
+        let properties = [
+            property("speed", PropertyValue::Number(2.0)),
+            property("tint", PropertyValue::Color([1.0, 0.0, 0.5])),
+            property("enabled", PropertyValue::Bool(true)),
+            property("count", PropertyValue::Text(" 3 ".to_string())),
+            property("name", PropertyValue::Text("not a number".to_string())),
+            property("two words", PropertyValue::Number(1.0)),
+            property("gl_Position", PropertyValue::Number(1.0)),
+            property("infinite", PropertyValue::Number(f32::INFINITY)),
+        ];
+
+        let uniforms = uniforms(&properties);
+        let keys: Vec<&str> = uniforms.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["count", "enabled", "speed", "tint"]);
+        assert_eq!(uniforms["tint"], UniformValue::Vector(vec![1.0, 0.0, 0.5]));
+        assert_eq!(uniforms["count"], UniformValue::Float(3.0));
+
+        assert!(validate_uniform("_ok1", &UniformValue::Vector(vec![0.0, 1.0])).is_ok());
+        assert!(validate_uniform("1st", &UniformValue::Float(0.0)).is_err());
+        assert!(validate_uniform("v", &UniformValue::Vector(vec![0.0; 5])).is_err());
+        assert!(validate_uniform("v", &UniformValue::Vector(vec![f32::NAN, 0.0])).is_err());
+    }
+}
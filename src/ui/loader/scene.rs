@@ -0,0 +1,163 @@
+use image::RgbaImage;
+use papyrust::wallpaper_engine::{Pkg, tex};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Files of a scene project, read from `scene.pkg` when the project ships
+/// one, or else from the project directory.
+struct SceneFiles {
+    dir: PathBuf,
+    pkg: Option<Pkg>,
+}
+
+impl SceneFiles {
+    fn open(dir: &Path) -> Result<Self, String> {
+        let pkg_path = dir.join("scene.pkg");
+        let pkg = if pkg_path.exists() {
+            Some(Pkg::open(&pkg_path).map_err(|e| e.to_string())?)
+        } else {
+            None
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            pkg,
+        })
+    }
+
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        if let Some(bytes) = self.pkg.as_ref().and_then(|pkg| pkg.read(name)) {
+            return Some(bytes.to_vec());
+        }
+        fs::read(self.dir.join(name)).ok()
+    }
+
+    fn json(&self, name: &str) -> Result<Value, String> {
+        let bytes = self
+            .read(name)
+            .ok_or_else(|| format!("{} not found in scene", name))?;
+        serde_json::from_slice(&bytes).map_err(|e| format!("JSON parse error in {}: {}", name, e))
+    }
+}
+
+/// Scene values bound to user properties are objects holding the default in
+/// `value`.
+fn plain(value: &Value) -> &Value {
+    value.get("value").unwrap_or(value)
+}
+
+/// Parses vectors written as space separated numbers, such as `"1920 1080"`.
+fn vector(value: Option<&Value>) -> Vec<f64> {
+    match value.map(plain) {
+        Some(Value::String(s)) => s
+            .split_whitespace()
+            .filter_map(|n| n.parse().ok())
+            .collect(),
+        Some(Value::Number(n)) => n.as_f64().into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Image layers of the scene, largest first.
+fn image_layers(scene: &Value) -> Vec<String> {
+    let mut layers: Vec<(f64, String)> = scene
+        .get("objects")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|object| object.get("visible").map(plain).and_then(Value::as_bool) != Some(false))
+        .filter_map(|object| {
+            let model = object.get("image")?.as_str()?.to_string();
+            let size = vector(object.get("size"));
+            let scale = vector(object.get("scale"));
+            let area = size.first().unwrap_or(&0.0)
+                * size.get(1).unwrap_or(&0.0)
+                * scale.first().unwrap_or(&1.0).abs()
+                * scale.get(1).unwrap_or(&1.0).abs();
+            Some((area, model))
+        })
+        .collect();
+    // Stable, so equally sized layers keep scene order
+    layers.sort_by(|a, b| b.0.total_cmp(&a.0));
+    layers.into_iter().map(|(_, model)| model).collect()
+}
+
+/// Follows an image layer's model to its material and decodes the first
+/// texture of the material's first pass.
+fn layer_texture(files: &SceneFiles, model: &str) -> Result<RgbaImage, String> {
+    let model = files.json(model)?;
+    let material = model
+        .get("material")
+        .and_then(Value::as_str)
+        .ok_or("Model has no material")?;
+    let material = files.json(material)?;
+    let texture = material
+        .get("passes")
+        .and_then(|passes| passes.get(0))
+        .and_then(|pass| pass.get("textures"))
+        .and_then(|textures| textures.get(0))
+        .and_then(Value::as_str)
+        .ok_or("Material has no texture")?;
+
+    let name = format!("materials/{}.tex", texture);
+    let bytes = files
+        .read(&name)
+        .ok_or_else(|| format!("{} not found in scene", name))?;
+    tex::decode(&bytes).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// Decodes the background of a scene project: the largest image layer whose
+/// texture ships with the project. Effects, particles and scripts are not
+/// rendered.
+pub fn background(dir: &Path) -> Result<RgbaImage, String> {
+    let files = SceneFiles::open(dir)?;
+    let scene = files.json("scene.json")?;
+
+    let mut last_error = "Scene has no image layers".to_string();
+    for model in image_layers(&scene) {
+        match layer_texture(&files, &model) {
+            Ok(image) => return Ok(image),
+            Err(e) => last_error = format!("{}: {}", model, e),
+        }
+    }
+    Err(last_error)
+}
+
+fn cache_dir() -> PathBuf {
+    let base = std::env::var("XDG_CACHE_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| shellexpand::tilde("~/.cache").to_string());
+    PathBuf::from(base).join("papyrust").join("scenes")
+}
+
+/// Path of a PNG of the scene's background, decoding it on first use.
+pub fn background_path(dir: &Path) -> Result<String, String> {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or("Scene project has no directory name")?;
+    let target = cache_dir().join(format!("{}.png", name));
+
+    let source_modified = ["scene.pkg", "scene.json"]
+        .iter()
+        .filter_map(|file| fs::metadata(dir.join(file)).and_then(|m| m.modified()).ok())
+        .max();
+    let cached_modified = fs::metadata(&target).and_then(|m| m.modified()).ok();
+    let fresh = matches!((cached_modified, source_modified), (Some(cached), Some(source)) if cached >= source);
+
+    if !fresh {
+        let image = background(dir)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        image
+            .save(&target)
+            .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+    }
+
+    Ok(target.to_string_lossy().to_string())
+}
@@ -34,8 +34,11 @@ impl Library {
         while let Some(result) = loader.next() {
             match result {
                 Ok(project) => {
-                    // Skip for now all non-video projects
-                    if project.meta.file_type != Some(ProjectType::Video) {
+                    // Application projects are Windows executables we can't run
+                    if !matches!(
+                        project.meta.file_type,
+                        Some(ProjectType::Video | ProjectType::Scene | ProjectType::Web)
+                    ) {
                         continue;
                     }
                     projects.push(project);
@@ -1,6 +1,14 @@
 use iced::{widget::image::Handle, Task};
+use std::path::PathBuf;
+use tokio::task;
 
 use tracing::error;
+use crate::ui::ipc;
+use crate::ui::loader::{
+    project::{Project, ProjectType},
+    properties::{self, Property},
+    scene,
+};
 use crate::{Message, Papyrust};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,21 +43,30 @@ pub fn update(app: &mut Papyrust, message: Message) -> Task<Message> {
             Task::none()
         }
         Message::OpenPopup(project) => {
+            app.popup_properties = properties::load(&project);
             app.popup_state = Some(project.clone());
 
+            let monitors = load_monitors();
+
+            if project.meta.file_type != Some(ProjectType::Video) {
+                return monitors;
+            }
             if let Some(file_name) = &project.meta.file {
                 let video_path = format!("{}/{}", project.path, file_name);
                 if app.should_load(&video_path) {
-                    return Papyrust::load_video_async(video_path);
+                    return Task::batch([monitors, Papyrust::load_video_async(video_path)]);
                 }
             }
-            Task::none()
+            monitors
         }
         Message::ClosePopup => {
             for video in app.videos.values_mut() {
                 video.set_paused(true);
             }
-            app.popup_state = None;
+            if let Some(project) = app.popup_state.take() {
+                save_properties(&project, &app.popup_properties);
+            }
+            app.popup_properties.clear();
             Task::none()
         }
         Message::ApplyProject(project) => {
@@ -58,16 +75,130 @@ pub fn update(app: &mut Papyrust, message: Message) -> Task<Message> {
                     app.popup_state = Some(project.clone());
                 }
             } else {
+                app.popup_properties = properties::load(&project);
                 app.popup_state = Some(project.clone());
             }
 
-            if let Some(file_name) = &project.meta.file {
-                let video_path = format!("{}/{}", project.path, file_name);
-                crate::ui::ipc::set_video("DP-2".to_string(), video_path, None).unwrap_or_else(
-                    |e| {
-                        error!("Failed to set video: {}", e);
-                    },
-                );
+            // Properties edited in the popup belong to the project it shows
+            let in_popup = app
+                .popup_state
+                .as_ref()
+                .is_some_and(|popup| popup.path == project.path);
+            let project_properties = if in_popup {
+                app.popup_properties.clone()
+            } else {
+                properties::load(&project)
+            };
+            save_properties(&project, &project_properties);
+            let targets = selected_targets(app);
+
+            // Each command waits for the daemon to load the media, so they
+            // are sent off the UI thread
+            match project.meta.file_type {
+                Some(ProjectType::Video) => {
+                    let uniforms = properties::uniforms(&project_properties);
+                    let video_path = project
+                        .meta
+                        .file
+                        .as_ref()
+                        .map(|file_name| format!("{}/{}", project.path, file_name));
+                    send_in_background(move || {
+                        if let Err(e) = ipc::set_uniforms(targets.clone(), uniforms) {
+                            error!("Failed to set uniforms: {}", e);
+                        }
+                        match video_path {
+                            Some(path) => ipc::set_video(targets, path, None)
+                                .map_err(|e| format!("Failed to set video: {}", e)),
+                            None => Ok(()),
+                        }
+                    })
+                }
+                // Scene and web projects are shown as a still image, which no
+                // scene parameter or script can drive, so their values are only saved
+                Some(ProjectType::Scene) => {
+                    let dir = PathBuf::from(&project.path);
+                    Task::perform(
+                        async move {
+                            let result = task::spawn_blocking(move || scene::background_path(&dir))
+                                .await
+                                .unwrap_or_else(|e| Err(format!("Task error: {}", e)));
+                            (targets, result)
+                        },
+                        |(targets, result)| Message::SceneExtracted(targets, result),
+                    )
+                }
+                // Web wallpapers need a browser, so show their preview instead
+                Some(ProjectType::Web) => match &project.meta.preview {
+                    Some(preview) => {
+                        let preview_path = format!("{}/{}", project.path, preview);
+                        send_in_background(move || {
+                            ipc::set_image(targets, preview_path, None)
+                                .map_err(|e| format!("Failed to set image: {}", e))
+                        })
+                    }
+                    None => Task::none(),
+                },
+                _ => Task::none(),
+            }
+        }
+        Message::SceneExtracted(targets, result) => match result {
+            Ok(path) => send_in_background(move || {
+                ipc::set_image(targets, path, None).map_err(|e| format!("Failed to set image: {}", e))
+            }),
+            Err(e) => {
+                error!("Failed to extract scene: {}", e);
+                Task::none()
+            }
+        },
+        Message::WallpaperSent(result) => {
+            if let Err(e) = result {
+                error!("{}", e);
+            }
+            Task::none()
+        }
+        Message::PropertyChanged(key, value) => {
+            if let Some(property) = app.popup_properties.iter_mut().find(|p| p.key == key) {
+                property.value = value;
+            }
+            Task::none()
+        }
+        Message::MonitorsLoaded(result) => {
+            match result {
+                Ok(monitors) => {
+                    // Forget outputs that have since been unplugged
+                    app.selected_monitors
+                        .retain(|name| monitors.iter().any(|monitor| &monitor.name == name));
+                    app.monitors = monitors;
+                }
+                Err(e) => {
+                    error!("Failed to list monitors: {}", e);
+                    app.monitors.clear();
+                }
+            }
+            Task::none()
+        }
+        Message::DaemonEvent(event) => match event {
+            // Keeps the monitor picker in step with outputs coming and going
+            ipc::Event::OutputAdded { .. } | ipc::Event::OutputRemoved { .. }
+                if app.popup_state.is_some() =>
+            {
+                load_monitors()
+            }
+            ipc::Event::ShaderError { path, error } => {
+                error!("Shader {} failed to compile: {}", path, error);
+                Task::none()
+            }
+            _ => Task::none(),
+        },
+        Message::ToggleMonitor(None) => {
+            app.selected_monitors.clear();
+            Task::none()
+        }
+        Message::ToggleMonitor(Some(name)) => {
+            if let Some(idx) = app.selected_monitors.iter().position(|n| n == &name) {
+                app.selected_monitors.remove(idx);
+            } else {
+                app.selected_monitors.push(name);
             }
             Task::none()
         }
@@ -86,3 +217,41 @@ pub fn update(app: &mut Papyrust, message: Message) -> Task<Message> {
         Message::DoNothing => Task::none(),
     }
 }
+
+fn load_monitors() -> Task<Message> {
+    Task::perform(
+        async {
+            task::spawn_blocking(|| ipc::list_monitors().map_err(|e| e.to_string()))
+                .await
+                .unwrap_or_else(|e| Err(format!("Task error: {}", e)))
+        },
+        Message::MonitorsLoaded,
+    )
+}
+
+/// Runs `send` on a blocking thread and reports its outcome with
+/// [`Message::WallpaperSent`].
+fn send_in_background(send: impl FnOnce() -> Result<(), String> + Send + 'static) -> Task<Message> {
+    Task::perform(
+        async {
+            task::spawn_blocking(send)
+                .await
+                .unwrap_or_else(|e| Err(format!("Task error: {}", e)))
+        },
+        Message::WallpaperSent,
+    )
+}
+
+fn save_properties(project: &Project, project_properties: &[Property]) {
+    if project_properties.is_empty() {
+        return;
+    }
+    if let Err(e) = properties::save(project, project_properties) {
+        error!("Failed to save properties: {}", e);
+    }
+}
+
+/// Outputs to send commands to, or `None` for all of them.
+fn selected_targets(app: &Papyrust) -> Option<Vec<String>> {
+    (!app.selected_monitors.is_empty()).then(|| app.selected_monitors.clone())
+}
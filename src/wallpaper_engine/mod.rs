@@ -0,0 +1,7 @@
+//! Readers for Wallpaper Engine's asset formats: `.pkg` archives that scene
+//! projects ship their files in, and `.tex` textures.
+
+pub mod pkg;
+pub mod tex;
+
+pub use pkg::Pkg;
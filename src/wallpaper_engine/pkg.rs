@@ -0,0 +1,198 @@
+use anyhow::{Result, anyhow};
+use std::{fs, path::Path};
+
+/// A file stored in a `.pkg` archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PkgEntry {
+    pub name: String,
+    offset: usize,
+    pub size: usize,
+}
+
+/// Wallpaper Engine `scene.pkg` archive: a length-prefixed version string,
+/// an entry table of names with offsets and sizes, then the file data.
+pub struct Pkg {
+    pub version: String,
+    entries: Vec<PkgEntry>,
+    data: Vec<u8>,
+    data_start: usize,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| anyhow!("Truncated pkg header"))?;
+        self.pos += 4;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("Truncated pkg header"))?;
+        self.pos += len;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+impl Pkg {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(data).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        let mut reader = Reader {
+            bytes: &data,
+            pos: 0,
+        };
+        let version = reader.string()?;
+        if !version.starts_with("PKGV") {
+            return Err(anyhow!("Not a pkg archive (version {:?})", version));
+        }
+
+        let count = reader.u32()? as usize;
+        let mut entries = Vec::with_capacity(count.min(4096));
+        for _ in 0..count {
+            let name = reader.string()?;
+            let offset = reader.u32()? as usize;
+            let size = reader.u32()? as usize;
+            entries.push(PkgEntry { name, offset, size });
+        }
+        let data_start = reader.pos;
+
+        for entry in &entries {
+            if data_start + entry.offset + entry.size > data.len() {
+                return Err(anyhow!("Entry {} lies outside the archive", entry.name));
+            }
+        }
+
+        Ok(Self {
+            version,
+            entries,
+            data,
+            data_start,
+        })
+    }
+
+    pub fn entries(&self) -> &[PkgEntry] {
+        &self.entries
+    }
+
+    /// Contents of the entry called `name`, as stored in the archive.
+    pub fn read(&self, name: &str) -> Option<&[u8]> {
+        let entry = self.entries.iter().find(|entry| entry.name == name)?;
+        let start = self.data_start + entry.offset;
+        self.data.get(start..start + entry.size)
+    }
+
+    /// Writes every entry below `dir`, keeping the archive's paths.
+    pub fn extract(&self, dir: &Path) -> Result<()> {
+        for entry in &self.entries {
+            // Entry names are relative; anything trying to leave `dir` is skipped
+            if entry.name.starts_with('/') || entry.name.split(['/', '\\']).any(|part| part == "..")
+            {
+                tracing::warn!(event = "pkg_entry_skipped", name = %entry.name, "Skipping pkg entry outside the target directory");
+                continue;
+            }
+            let target = dir.join(&entry.name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| anyhow!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            let contents = self.read(&entry.name).unwrap_or_default();
+            fs::write(&target, contents)
+                .map_err(|e| anyhow!("Failed to write {}: {}", target.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(version: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let string = |out: &mut Vec<u8>, s: &str| {
+            out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            out.extend_from_slice(s.as_bytes());
+        };
+
+        string(&mut out, version);
+        out.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        let mut offset = 0u32;
+        for (name, contents) in files {
+            string(&mut out, name);
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            offset += contents.len() as u32;
+        }
+        for (_, contents) in files {
+            out.extend_from_slice(contents);
+        }
+        out
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let files: [(&str, &[u8]); 3] = [
+            ("scene.json", br#"{"objects":[]}"#),
+            ("materials/background.tex", &[1, 2, 3, 4, 5]),
+            ("empty", &[]),
+        ];
+        let pkg = Pkg::parse(write("PKGV0001", &files)).unwrap();
+
+        assert_eq!(pkg.version, "PKGV0001");
+        let names: Vec<_> = pkg
+            .entries()
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["scene.json", "materials/background.tex", "empty"]);
+        for (name, contents) in files {
+            assert_eq!(pkg.read(name), Some(contents));
+        }
+        assert_eq!(pkg.read("missing"), None);
+    }
+
+    #[test]
+    fn extracts_entries_below_dir() {
+        let bytes = write(
+            "PKGV0002",
+            &[
+                ("models/a.json", b"{}"),
+                ("../escape", b"x"),
+                ("/absolute", b"y"),
+            ],
+        );
+        let pkg = Pkg::parse(bytes).unwrap();
+        let dir = std::env::temp_dir().join(format!("papyrust-pkg-test-{}", std::process::id()));
+        pkg.extract(&dir).unwrap();
+
+        assert_eq!(fs::read(dir.join("models/a.json")).unwrap(), b"{}");
+        assert!(!dir.join("../escape").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_archives() {
+        assert!(Pkg::parse(write("NOTAPKG", &[])).is_err());
+
+        let mut truncated = write("PKGV0001", &[("file", b"contents")]);
+        truncated.truncate(truncated.len() - 2);
+        assert!(Pkg::parse(truncated).is_err());
+        assert!(Pkg::parse(vec![8, 0]).is_err());
+    }
+}
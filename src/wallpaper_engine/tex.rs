@@ -0,0 +1,626 @@
+use anyhow::{Result, anyhow};
+use image::RgbaImage;
+
+/// Pixel formats of `.tex` textures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TexFormat {
+    Rgba8888,
+    Dxt5,
+    Dxt3,
+    Dxt1,
+    Rg88,
+    R8,
+}
+
+impl TexFormat {
+    fn from_u32(value: u32) -> Result<Self> {
+        Ok(match value {
+            0 => TexFormat::Rgba8888,
+            4 => TexFormat::Dxt5,
+            6 => TexFormat::Dxt3,
+            7 => TexFormat::Dxt1,
+            8 => TexFormat::Rg88,
+            9 => TexFormat::R8,
+            other => return Err(anyhow!("Unknown tex format {}", other)),
+        })
+    }
+}
+
+/// One mip level as stored in the file.
+#[derive(Debug, Clone)]
+pub struct TexMipmap {
+    pub width: u32,
+    pub height: u32,
+    pub lz4: bool,
+    pub decompressed_size: usize,
+    pub data: Vec<u8>,
+}
+
+/// A Wallpaper Engine texture: `TEXV0005`/`TEXI0001` headers with the pixel
+/// format and sizes, then a `TEXB` container of images, each with its mip
+/// levels largest first.
+#[derive(Debug, Clone)]
+pub struct Tex {
+    pub format: TexFormat,
+    /// Size of the picture within the texture, which may be padded to a
+    /// power of two
+    pub image_width: u32,
+    pub image_height: u32,
+    /// FreeImage format of mips holding an encoded file, such as PNG or JPEG,
+    /// instead of raw pixels
+    pub image_format: Option<i32>,
+    pub images: Vec<Vec<TexMipmap>>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("Truncated tex file"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Eight character magic followed by a NUL.
+    fn magic(&mut self) -> Result<&'a str> {
+        let bytes = self.take(9)?;
+        if bytes[8] != 0 {
+            return Err(anyhow!("Malformed tex magic"));
+        }
+        std::str::from_utf8(&bytes[..8]).map_err(|_| anyhow!("Malformed tex magic"))
+    }
+}
+
+impl Tex {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let magic = reader.magic()?;
+        if magic != "TEXV0005" {
+            return Err(anyhow!("Not a tex file ({})", magic));
+        }
+        let magic = reader.magic()?;
+        if magic != "TEXI0001" {
+            return Err(anyhow!("Unsupported tex header {}", magic));
+        }
+
+        let format = TexFormat::from_u32(reader.u32()?)?;
+        let _flags = reader.u32()?;
+        let _texture_width = reader.u32()?;
+        let _texture_height = reader.u32()?;
+        let image_width = reader.u32()?;
+        let image_height = reader.u32()?;
+        let _unknown = reader.u32()?;
+
+        let container = reader.magic()?;
+        let version = match container {
+            "TEXB0001" => 1,
+            "TEXB0002" => 2,
+            "TEXB0003" => 3,
+            other => return Err(anyhow!("Unsupported tex container {}", other)),
+        };
+        let image_count = reader.u32()? as usize;
+        let image_format = if version >= 3 {
+            Some(reader.u32()? as i32).filter(|&format| format != -1)
+        } else {
+            None
+        };
+
+        let mut images = Vec::with_capacity(image_count.min(64));
+        for _ in 0..image_count {
+            let mip_count = reader.u32()? as usize;
+            let mut mips = Vec::with_capacity(mip_count.min(32));
+            for _ in 0..mip_count {
+                let width = reader.u32()?;
+                let height = reader.u32()?;
+                let (lz4, decompressed_size) = if version >= 2 {
+                    (reader.u32()? == 1, reader.u32()? as usize)
+                } else {
+                    (false, 0)
+                };
+                let len = reader.u32()? as usize;
+                let data = reader.take(len)?.to_vec();
+                mips.push(TexMipmap {
+                    width,
+                    height,
+                    lz4,
+                    decompressed_size,
+                    data,
+                });
+            }
+            images.push(mips);
+        }
+
+        Ok(Self {
+            format,
+            image_width,
+            image_height,
+            image_format,
+            images,
+        })
+    }
+
+    /// The first image's largest mip as RGBA, cropped to the picture size.
+    pub fn to_rgba(&self) -> Result<RgbaImage> {
+        let mip = self
+            .images
+            .first()
+            .and_then(|mips| mips.first())
+            .ok_or_else(|| anyhow!("Tex file has no images"))?;
+        let data = if mip.lz4 {
+            lz4_decompress(&mip.data, mip.decompressed_size)?
+        } else {
+            mip.data.clone()
+        };
+
+        let image = if self.image_format.is_some() {
+            image::load_from_memory(&data)
+                .map_err(|e| anyhow!("Failed to decode embedded image: {}", e))?
+                .to_rgba8()
+        } else {
+            let pixels = decode_pixels(self.format, &data, mip.width, mip.height)?;
+            RgbaImage::from_raw(mip.width, mip.height, pixels)
+                .ok_or_else(|| anyhow!("Tex pixel data too short"))?
+        };
+
+        let (width, height) = (
+            self.image_width.min(image.width()).max(1),
+            self.image_height.min(image.height()).max(1),
+        );
+        if (width, height) == image.dimensions() {
+            return Ok(image);
+        }
+        Ok(image::imageops::crop_imm(&image, 0, 0, width, height).to_image())
+    }
+}
+
+/// Decodes a texture file into RGBA.
+pub fn decode(bytes: &[u8]) -> Result<RgbaImage> {
+    Tex::parse(bytes)?.to_rgba()
+}
+
+/// Raw LZ4 block decompression, as used for compressed mips.
+pub fn lz4_decompress(src: &[u8], size: usize) -> Result<Vec<u8>> {
+    let truncated = || anyhow!("Truncated LZ4 block");
+    let overrun = || anyhow!("LZ4 block decompresses past its expected {} bytes", size);
+    // `size` comes from the file; a block expands at most 255 times
+    let mut out = Vec::with_capacity(size.min(src.len().saturating_mul(255)));
+    let mut i = 0;
+
+    let read_length = |i: &mut usize, mut len: usize| -> Result<usize> {
+        loop {
+            let byte = *src.get(*i).ok_or_else(truncated)?;
+            *i += 1;
+            len += byte as usize;
+            if byte != 255 {
+                return Ok(len);
+            }
+        }
+    };
+
+    while i < src.len() {
+        let token = src[i];
+        i += 1;
+
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals = read_length(&mut i, literals)?;
+        }
+        if out.len() + literals > size {
+            return Err(overrun());
+        }
+        out.extend_from_slice(src.get(i..i + literals).ok_or_else(truncated)?);
+        i += literals;
+        // The last sequence is literals only
+        if i == src.len() {
+            break;
+        }
+
+        let offset = src.get(i..i + 2).ok_or_else(truncated)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        i += 2;
+        if offset == 0 || offset > out.len() {
+            return Err(anyhow!("Invalid LZ4 match offset"));
+        }
+
+        let mut len = (token & 15) as usize;
+        if len == 15 {
+            len = read_length(&mut i, len)?;
+        }
+        len += 4;
+        if out.len() + len > size {
+            return Err(overrun());
+        }
+        // Matches may overlap the bytes they produce
+        let start = out.len() - offset;
+        for k in 0..len {
+            let byte = out[start + k];
+            out.push(byte);
+        }
+    }
+
+    if out.len() != size {
+        return Err(anyhow!(
+            "LZ4 block decompressed to {} bytes, expected {}",
+            out.len(),
+            size
+        ));
+    }
+    Ok(out)
+}
+
+fn decode_pixels(format: TexFormat, data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    let pixels = width as usize * height as usize;
+    let short = || anyhow!("{:?} data too short for {}x{}", format, width, height);
+    match format {
+        TexFormat::Rgba8888 => data.get(..pixels * 4).map(<[u8]>::to_vec).ok_or_else(short),
+        TexFormat::Rg88 => {
+            let data = data.get(..pixels * 2).ok_or_else(short)?;
+            Ok(data
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect())
+        }
+        TexFormat::R8 => {
+            let data = data.get(..pixels).ok_or_else(short)?;
+            Ok(data.iter().flat_map(|&l| [l, l, l, 255]).collect())
+        }
+        TexFormat::Dxt1 | TexFormat::Dxt3 | TexFormat::Dxt5 => {
+            decode_dxt(format, data, width, height)
+        }
+    }
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 31) as u32;
+    let g = ((color >> 5) & 63) as u32;
+    let b = (color & 31) as u32;
+    [
+        ((r * 255 + 15) / 31) as u8,
+        ((g * 255 + 31) / 63) as u8,
+        ((b * 255 + 15) / 31) as u8,
+    ]
+}
+
+/// Colours of a DXT colour block. DXT1 blocks with `c0 <= c1` have three
+/// colours and transparent black.
+fn block_colors(block: &[u8], dxt1: bool) -> [[u8; 4]; 4] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u32, wb: u32| -> [u8; 4] {
+        let total = wa + wb;
+        let channel = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / total) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    if c0 > c1 || !dxt1 {
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            mix(2, 1),
+            mix(1, 2),
+        ]
+    } else {
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            mix(1, 1),
+            [0, 0, 0, 0],
+        ]
+    }
+}
+
+fn decode_dxt(format: TexFormat, data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    let block_size = if format == TexFormat::Dxt1 { 8 } else { 16 };
+    let (blocks_x, blocks_y) = (width.div_ceil(4) as usize, height.div_ceil(4) as usize);
+    if data.len() < blocks_x * blocks_y * block_size {
+        return Err(anyhow!(
+            "{:?} data too short for {}x{}",
+            format,
+            width,
+            height
+        ));
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let mut out = vec![0u8; width * height * 4];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let offset = (by * blocks_x + bx) * block_size;
+            let block = &data[offset..offset + block_size];
+            let (alpha, color) = block.split_at(block_size - 8);
+
+            let colors = block_colors(color, format == TexFormat::Dxt1);
+            let indices = u32::from_le_bytes([color[4], color[5], color[6], color[7]]);
+            let alphas = match format {
+                TexFormat::Dxt3 => {
+                    let bits = u64::from_le_bytes(alpha.try_into().unwrap_or_default());
+                    Some(std::array::from_fn::<u8, 16, _>(|i| {
+                        ((bits >> (i * 4)) & 15) as u8 * 17
+                    }))
+                }
+                TexFormat::Dxt5 => {
+                    let (a0, a1) = (alpha[0] as u32, alpha[1] as u32);
+                    let palette: [u8; 8] = std::array::from_fn(|i| match i {
+                        0 => a0 as u8,
+                        1 => a1 as u8,
+                        _ if a0 > a1 => ((a0 * (8 - i as u32) + a1 * (i as u32 - 1)) / 7) as u8,
+                        6 => 0,
+                        7 => 255,
+                        _ => ((a0 * (6 - i as u32) + a1 * (i as u32 - 1)) / 5) as u8,
+                    });
+                    let mut bits = 0u64;
+                    for (k, &byte) in alpha[2..8].iter().enumerate() {
+                        bits |= (byte as u64) << (8 * k);
+                    }
+                    Some(std::array::from_fn::<u8, 16, _>(|i| {
+                        palette[((bits >> (i * 3)) & 7) as usize]
+                    }))
+                }
+                _ => None,
+            };
+
+            for py in 0..4 {
+                for px in 0..4 {
+                    let (x, y) = (bx * 4 + px, by * 4 + py);
+                    if x >= width || y >= height {
+                        continue;
+                    }
+                    let i = py * 4 + px;
+                    let mut pixel = colors[((indices >> (i * 2)) & 3) as usize];
+                    if let Some(alphas) = &alphas {
+                        pixel[3] = alphas[i];
+                    }
+                    let at = (y * width + x) * 4;
+                    out[at..at + 4].copy_from_slice(&pixel);
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Mip {
+        width: u32,
+        height: u32,
+        lz4: Option<usize>,
+        data: Vec<u8>,
+    }
+
+    fn magic(out: &mut Vec<u8>, magic: &str) {
+        out.extend_from_slice(magic.as_bytes());
+        out.push(0);
+    }
+
+    fn u32s(out: &mut Vec<u8>, values: &[u32]) {
+        for value in values {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// Single image texture in the layout [`Tex::parse`] reads.
+    fn write(
+        format: u32,
+        container: u32,
+        image_format: Option<i32>,
+        image_size: (u32, u32),
+        mips: &[Mip],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        magic(&mut out, "TEXV0005");
+        magic(&mut out, "TEXI0001");
+        let (width, height) = (mips[0].width, mips[0].height);
+        u32s(
+            &mut out,
+            &[format, 0, width, height, image_size.0, image_size.1, 0],
+        );
+        magic(&mut out, &format!("TEXB000{}", container));
+        u32s(&mut out, &[1]);
+        if container >= 3 {
+            u32s(&mut out, &[image_format.unwrap_or(-1) as u32]);
+        }
+        u32s(&mut out, &[mips.len() as u32]);
+        for mip in mips {
+            u32s(&mut out, &[mip.width, mip.height]);
+            if container >= 2 {
+                u32s(
+                    &mut out,
+                    &[mip.lz4.is_some() as u32, mip.lz4.unwrap_or(0) as u32],
+                );
+            }
+            u32s(&mut out, &[mip.data.len() as u32]);
+            out.extend_from_slice(&mip.data);
+        }
+        out
+    }
+
+    /// LZ4 block holding `data` as a single run of literals.
+    fn lz4_literals(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![(data.len().min(15) as u8) << 4];
+        if data.len() >= 15 {
+            let mut rest = data.len() - 15;
+            while rest >= 255 {
+                out.push(255);
+                rest -= 255;
+            }
+            out.push(rest as u8);
+        }
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn raw(width: u32, height: u32, data: Vec<u8>) -> Mip {
+        Mip {
+            width,
+            height,
+            lz4: None,
+            data,
+        }
+    }
+
+    #[test]
+    fn round_trips_rgba_cropped_to_image_size() {
+        let pixels: Vec<u8> = (0..4 * 4 * 4).map(|i| i as u8).collect();
+        let bytes = write(
+            0,
+            1,
+            None,
+            (3, 2),
+            &[raw(4, 4, pixels.clone()), raw(2, 2, vec![0; 16])],
+        );
+        let tex = Tex::parse(&bytes).unwrap();
+        assert_eq!(tex.format, TexFormat::Rgba8888);
+        assert_eq!(tex.images[0].len(), 2);
+
+        let image = tex.to_rgba().unwrap();
+        assert_eq!(image.dimensions(), (3, 2));
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let at = ((y * 4 + x) * 4) as usize;
+            assert_eq!(pixel.0, pixels[at..at + 4]);
+        }
+    }
+
+    #[test]
+    fn round_trips_lz4_compressed_mips() {
+        let pixels: Vec<u8> = (0..8 * 8 * 4).map(|i| (i * 7) as u8).collect();
+        let mip = Mip {
+            width: 8,
+            height: 8,
+            lz4: Some(pixels.len()),
+            data: lz4_literals(&pixels),
+        };
+        let image = decode(&write(0, 2, None, (8, 8), &[mip])).unwrap();
+        assert_eq!(image.into_raw(), pixels);
+    }
+
+    #[test]
+    fn lz4_copies_overlapping_matches() {
+        let block = [0x35, b'a', b'b', b'c', 3, 0, 0x10, b'!'];
+        assert_eq!(lz4_decompress(&block, 13).unwrap(), b"abcabcabcabc!");
+        assert!(lz4_decompress(&block, 12).is_err());
+        assert!(lz4_decompress(&[0x30, b'a', b'b', b'c', 9, 0], 7).is_err());
+    }
+
+    #[test]
+    fn lz4_stops_at_the_expected_size() {
+        // A huge declared size is not allocated up front
+        let block = lz4_literals(b"abc");
+        assert!(lz4_decompress(&block, u32::MAX as usize).is_err());
+
+        // A long match is rejected before it is copied
+        let long_match = [0x1F, b'a', 1, 0, 255, 255, 255, 255, 0];
+        let error = lz4_decompress(&long_match, 64).unwrap_err();
+        assert!(error.to_string().contains("past its expected"), "{}", error);
+        assert!(lz4_decompress(&lz4_literals(b"abcdef"), 3).is_err());
+    }
+
+    #[test]
+    fn decodes_dxt1_four_and_three_colour_blocks() {
+        // Red and blue endpoints, each row indexing colours 0, 1, 2 and 3
+        let four = [0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4];
+        let image = decode(&write(7, 1, None, (4, 4), &[raw(4, 4, four.to_vec())])).unwrap();
+        let row: Vec<_> = (0..4).map(|x| image.get_pixel(x, 2).0).collect();
+        assert_eq!(
+            row,
+            [
+                [255, 0, 0, 255],
+                [0, 0, 255, 255],
+                [170, 0, 85, 255],
+                [85, 0, 170, 255]
+            ]
+        );
+
+        let three = [0x1F, 0x00, 0x00, 0xF8, 0xE4, 0xE4, 0xE4, 0xE4];
+        let image = decode(&write(7, 1, None, (4, 4), &[raw(4, 4, three.to_vec())])).unwrap();
+        let row: Vec<_> = (0..4).map(|x| image.get_pixel(x, 0).0).collect();
+        assert_eq!(
+            row,
+            [
+                [0, 0, 255, 255],
+                [255, 0, 0, 255],
+                [127, 0, 127, 255],
+                [0, 0, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_dxt3_and_dxt5_alpha() {
+        let color = [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0];
+
+        let mut dxt3 = vec![0x10, 0xF0, 0, 0, 0, 0, 0, 0];
+        dxt3.extend_from_slice(&color);
+        let image = decode(&write(6, 1, None, (4, 4), &[raw(4, 4, dxt3)])).unwrap();
+        let alphas: Vec<_> = (0..4).map(|x| image.get_pixel(x, 0)[3]).collect();
+        assert_eq!(alphas, [0, 17, 0, 255]);
+
+        // Alpha indices 0, 1, 2 for the first three pixels
+        let mut dxt5 = vec![255, 0, 0b10_001_000, 0, 0, 0, 0, 0];
+        dxt5.extend_from_slice(&color);
+        let image = decode(&write(4, 1, None, (4, 4), &[raw(4, 4, dxt5)])).unwrap();
+        let alphas: Vec<_> = (0..4).map(|x| image.get_pixel(x, 0)[3]).collect();
+        assert_eq!(alphas, [255, 0, 218, 255]);
+        assert_eq!(image.get_pixel(0, 0).0[..3], [255, 255, 255]);
+    }
+
+    #[test]
+    fn decodes_dxt_with_partial_blocks() {
+        let block = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let data = [block, block].concat();
+        let image = decode(&write(7, 1, None, (6, 2), &[raw(6, 2, data)])).unwrap();
+        assert_eq!(image.dimensions(), (6, 2));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn expands_r8_and_rg88() {
+        let image = decode(&write(9, 1, None, (2, 1), &[raw(2, 1, vec![10, 200])])).unwrap();
+        assert_eq!(image.into_raw(), [10, 10, 10, 255, 200, 200, 200, 255]);
+
+        let image = decode(&write(8, 1, None, (1, 1), &[raw(1, 1, vec![50, 128])])).unwrap();
+        assert_eq!(image.into_raw(), [50, 50, 50, 128]);
+    }
+
+    #[test]
+    fn decodes_embedded_images() {
+        let source = RgbaImage::from_fn(3, 2, |x, y| {
+            image::Rgba([x as u8 * 80, y as u8 * 100, 7, 255])
+        });
+        let mut png = Vec::new();
+        source
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        // FreeImage's PNG format
+        let image = decode(&write(0, 3, Some(13), (3, 2), &[raw(3, 2, png)])).unwrap();
+        assert_eq!(image, source);
+    }
+
+    #[test]
+    fn rejects_bad_textures() {
+        let valid = write(0, 1, None, (1, 1), &[raw(1, 1, vec![0; 4])]);
+        assert!(decode(&valid).is_ok());
+        assert!(decode(&valid[..valid.len() - 1]).is_err());
+
+        let mut wrong_magic = valid.clone();
+        wrong_magic[..8].copy_from_slice(b"TEXV0004");
+        assert!(decode(&wrong_magic).is_err());
+
+        assert!(decode(&write(5, 1, None, (1, 1), &[raw(1, 1, vec![0; 4])])).is_err());
+        assert!(decode(&write(0, 1, None, (2, 2), &[raw(2, 2, vec![0; 4])])).is_err());
+    }
+}